@@ -0,0 +1,572 @@
+//! Incremental ITM and DWT packet decoder
+//!
+//! A [`Decoder`] takes the trace stream one byte at a time, as it comes off SWO or out
+//! of a TPIU deformatter, and hands back each packet as soon as its last byte arrives.
+//! It holds nothing but the packet in progress, so it needs no allocator and runs just
+//! as well in probe firmware as on the host.
+//!
+//! Packets follow appendix D4 of the ARMv7-M Architecture Reference Manual:
+//!
+//! | header     | packet                    | after the header                 |
+//! |------------|---------------------------|----------------------------------|
+//! | `00`       | synchronisation           | at least four more `00`, then `80` |
+//! | `70`       | overflow                  |                                  |
+//! | `0ttt0000` | local timestamp, format 2 |                                  |
+//! | `11rr0000` | local timestamp, format 1 | up to 4 continuation bytes       |
+//! | `94`       | global timestamp, GTS1    | up to 4 continuation bytes       |
+//! | `b4`       | global timestamp, GTS2    | up to 6 continuation bytes       |
+//! | `ceee1s00` | extension                 | up to 4 continuation bytes       |
+//! | `aaaaa0ss` | instrumentation, port `a` | 1, 2 or 4 bytes, as `ss` says    |
+//! | `aaaaa1ss` | hardware source `a`       | 1, 2 or 4 bytes, as `ss` says    |
+//!
+//! Anything that does not fit is reported as a [`DecodeError`] giving the offset of the
+//! first byte of the bad packet, and decoding carries on with as little lost as possible:
+//!
+//! * a reserved header costs just that byte.
+//! * a run of zeros ended by anything but `80` is a broken sync. The byte that ended it
+//!   is decoded as the next header.
+//! * a timestamp whose continuation bit is still set at its longest length ends there,
+//!   and the byte after is decoded as the next header.
+//! * a hardware source packet with a discriminator or size the DWT does not produce is
+//!   still skipped by the size in its header, so the packets after it line up.
+
+#![no_std]
+
+use core::fmt;
+
+/// Zero bytes, counting the header, that make up the start of a sync packet. With the
+/// seven zero bits of the final `80` that makes the 47 the architecture asks for.
+pub const SYNC_ZEROS: u32 = 5;
+
+/// Value of the final byte of a sync packet
+pub const SYNC_END: u8 = 0x80;
+
+/// Header of the overflow packet
+pub const OVERFLOW: u8 = 0x70;
+
+/// Header of the first global timestamp packet
+pub const GTS1: u8 = 0x94;
+
+/// Header of the second global timestamp packet
+pub const GTS2: u8 = 0xb4;
+
+/// A 1-, 2- or 4-byte payload, as carried by instrumentation and data value packets
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Payload {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+}
+
+impl Payload {
+    fn new(size: u8, value: u32) -> Self {
+        match size {
+            1 => Payload::U8(value as u8),
+            2 => Payload::U16(value as u16),
+            _ => Payload::U32(value),
+        }
+    }
+
+    /// Number of payload bytes
+    pub fn size(self) -> usize {
+        match self {
+            Payload::U8(_) => 1,
+            Payload::U16(_) => 2,
+            Payload::U32(_) => 4,
+        }
+    }
+
+    pub fn value(self) -> u32 {
+        match self {
+            Payload::U8(v) => v as u32,
+            Payload::U16(v) => v as u32,
+            Payload::U32(v) => v,
+        }
+    }
+
+    /// The payload bytes in the order they were sent, and how many of them are used
+    pub fn bytes(self) -> ([u8; 4], usize) {
+        (self.value().to_le_bytes(), self.size())
+    }
+}
+
+/// How a local timestamp relates to the packet it follows, from the TC field
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimestampRelation {
+    /// Exact for the packet before it
+    Synchronous,
+    /// The timestamp itself was delayed
+    TimestampDelayed,
+    /// The packet it stamps was delayed, for example by a full FIFO
+    PacketDelayed,
+    /// Both were delayed
+    BothDelayed,
+}
+
+impl TimestampRelation {
+    fn from_header(header: u8) -> Self {
+        match (header >> 4) & 0x3 {
+            0 => TimestampRelation::Synchronous,
+            1 => TimestampRelation::TimestampDelayed,
+            2 => TimestampRelation::PacketDelayed,
+            _ => TimestampRelation::BothDelayed,
+        }
+    }
+}
+
+/// What happened to an exception, from an exception trace packet
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExceptionFunction {
+    Enter,
+    Exit,
+    Return,
+}
+
+/// One decoded packet
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Packet {
+    Sync,
+    Overflow,
+    /// A write to stimulus `port`. On an ITM with more than 32 ports, the page comes
+    /// from the last [`Packet::Extension`] without `hardware` set.
+    Instrumentation {
+        port: u8,
+        payload: Payload,
+    },
+    /// Timestamp ticks since the last local timestamp
+    LocalTimestamp {
+        delta: u32,
+        relation: TimestampRelation,
+    },
+    /// The low `width` bits of bits 25:0 of the global timestamp. Only the bits that
+    /// changed since the last GTS1 are sent.
+    GlobalTimestamp1 {
+        value: u32,
+        width: u8,
+        /// The upper bits are about to change, and a GTS2 follows
+        wrap: bool,
+        /// The timestamp clock has changed since the last GTS1
+        clock_change: bool,
+    },
+    /// Bits 63:26 of the global timestamp, shifted down to bit 0
+    GlobalTimestamp2 {
+        high: u64,
+    },
+    /// An extension packet. `hardware` is the SH bit. For the ITM, `value` is the
+    /// stimulus port page.
+    Extension {
+        hardware: bool,
+        value: u32,
+    },
+    /// One of the DWT event counters wrapped. Bits 0 to 5 of `flags` are the CPI, EXC,
+    /// SLEEP, LSU, FOLD and POSTCNT counters.
+    EventCounter {
+        flags: u8,
+    },
+    Exception {
+        number: u16,
+        function: ExceptionFunction,
+    },
+    /// A periodic PC sample, or `None` if the core was asleep
+    PcSample(Option<u32>),
+    /// PC of an access that matched comparator `comparator`
+    DataTracePc {
+        comparator: u8,
+        pc: u32,
+    },
+    /// Low 16 bits of the address of an access that matched `comparator`
+    DataTraceAddress {
+        comparator: u8,
+        offset: u16,
+    },
+    /// Value read or written by an access that matched `comparator`
+    DataTraceValue {
+        comparator: u8,
+        write: bool,
+        value: Payload,
+    },
+}
+
+/// What was wrong with a malformed packet
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ErrorKind {
+    /// A header that starts no packet
+    ReservedHeader(u8),
+    /// `zeros` zero bytes, ended by `byte` rather than by [`SYNC_END`] after at least
+    /// [`SYNC_ZEROS`] of them
+    BrokenSync { zeros: u32, byte: u8 },
+    /// A packet with `header` still had its continuation bit set at its longest length
+    Overlong { header: u8 },
+    /// A hardware source packet that the DWT does not produce
+    InvalidHardwareSource { discriminator: u8, payload: Payload },
+    /// The stream ended part way through a packet with `header`
+    Truncated { header: u8 },
+}
+
+/// A malformed packet, starting `offset` bytes into the stream
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DecodeError {
+    pub offset: u64,
+    pub kind: ErrorKind,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: ", self.offset)?;
+        match self.kind {
+            ErrorKind::ReservedHeader(header) => write!(f, "reserved header {:#04x}", header),
+            ErrorKind::BrokenSync { zeros, byte } => {
+                write!(f, "{} zero bytes ended by {:#04x}", zeros, byte)
+            }
+            ErrorKind::Overlong { header } => {
+                write!(f, "packet with header {:#04x} is too long", header)
+            }
+            ErrorKind::InvalidHardwareSource {
+                discriminator,
+                payload,
+            } => write!(
+                f,
+                "no hardware source {} with a {}-byte payload",
+                discriminator,
+                payload.size()
+            ),
+            ErrorKind::Truncated { header } => {
+                write!(f, "stream ends inside packet with header {:#04x}", header)
+            }
+        }
+    }
+}
+
+/// The packets and errors produced by one byte, in stream order
+///
+/// Most bytes produce nothing or one packet. A byte that ends a broken sync produces the
+/// error and then whatever the byte itself decodes to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Output {
+    items: [Option<Result<Packet, DecodeError>>; 2],
+    next: usize,
+}
+
+impl Output {
+    fn add(&mut self, item: Option<Result<Packet, DecodeError>>) {
+        if let Some(item) = item {
+            let slot = self.items.iter_mut().find(|slot| slot.is_none());
+            *slot.expect("at most two results per byte") = Some(item);
+        }
+    }
+}
+
+impl Iterator for Output {
+    type Item = Result<Packet, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.items.get_mut(self.next)?.take();
+        self.next += 1;
+        item
+    }
+}
+
+/// Packets that end with the first byte whose continuation bit is clear
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Continued {
+    LocalTimestamp,
+    GlobalTimestamp1,
+    GlobalTimestamp2,
+    Extension,
+}
+
+impl Continued {
+    /// Most bytes after the header
+    fn max_len(self) -> u8 {
+        match self {
+            Continued::GlobalTimestamp2 => 6,
+            _ => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Header,
+    /// Counting the zeros of a sync packet
+    Sync {
+        zeros: u32,
+    },
+    /// Collecting the payload of an instrumentation or hardware source packet
+    Source {
+        header: u8,
+        size: u8,
+        received: u8,
+        value: u32,
+    },
+    Continued {
+        header: u8,
+        kind: Continued,
+        received: u8,
+        value: u64,
+    },
+}
+
+/// Turns a trace byte stream into [`Packet`]s, one byte at a time
+///
+/// The stream is assumed to start on a packet boundary, as it does when the capture
+/// starts before the trace is enabled. Otherwise the first sync packet lines it up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Decoder {
+    state: State,
+    /// Bytes taken so far
+    offset: u64,
+    /// Offset of the first byte of the packet in progress
+    start: u64,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            state: State::Header,
+            offset: 0,
+            start: 0,
+        }
+    }
+
+    /// Number of bytes taken so far
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Take the next byte of the stream
+    pub fn push(&mut self, byte: u8) -> Output {
+        let offset = self.offset;
+        self.offset += 1;
+
+        let mut output = Output::default();
+        match self.state {
+            State::Header => output.add(self.header(byte, offset)),
+            State::Sync { zeros } => match byte {
+                0 => self.state = State::Sync { zeros: zeros + 1 },
+                SYNC_END => {
+                    self.state = State::Header;
+                    output.add(Some(if zeros >= SYNC_ZEROS {
+                        Ok(Packet::Sync)
+                    } else {
+                        Err(self.error(ErrorKind::BrokenSync { zeros, byte }))
+                    }));
+                }
+                _ => {
+                    output.add(Some(Err(self.error(ErrorKind::BrokenSync { zeros, byte }))));
+                    output.add(self.header(byte, offset));
+                }
+            },
+            State::Source {
+                header,
+                size,
+                received,
+                value,
+            } => {
+                let value = value | (byte as u32) << (8 * received);
+                let received = received + 1;
+                if received < size {
+                    self.state = State::Source {
+                        header,
+                        size,
+                        received,
+                        value,
+                    };
+                } else {
+                    self.state = State::Header;
+                    output.add(Some(self.source(header, Payload::new(size, value))));
+                }
+            }
+            State::Continued {
+                header,
+                kind,
+                received,
+                value,
+            } => output.add(self.continued(header, kind, received, value, byte)),
+        }
+        output
+    }
+
+    /// Say whether the stream ended part way through a packet, and get ready for a new
+    /// stream
+    pub fn finish(&mut self) -> Option<DecodeError> {
+        let header = match self.state {
+            State::Header => None,
+            State::Sync { .. } => Some(0),
+            State::Source { header, .. } | State::Continued { header, .. } => Some(header),
+        };
+        let error = header.map(|header| self.error(ErrorKind::Truncated { header }));
+        *self = Decoder::new();
+        error
+    }
+
+    fn error(&self, kind: ErrorKind) -> DecodeError {
+        DecodeError {
+            offset: self.start,
+            kind,
+        }
+    }
+
+    fn header(&mut self, byte: u8, offset: u64) -> Option<Result<Packet, DecodeError>> {
+        self.start = offset;
+        self.state = State::Header;
+
+        let size = byte & 0x3;
+        if size != 0 {
+            self.state = State::Source {
+                header: byte,
+                size: if size == 3 { 4 } else { size },
+                received: 0,
+                value: 0,
+            };
+            return None;
+        }
+
+        let continued = match byte {
+            0 => {
+                self.state = State::Sync { zeros: 1 };
+                return None;
+            }
+            OVERFLOW => return Some(Ok(Packet::Overflow)),
+            GTS1 => Continued::GlobalTimestamp1,
+            GTS2 => Continued::GlobalTimestamp2,
+            /* Local timestamp format 2, with the delta in the header */
+            0x10..=0x60 if byte & 0x0f == 0 => {
+                return Some(Ok(Packet::LocalTimestamp {
+                    delta: (byte >> 4) as u32,
+                    relation: TimestampRelation::Synchronous,
+                }))
+            }
+            0xc0..=0xf0 if byte & 0x0f == 0 => Continued::LocalTimestamp,
+            _ if byte & 0x08 != 0 => {
+                let ex = ((byte >> 4) & 0x7) as u64;
+                if byte & 0x80 == 0 {
+                    return Some(Ok(extension(byte, ex)));
+                }
+                self.state = State::Continued {
+                    header: byte,
+                    kind: Continued::Extension,
+                    received: 0,
+                    value: ex,
+                };
+                return None;
+            }
+            _ => return Some(Err(self.error(ErrorKind::ReservedHeader(byte)))),
+        };
+        self.state = State::Continued {
+            header: byte,
+            kind: continued,
+            received: 0,
+            value: 0,
+        };
+        None
+    }
+
+    fn continued(
+        &mut self,
+        header: u8,
+        kind: Continued,
+        received: u8,
+        value: u64,
+        byte: u8,
+    ) -> Option<Result<Packet, DecodeError>> {
+        let last = received + 1 == kind.max_len();
+        let more = byte & 0x80 != 0;
+        let value = match kind {
+            /* The last byte of an extension has no continuation bit */
+            Continued::Extension if last => value | (byte as u64) << (3 + 7 * received),
+            Continued::Extension => value | ((byte & 0x7f) as u64) << (3 + 7 * received),
+            /* The last byte of a GTS1 holds bits 25:21 and two flags */
+            Continued::GlobalTimestamp1 if last => value | ((byte & 0x1f) as u64) << 21,
+            _ => value | ((byte & 0x7f) as u64) << (7 * received),
+        };
+
+        if more && !(last && kind == Continued::Extension) {
+            if last {
+                self.state = State::Header;
+                return Some(Err(self.error(ErrorKind::Overlong { header })));
+            }
+            self.state = State::Continued {
+                header,
+                kind,
+                received: received + 1,
+                value,
+            };
+            return None;
+        }
+
+        self.state = State::Header;
+        Some(Ok(match kind {
+            Continued::LocalTimestamp => Packet::LocalTimestamp {
+                delta: value as u32,
+                relation: TimestampRelation::from_header(header),
+            },
+            Continued::GlobalTimestamp1 => Packet::GlobalTimestamp1 {
+                value: value as u32,
+                width: if last { 26 } else { 7 * (received + 1) },
+                wrap: last && byte & 0x40 != 0,
+                clock_change: last && byte & 0x20 != 0,
+            },
+            Continued::GlobalTimestamp2 => Packet::GlobalTimestamp2 { high: value },
+            Continued::Extension => extension(header, value),
+        }))
+    }
+
+    fn source(&self, header: u8, payload: Payload) -> Result<Packet, DecodeError> {
+        let id = header >> 3;
+        if header & 0x4 == 0 {
+            return Ok(Packet::Instrumentation { port: id, payload });
+        }
+
+        let comparator = (id >> 1) & 0x3;
+        match (id, payload) {
+            (0, Payload::U8(flags)) => Ok(Packet::EventCounter { flags }),
+            (1, Payload::U16(value)) => {
+                let function = match (value >> 12) & 0x3 {
+                    1 => Some(ExceptionFunction::Enter),
+                    2 => Some(ExceptionFunction::Exit),
+                    3 => Some(ExceptionFunction::Return),
+                    _ => None,
+                };
+                function
+                    .map(|function| Packet::Exception {
+                        number: value & 0x1ff,
+                        function,
+                    })
+                    .ok_or_else(|| self.invalid_hardware(id, payload))
+            }
+            (2, Payload::U32(pc)) => Ok(Packet::PcSample(Some(pc))),
+            (2, Payload::U8(0)) => Ok(Packet::PcSample(None)),
+            (8..=15, Payload::U32(pc)) if id & 1 == 0 => Ok(Packet::DataTracePc { comparator, pc }),
+            (8..=15, Payload::U16(offset)) if id & 1 == 1 => {
+                Ok(Packet::DataTraceAddress { comparator, offset })
+            }
+            (16..=23, value) => Ok(Packet::DataTraceValue {
+                comparator,
+                write: id & 1 == 1,
+                value,
+            }),
+            _ => Err(self.invalid_hardware(id, payload)),
+        }
+    }
+
+    fn invalid_hardware(&self, discriminator: u8, payload: Payload) -> DecodeError {
+        self.error(ErrorKind::InvalidHardwareSource {
+            discriminator,
+            payload,
+        })
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn extension(header: u8, value: u64) -> Packet {
+    Packet::Extension {
+        hardware: header & 0x4 != 0,
+        value: value as u32,
+    }
+}
@@ -0,0 +1,443 @@
+//! Streams built from the firmware's own pattern definitions, decoded back
+
+use tracetest::{
+    data_trace, event_counters,
+    exceptions::{self, ExceptionEvent},
+    hello::{counter_word, COUNTER_PORTS, HELLO_BYTE, HELLO_MESSAGE, HELLO_PORT},
+    itm::TraceWrite,
+    timestamps::{lts_size, GAP_TICKS, OVERFLOW_GAP_TICKS},
+    widths,
+};
+use tracetest_decode::{
+    DecodeError, Decoder, ErrorKind, ExceptionFunction, Packet, Payload, TimestampRelation,
+};
+
+/// The bytes the ITM and DWT would put on the wire
+#[derive(Default)]
+struct Stream(Vec<u8>);
+
+impl Stream {
+    fn sync(&mut self) {
+        self.0.extend_from_slice(&[0, 0, 0, 0, 0, 0x80]);
+    }
+
+    fn source(&mut self, header: u8, value: u32, size: usize) {
+        self.0.push(header | if size == 4 { 3 } else { size as u8 });
+        self.0.extend_from_slice(&value.to_le_bytes()[..size]);
+    }
+
+    fn hardware(&mut self, discriminator: u8, value: u32, size: usize) {
+        self.source(discriminator << 3 | 0x4, value, size);
+    }
+
+    fn local_timestamp(&mut self, delta: u32) {
+        match delta {
+            1..=6 => self.0.push((delta as u8) << 4),
+            0x1000_0000..=u32::MAX => self.0.push(0x70),
+            _ => {
+                self.0.push(0xc0);
+                let mut rest = delta;
+                loop {
+                    let byte = (rest & 0x7f) as u8;
+                    rest >>= 7;
+                    if rest == 0 {
+                        self.0.push(byte);
+                        break;
+                    }
+                    self.0.push(byte | 0x80);
+                }
+            }
+        }
+    }
+
+    /// A writer for stimulus port `port`
+    fn port(&mut self, port: usize) -> Port<'_> {
+        Port { stream: self, port }
+    }
+
+    fn decode(&self) -> Vec<Result<Packet, DecodeError>> {
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        for byte in self.0.iter() {
+            out.extend(decoder.push(*byte));
+        }
+        assert_eq!(decoder.finish(), None);
+        out
+    }
+
+    fn packets(&self) -> Vec<Packet> {
+        self.decode()
+            .into_iter()
+            .map(|item| item.expect("decode error"))
+            .collect()
+    }
+}
+
+/// Records stimulus writes the way an `ItmPort` makes them
+struct Port<'a> {
+    stream: &'a mut Stream,
+    port: usize,
+}
+
+impl TraceWrite for Port<'_> {
+    fn write_u8(&mut self, value: u8) {
+        self.stream.source((self.port as u8) << 3, value as u32, 1);
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.stream.source((self.port as u8) << 3, value as u32, 2);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.stream.source((self.port as u8) << 3, value, 4);
+    }
+}
+
+/// The payload bytes sent on `port`, in order
+fn port_bytes(packets: &[Packet], port: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for packet in packets {
+        if let Packet::Instrumentation { port: p, payload } = packet {
+            if *p as usize == port {
+                let (data, len) = payload.bytes();
+                bytes.extend_from_slice(&data[..len]);
+            }
+        }
+    }
+    bytes
+}
+
+#[test]
+fn hello() {
+    let mut stream = Stream::default();
+    stream.sync();
+    for tick in 0..6 {
+        if tick % 2 == 0 {
+            stream.port(HELLO_PORT).write_all(HELLO_MESSAGE.as_bytes());
+        } else {
+            stream.port(HELLO_PORT).write_u8(HELLO_BYTE);
+        }
+        for port in COUNTER_PORTS.iter() {
+            stream.port(*port).write_u32(counter_word(*port, tick));
+        }
+    }
+
+    let packets = stream.packets();
+    assert_eq!(packets[0], Packet::Sync);
+
+    let mut expected = Vec::new();
+    for _ in 0..3 {
+        expected.extend_from_slice(HELLO_MESSAGE.as_bytes());
+        expected.push(HELLO_BYTE);
+    }
+    assert_eq!(port_bytes(&packets, HELLO_PORT), expected);
+
+    for port in COUNTER_PORTS.iter() {
+        let words: Vec<Payload> = packets
+            .iter()
+            .filter_map(|packet| match packet {
+                Packet::Instrumentation { port: p, payload } if *p as usize == *port => {
+                    Some(*payload)
+                }
+                _ => None,
+            })
+            .collect();
+        let expected: Vec<Payload> = (0..6)
+            .map(|tick| Payload::U32(counter_word(*port, tick)))
+            .collect();
+        assert_eq!(words, expected);
+    }
+}
+
+#[test]
+fn widths_round() {
+    let mut stream = Stream::default();
+    for write in widths::ROUND.iter() {
+        let (packet, len) = write.packet();
+        stream.0.extend_from_slice(&packet[..len]);
+    }
+
+    let packets = stream.packets();
+    assert_eq!(packets.len(), widths::ROUND.len());
+    for (packet, write) in packets.iter().zip(widths::ROUND.iter()) {
+        let payload = match write.payload {
+            widths::Payload::U8(v) => Payload::U8(v),
+            widths::Payload::U16(v) => Payload::U16(v),
+            widths::Payload::U32(v) => Payload::U32(v),
+        };
+        assert_eq!(
+            *packet,
+            Packet::Instrumentation {
+                port: write.port as u8,
+                payload
+            }
+        );
+    }
+}
+
+#[test]
+fn exception_round() {
+    let mut stream = Stream::default();
+    let mut port = stream.port(exceptions::EXCEPTION_PORT);
+    port.write_u32(exceptions::DESCRIPTOR_MAGIC);
+    port.write_u32(0);
+    port.write_u32(exceptions::ROUND.len() as u32);
+    for event in exceptions::ROUND.iter() {
+        port.write_u16(event.payload());
+    }
+    for event in exceptions::ROUND.iter() {
+        stream.hardware(1, event.payload() as u32, 2);
+    }
+
+    let packets = stream.packets();
+    let described: Vec<ExceptionEvent> = packets[3..3 + exceptions::ROUND.len()]
+        .iter()
+        .map(|packet| match packet {
+            Packet::Instrumentation {
+                payload: Payload::U16(value),
+                ..
+            } => ExceptionEvent::from_payload(*value).unwrap(),
+            other => panic!("expected a 16-bit write, got {:?}", other),
+        })
+        .collect();
+    let traced: Vec<ExceptionEvent> = packets[3 + exceptions::ROUND.len()..]
+        .iter()
+        .map(|packet| match *packet {
+            Packet::Exception { number, function } => match function {
+                ExceptionFunction::Enter => ExceptionEvent::Enter(number),
+                ExceptionFunction::Exit => ExceptionEvent::Exit(number),
+                ExceptionFunction::Return => ExceptionEvent::Return(number),
+            },
+            other => panic!("expected exception trace, got {:?}", other),
+        })
+        .collect();
+    assert_eq!(described, exceptions::ROUND);
+    assert_eq!(traced, exceptions::ROUND);
+}
+
+#[test]
+fn timestamp_gaps() {
+    let mut stream = Stream::default();
+    for (index, gap) in GAP_TICKS.iter().enumerate() {
+        stream.port(0).write_u8(index as u8);
+        let before = stream.0.len();
+        stream.local_timestamp(*gap);
+        assert_eq!(stream.0.len() - before, lts_size(*gap));
+    }
+    stream.port(0).write_u8(0xff);
+    stream.local_timestamp(OVERFLOW_GAP_TICKS);
+
+    let packets = stream.packets();
+    let deltas: Vec<u32> = packets
+        .iter()
+        .filter_map(|packet| match packet {
+            Packet::LocalTimestamp { delta, relation } => {
+                assert_eq!(*relation, TimestampRelation::Synchronous);
+                Some(*delta)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(deltas, GAP_TICKS);
+    assert_eq!(packets.last(), Some(&Packet::Overflow));
+}
+
+#[test]
+fn event_counter_phases() {
+    let mut stream = Stream::default();
+    for counter in event_counters::EventCounter::ALL.iter() {
+        let marker = event_counters::Marker {
+            counter: *counter,
+            ticks: 10,
+        };
+        for word in marker.words().iter() {
+            stream
+                .port(event_counters::EVENT_COUNTER_PORT)
+                .write_u32(*word);
+        }
+        stream.hardware(0, counter.packet_bit() as u32, 1);
+    }
+
+    let flags: Vec<u8> = stream
+        .packets()
+        .iter()
+        .filter_map(|packet| match packet {
+            Packet::EventCounter { flags } => Some(*flags),
+            _ => None,
+        })
+        .collect();
+    let expected: Vec<u8> = event_counters::EventCounter::ALL
+        .iter()
+        .map(|counter| counter.packet_bit())
+        .collect();
+    assert_eq!(flags, expected);
+}
+
+#[test]
+fn data_trace_tick() {
+    let descriptor = data_trace::Descriptor {
+        counter: 0x2000_0010,
+        halfword: 0x2000_0014,
+        byte: 0x2000_0016,
+    };
+    let mut stream = Stream::default();
+    for word in descriptor.words().iter() {
+        stream.port(data_trace::DATA_TRACE_PORT).write_u32(*word);
+    }
+    let n = 0x1234_5678;
+    stream.port(data_trace::DATA_TRACE_PORT).write_u32(n);
+    /* Comparator 0: PC and data value on write */
+    stream.hardware(0b01000, 0x0800_0100, 4);
+    stream.hardware(0b10001, n, 4);
+    /* Comparator 1: data value on read */
+    stream.hardware(0b10010, n, 4);
+    /* Comparator 2: address offset */
+    stream.hardware(0b01101, descriptor.halfword & 0xffff, 2);
+    /* Comparator 3: PC */
+    stream.hardware(0b01110, 0x0800_0120, 4);
+
+    assert_eq!(
+        stream.packets()[5..],
+        [
+            Packet::DataTracePc {
+                comparator: 0,
+                pc: 0x0800_0100
+            },
+            Packet::DataTraceValue {
+                comparator: 0,
+                write: true,
+                value: Payload::U32(n)
+            },
+            Packet::DataTraceValue {
+                comparator: 1,
+                write: false,
+                value: Payload::U32(n)
+            },
+            Packet::DataTraceAddress {
+                comparator: 2,
+                offset: 0x0014
+            },
+            Packet::DataTracePc {
+                comparator: 3,
+                pc: 0x0800_0120
+            },
+        ]
+    );
+}
+
+#[test]
+fn pc_samples_and_sleep() {
+    let mut stream = Stream::default();
+    stream.hardware(2, 0x0800_0201, 4);
+    stream.hardware(2, 0, 1);
+    assert_eq!(
+        stream.packets(),
+        [Packet::PcSample(Some(0x0800_0201)), Packet::PcSample(None)]
+    );
+}
+
+#[test]
+fn global_timestamps_and_extensions() {
+    let stream = Stream(vec![
+        0x94, 0x85, 0x01, // GTS1, 14 bits
+        0x94, 0xff, 0xff, 0xff, 0x7f, // GTS1, all 26 bits with wrap and clock change
+        0xb4, 0x81, 0x80, 0x80, 0x01, // GTS2
+        0x08, // ITM page 0
+        0x9c, 0x01, // hardware extension with a continuation byte
+    ]);
+    assert_eq!(
+        stream.packets(),
+        [
+            Packet::GlobalTimestamp1 {
+                value: 0x85,
+                width: 14,
+                wrap: false,
+                clock_change: false
+            },
+            Packet::GlobalTimestamp1 {
+                value: 0x03ff_ffff,
+                width: 26,
+                wrap: true,
+                clock_change: true
+            },
+            Packet::GlobalTimestamp2 { high: 1 | 1 << 21 },
+            Packet::Extension {
+                hardware: false,
+                value: 0
+            },
+            Packet::Extension {
+                hardware: true,
+                value: 1 | 1 << 3
+            },
+        ]
+    );
+}
+
+#[test]
+fn recovers_from_malformed_packets() {
+    let mut stream = Stream::default();
+    stream.0.extend_from_slice(&[0x04]); // reserved header
+    stream.port(1).write_u8(0x11);
+    stream.0.extend_from_slice(&[0x00, 0x00, 0x70]); // broken sync ended by an overflow
+    stream.0.extend_from_slice(&[0xc0, 0x81, 0x81, 0x81, 0x81]); // overlong timestamp
+    stream.port(2).write_u16(0x2222);
+    stream.hardware(1, 0x0016, 2); // exception trace without a function
+    stream.sync();
+    stream.port(3).write_u32(0x3333_3333);
+
+    assert_eq!(
+        stream.decode(),
+        [
+            Err(DecodeError {
+                offset: 0,
+                kind: ErrorKind::ReservedHeader(0x04)
+            }),
+            Ok(Packet::Instrumentation {
+                port: 1,
+                payload: Payload::U8(0x11)
+            }),
+            Err(DecodeError {
+                offset: 3,
+                kind: ErrorKind::BrokenSync {
+                    zeros: 2,
+                    byte: 0x70
+                }
+            }),
+            Ok(Packet::Overflow),
+            Err(DecodeError {
+                offset: 6,
+                kind: ErrorKind::Overlong { header: 0xc0 }
+            }),
+            Ok(Packet::Instrumentation {
+                port: 2,
+                payload: Payload::U16(0x2222)
+            }),
+            Err(DecodeError {
+                offset: 14,
+                kind: ErrorKind::InvalidHardwareSource {
+                    discriminator: 1,
+                    payload: Payload::U16(0x0016)
+                }
+            }),
+            Ok(Packet::Sync),
+            Ok(Packet::Instrumentation {
+                port: 3,
+                payload: Payload::U32(0x3333_3333)
+            }),
+        ]
+    );
+}
+
+#[test]
+fn reports_truncated_packet() {
+    let mut decoder = Decoder::new();
+    assert_eq!(decoder.push(0x0b).count(), 0);
+    assert_eq!(decoder.push(0x01).count(), 0);
+    assert_eq!(
+        decoder.finish(),
+        Some(DecodeError {
+            offset: 0,
+            kind: ErrorKind::Truncated { header: 0x0b }
+        })
+    );
+}
@@ -0,0 +1,449 @@
+//! Sends "Hello, world!" through the ITM port 0, with counters on a few other ports
+//!
+//! ITM is much faster than semihosting. Like 4 orders of magnitude or so.
+//!
+//! **NOTE** Cortex-M0 chips don't support ITM.
+//!
+//! You'll have to connect the microcontroller's SWO pin to the SWD interface. Note that some
+//! development boards don't provide this option.
+//!
+//! You'll need `swodump` from this workspace, or [`itmdump`], to receive the message on the
+//! host plus you'll need to uncomment two `monitor` commands in the `.gdbinit` file.
+//!
+//! [`itmdump`]: https://docs.rs/itm/0.2.1/itm/
+//!
+//! ---
+
+#![no_main]
+#![no_std]
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{entry, exception};
+use nb::block;
+use panic_halt as _;
+use stm32f1xx_hal::{
+    pac::{self},
+    prelude::*,
+    timer::{SysEvent, Timer},
+};
+mod scenario;
+
+use tracetest::{
+    bert::Prbs,
+    config::{ClockSource, SwoConfig, SwoConfigError, TimestampPrescaler},
+    hello::{self, COUNTER_PORTS, HELLO_PORT},
+    itm::{self, ItmPort, Payload, Stimulus, TraceWrite},
+    mailbox::{Ack, Command, Mailbox, Response, Scenario, Settings, Status, ACK_PORT},
+    regs::{ItmTcr, Mmio, Registers, TraceMode, TracePins},
+    rtt::{ChannelMode, Rtt, RttBuffer, UpChannel, UpConfig},
+    sweep::SWEEP_PORT,
+    swo::{swo_setup, SwoProtocol},
+};
+
+// const SWO_BAUDRATE: u32 = 9600;
+const SWO_BAUDRATE: u32 = 4 * 1000 * 1000;
+/// Trace port to send everything out of. The parallel modes use TRACECK on PE2 and
+/// TRACED0 to TRACED3 on PE3 to PE6.
+const TRACE_MODE: TraceMode = if cfg!(feature = "parallel-4") {
+    TraceMode::Sync4
+} else if cfg!(feature = "parallel-2") {
+    TraceMode::Sync2
+} else if cfg!(feature = "parallel-1") {
+    TraceMode::Sync1
+} else {
+    TraceMode::Async
+};
+/// Sequence streamed by the `bert` feature
+const BERT_SEQUENCE: Prbs = if cfg!(feature = "bert-prbs7") {
+    Prbs::Prbs7
+} else if cfg!(feature = "bert-prbs15") {
+    Prbs::Prbs15
+} else {
+    Prbs::Prbs31
+};
+/// Scenario to run when the mailbox has not chosen one
+const DEFAULT_SCENARIO: Scenario = if cfg!(feature = "sweep") {
+    Scenario::Sweep
+} else if cfg!(feature = "timestamps") {
+    Scenario::Timestamps
+} else if cfg!(feature = "pc-sampling") {
+    Scenario::PcSampling
+} else if cfg!(feature = "exceptions") {
+    Scenario::Exceptions
+} else if cfg!(feature = "data-trace") {
+    Scenario::DataTrace
+} else if cfg!(feature = "event-counters") {
+    Scenario::EventCounters
+} else if cfg!(feature = "formatter") {
+    Scenario::Formatter
+} else if cfg!(feature = "framing") {
+    Scenario::Framing
+} else if cfg!(feature = "saturation") {
+    Scenario::Saturation
+} else if cfg!(feature = "bert") {
+    Scenario::Bert
+} else if cfg!(feature = "widths") {
+    Scenario::Widths
+} else {
+    Scenario::Hello
+};
+/// What the RTT channels do when the host falls behind
+const RTT_MODE: ChannelMode = if cfg!(feature = "rtt-blocking") {
+    ChannelMode::Blocking
+} else {
+    ChannelMode::NoBlockSkip
+};
+/// Rate of the SysTick timer that paces the scenarios
+const TICK_HZ: u32 = 10;
+/// How many ticks the sweep stays on each step
+const SWEEP_HOLD_TICKS: u32 = 20;
+/// How many ticks PC sampling stays at each rate
+const PC_SAMPLING_HOLD_TICKS: u32 = 50;
+/// How many ticks each event counter workload runs for
+const EVENT_COUNTER_HOLD_TICKS: u32 = 10;
+
+/// Command mailbox for the host, see `tracetest::mailbox`. It is not cleared at startup
+/// so that the settings survive the reset that applies them.
+#[no_mangle]
+#[link_section = ".uninit.TRACETEST_MAILBOX"]
+static TRACETEST_MAILBOX: Mailbox = Mailbox::new();
+
+/// RTT control block, see `tracetest::rtt`. Channel 0 mirrors the greeting on
+/// `HELLO_PORT` and channel 1 the words on `COUNTER_PORTS`.
+#[no_mangle]
+static _SEGGER_RTT: Rtt<2> = Rtt::new();
+static RTT_HELLO_BUFFER: RttBuffer<1024> = RttBuffer::new();
+static RTT_COUNTER_BUFFER: RttBuffer<1024> = RttBuffer::new();
+
+/// The hello-world loop's stimulus ports, each claimed for the life of the loop, with
+/// every write mirrored to RTT when that is on
+struct HelloPorts {
+    hello: ItmPort<HELLO_PORT>,
+    counter_a: ItmPort<{ COUNTER_PORTS[0] }>,
+    counter_b: ItmPort<{ COUNTER_PORTS[1] }>,
+    counter_c: ItmPort<{ COUNTER_PORTS[2] }>,
+    counter_d: ItmPort<{ COUNTER_PORTS[3] }>,
+    rtt_hello: Option<UpChannel>,
+    rtt_counters: Option<UpChannel>,
+}
+
+impl Stimulus for HelloPorts {
+    fn write(&mut self, port: usize, payload: Payload) {
+        let rtt = if port == HELLO_PORT {
+            self.hello.write_payload(payload);
+            self.rtt_hello.as_mut()
+        } else {
+            match COUNTER_PORTS.iter().position(|counter| *counter == port) {
+                Some(0) => self.counter_a.write_payload(payload),
+                Some(1) => self.counter_b.write_payload(payload),
+                Some(2) => self.counter_c.write_payload(payload),
+                Some(3) => self.counter_d.write_payload(payload),
+                _ => return,
+            }
+            self.rtt_counters.as_mut()
+        };
+        if let Some(rtt) = rtt {
+            rtt.write_payload(payload);
+        }
+    }
+
+    /// Mirror the greeting to RTT in one write, so that a channel without room for it
+    /// drops all of it rather than its tail
+    fn write_all(&mut self, port: usize, bytes: &[u8]) {
+        if port != HELLO_PORT {
+            for payload in itm::payloads(bytes) {
+                self.write(port, payload);
+            }
+            return;
+        }
+        self.hello.write_all(bytes);
+        if let Some(rtt) = self.rtt_hello.as_mut() {
+            rtt.write_all(bytes);
+        }
+    }
+}
+
+/// The trace configuration for `settings`
+fn swo_config(clock: ClockSource, settings: &Settings) -> Result<SwoConfig, SwoConfigError> {
+    let scenario = settings.scenario().unwrap_or(DEFAULT_SCENARIO);
+    // The sweep announces itself on its own stimulus port
+    let scenario_ports = if scenario == Scenario::Sweep {
+        hello::STIMULUS_PORTS | 1 << SWEEP_PORT
+    } else {
+        hello::STIMULUS_PORTS
+    };
+    let ack_port = if cfg!(feature = "mailbox") {
+        1 << ACK_PORT
+    } else {
+        0
+    };
+
+    let builder = SwoConfig::builder(clock, settings.baud())
+        .protocol(settings.protocol().unwrap_or(SwoProtocol::Manchester))
+        .trace_mode(TRACE_MODE)
+        .trace_pins(TracePins::Stm32f1Dbgmcu)
+        .stimulus_ports(settings.stimulus_ports().unwrap_or(scenario_ports) | ack_port);
+    match scenario {
+        Scenario::Timestamps => builder.local_timestamps(TimestampPrescaler::Div1),
+        Scenario::Formatter => builder.formatter(true),
+        _ => builder,
+    }
+    .build()
+}
+
+/// Mark command `seq` as done in the mailbox, and on the acknowledgement port if
+/// `on_port`. The port must be left alone while output is paused: with the ITM off its
+/// FIFO never reports room.
+fn acknowledge(seq: u32, status: Status, on_port: bool) {
+    if on_port {
+        if let Some(mut port) = ItmPort::<ACK_PORT>::take() {
+            for word in (Ack { seq, status }).words().iter() {
+                port.write_u32(*word);
+            }
+            port.release();
+        }
+    }
+    TRACETEST_MAILBOX.acknowledge(seq, status);
+}
+
+/// Turn the ITM off once it has sent what it holds, and stop it forwarding the DWT's
+/// packets. Returns the `ITM_TCR` value to put back.
+fn stop_trace(regs: &mut Mmio) -> ItmTcr {
+    let tcr = regs.read_typed::<ItmTcr>();
+    while regs.read_typed::<ItmTcr>().busy {}
+    regs.write_typed(ItmTcr {
+        itmena: false,
+        txena: false,
+        ..tcr
+    });
+    tcr
+}
+
+/// Act on whatever the host has left in the mailbox, staying here with trace turned off
+/// for as long as output is paused
+///
+/// Commands other than pause and resume are applied by resetting, see [`main`].
+fn poll_mailbox() {
+    // Safe because trace is only turned off and back on again before returning, so
+    // whatever `main` was doing with the trace registers sees them as it left them
+    let mut regs = unsafe { Mmio::new() };
+    let mut paused = None;
+    if TRACETEST_MAILBOX.settings().paused() {
+        paused = Some(stop_trace(&mut regs));
+    }
+    loop {
+        let Some((seq, command)) = TRACETEST_MAILBOX.pending() else {
+            if paused.is_none() {
+                return;
+            }
+            core::hint::spin_loop();
+            continue;
+        };
+        if let Ok(command @ (Command::Pause | Command::Resume)) = command {
+            TRACETEST_MAILBOX.set_settings(TRACETEST_MAILBOX.settings().with(command));
+        }
+        match Response::to(command, paused.is_some()) {
+            Response::Pause => {
+                // Acknowledge first so that the host still sees it
+                acknowledge(seq, Status::Ok, true);
+                paused = Some(stop_trace(&mut regs));
+            }
+            Response::Resume => {
+                if let Some(tcr) = paused.take() {
+                    regs.write_typed(tcr);
+                }
+                acknowledge(seq, Status::Ok, true);
+            }
+            Response::Acknowledge { status, on_port } => acknowledge(seq, status, on_port),
+            Response::Reset => {
+                TRACETEST_MAILBOX.request_reset();
+                SCB::sys_reset()
+            }
+        }
+    }
+}
+
+#[entry]
+fn main() -> ! {
+    // let mut p = Peripherals::take().unwrap();
+    // let stim = &mut p.ITM.stim[0];
+
+    // loop {
+    //     iprintln!(stim, "Hello, world!");
+    // }
+    // Get access to the core peripherals from the cortex-m crate
+    let mut cp = cortex_m::Peripherals::take().unwrap();
+    // Get access to the device specific peripherals from the peripheral access crate
+    let dp = pac::Peripherals::take().unwrap();
+
+    // Take ownership over the raw flash and rcc devices and convert them into the corresponding
+    // HAL structs
+    let mut flash = dp.FLASH.constrain();
+    let rcc = dp.RCC.constrain();
+
+    // Freeze the configuration of all the clocks in the system and store the frozen frequencies in
+    // `clocks`
+    let clocks = rcc.cfgr.freeze(&mut flash.acr);
+
+    // Apply any command the host left before resetting us, keeping the old settings if
+    // the new ones would not work
+    let clock = ClockSource::Hclk(clocks.hclk().to_Hz());
+    let defaults = Settings::new(DEFAULT_SCENARIO, SWO_BAUDRATE, SwoProtocol::Manchester);
+    let mut settings = defaults;
+    let mut ack = None;
+    if cfg!(feature = "mailbox") {
+        // Only carry settings over the reset the mailbox asked for, not into a newly
+        // flashed image
+        if !TRACETEST_MAILBOX.take_reset_request() {
+            TRACETEST_MAILBOX.initialise(defaults);
+        }
+        settings = TRACETEST_MAILBOX.settings();
+        if let Some((seq, command)) = TRACETEST_MAILBOX.pending() {
+            let status = match command {
+                Ok(command) => {
+                    let candidate = settings.with(command);
+                    if swo_config(clock, &candidate).is_ok() {
+                        settings = candidate;
+                        Status::Ok
+                    } else {
+                        Status::InvalidConfig
+                    }
+                }
+                Err(status) => status,
+            };
+            TRACETEST_MAILBOX.set_settings(settings);
+            ack = Some((seq, status));
+        }
+    }
+
+    // Refuse to start rather than put the wrong baud rate on the wire
+    let (scenario, swo_config) = match (settings.scenario(), swo_config(clock, &settings)) {
+        (Some(scenario), Ok(swo_config)) => (scenario, swo_config),
+        _ => (DEFAULT_SCENARIO, swo_config(clock, &defaults).unwrap()),
+    };
+
+    // Safe because this is an STM32F103 and nothing else touches the trace registers
+    let mut swo = swo_setup(unsafe { Mmio::new() }, &swo_config);
+    if let Some((seq, status)) = ack {
+        acknowledge(seq, status, true);
+    }
+    if cfg!(feature = "mailbox") {
+        poll_mailbox();
+    }
+
+    let mut rtt_hello = None;
+    let mut rtt_counters = None;
+    if cfg!(feature = "rtt") {
+        _SEGGER_RTT.init([
+            UpConfig::new(b"Hello\0", &RTT_HELLO_BUFFER, RTT_MODE),
+            UpConfig::new(b"Counters\0", &RTT_COUNTER_BUFFER, RTT_MODE),
+        ]);
+        rtt_hello = _SEGGER_RTT.up(0);
+        rtt_counters = _SEGGER_RTT.up(1);
+    }
+
+    // Acquire the GPIOC peripheral
+    let mut gpioa = dp.GPIOA.split();
+
+    // Configure gpio A pin 3 as a push-pull output. The `crl` register is passed to the function
+    // in order to configure the port. For pins 8-15, crh should be passed instead.
+    let mut led = gpioa.pa3.into_push_pull_output(&mut gpioa.crl);
+    // Configure the syst timer to trigger an update every second
+    let mut timer = Timer::syst(cp.SYST, &clocks).counter_hz();
+    timer.start(TICK_HZ.Hz()).unwrap();
+    if cfg!(feature = "mailbox") {
+        // The mailbox is checked from the SysTick handler
+        timer.listen(SysEvent::Update);
+    }
+
+    match scenario {
+        Scenario::Sweep => {
+            scenario::sweep::run(&mut swo, &mut cp.ITM, SWEEP_HOLD_TICKS, || {
+                led.toggle();
+                block!(timer.wait()).unwrap();
+            });
+        }
+        Scenario::Timestamps => {
+            scenario::timestamps::run(&mut swo, &mut cp.ITM);
+        }
+        Scenario::PcSampling => {
+            // WFI needs an interrupt to wake it at the end of each tick
+            timer.listen(SysEvent::Update);
+            let tick_cycles = clocks.hclk().to_Hz() / TICK_HZ;
+            scenario::pc_sampling::run(&mut swo, &mut cp.ITM, tick_cycles, PC_SAMPLING_HOLD_TICKS);
+        }
+        Scenario::Exceptions => {
+            scenario::exceptions::run(
+                &mut swo,
+                &mut cp.ITM,
+                &mut cp.NVIC,
+                &mut cp.SCB,
+                dp.TIM2,
+                dp.TIM3,
+                &clocks,
+                || block!(timer.wait()).unwrap(),
+            );
+        }
+        Scenario::DataTrace => {
+            scenario::data_trace::run(&mut swo, &mut cp.ITM, || block!(timer.wait()).unwrap());
+        }
+        Scenario::EventCounters => {
+            // The sleep workload needs an interrupt to wake it at the end of each tick
+            timer.listen(SysEvent::Update);
+            scenario::event_counters::run(&mut swo, &mut cp.ITM, EVENT_COUNTER_HOLD_TICKS, || {
+                timer.wait().is_ok()
+            });
+        }
+        Scenario::Formatter => {
+            scenario::formatter::run(&mut swo, &mut cp.ITM, || block!(timer.wait()).unwrap());
+        }
+        Scenario::Framing => {
+            scenario::framing::run(&mut swo, &mut cp.ITM, || block!(timer.wait()).unwrap());
+        }
+        Scenario::Saturation => {
+            scenario::saturation::run(&mut swo, &mut cp.ITM, || block!(timer.wait()).unwrap());
+        }
+        Scenario::Bert => {
+            scenario::bert::run(&mut swo, &mut cp.ITM, BERT_SEQUENCE, || {
+                timer.wait().is_ok()
+            });
+        }
+        Scenario::Widths => {
+            scenario::widths::run(&mut swo, &mut cp.ITM, || block!(timer.wait()).unwrap());
+        }
+        Scenario::Hello => {}
+    }
+
+    let mut ports = HelloPorts {
+        hello: ItmPort::take().unwrap(),
+        counter_a: ItmPort::take().unwrap(),
+        counter_b: ItmPort::take().unwrap(),
+        counter_c: ItmPort::take().unwrap(),
+        counter_d: ItmPort::take().unwrap(),
+        rtt_hello,
+        rtt_counters,
+    };
+
+    // Wait for the timer to trigger an update and change the state of the LED. The LED
+    // is on for the greeting and off for the single byte.
+    let mut tick = 0u32;
+    loop {
+        if tick & 1 == 0 {
+            led.set_high();
+        } else {
+            led.set_low();
+        }
+        hello::send_tick(&mut ports, tick);
+        tick = tick.wrapping_add(1);
+        block!(timer.wait()).unwrap();
+    }
+}
+
+/// Wakes the core from WFI, and checks the mailbox once per tick. The timer flag is
+/// polled separately.
+#[exception]
+fn SysTick() {
+    if cfg!(feature = "mailbox") {
+        poll_mailbox();
+    }
+}
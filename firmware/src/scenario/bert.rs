@@ -0,0 +1,40 @@
+//! Continuous PRBS stream for bit error rate testing
+
+use cortex_m::peripheral::ITM;
+use tracetest::{
+    bert::{send_word, Descriptor, Lfsr, Prbs, BERT_PORT, DESCRIPTOR_PORT},
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::Stimuli;
+
+/// Stream `prbs` forever, with a descriptor whenever `ticked` returns true
+pub fn run<R: Registers>(
+    swo: &mut SwoHandle<R>,
+    itm: &mut ITM,
+    prbs: Prbs,
+    mut ticked: impl FnMut() -> bool,
+) -> ! {
+    let config = swo
+        .config()
+        .to_builder()
+        .stimulus_ports(swo.config().stimulus_ports() | 1 << BERT_PORT | 1 << DESCRIPTOR_PORT)
+        .no_local_timestamps()
+        .build()
+        .unwrap();
+    swo.reconfigure(&config);
+
+    let mut lfsr = Lfsr::new(prbs);
+    let mut descriptor = Descriptor {
+        prbs,
+        words_sent: 0,
+    };
+    let mut out = Stimuli(itm);
+    loop {
+        send_word(&mut out, &mut lfsr, &mut descriptor);
+        if ticked() {
+            descriptor.send(&mut out);
+        }
+    }
+}
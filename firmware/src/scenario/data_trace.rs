@@ -0,0 +1,52 @@
+//! Watched statics updated once per tick for DWT data trace
+
+use core::sync::atomic::{AtomicU16, AtomicU32, AtomicU8, Ordering};
+
+use cortex_m::peripheral::ITM;
+use tracetest::{
+    data_trace::{Descriptor, DATA_TRACE_PORT},
+    dwt::DwtConfig,
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::write_word;
+
+static WATCHED_COUNTER: AtomicU32 = AtomicU32::new(0);
+static WATCHED_HALFWORD: AtomicU16 = AtomicU16::new(0);
+static WATCHED_BYTE: AtomicU8 = AtomicU8::new(0);
+
+/// Run the data trace accesses once per call to `wait`, forever
+pub fn run<R: Registers>(swo: &mut SwoHandle<R>, itm: &mut ITM, mut wait: impl FnMut()) -> ! {
+    let descriptor = Descriptor {
+        counter: &WATCHED_COUNTER as *const _ as u32,
+        halfword: &WATCHED_HALFWORD as *const _ as u32,
+        byte: &WATCHED_BYTE as *const _ as u32,
+    };
+    let config = swo
+        .config()
+        .to_builder()
+        .dwt(DwtConfig {
+            watchpoints: descriptor.watchpoints(),
+            ..*swo.config().dwt()
+        })
+        .build()
+        .unwrap();
+    swo.reconfigure(&config);
+
+    for word in descriptor.words().iter() {
+        write_word(&mut itm.stim[DATA_TRACE_PORT], *word);
+    }
+
+    let mut n = 0u32;
+    loop {
+        write_word(&mut itm.stim[DATA_TRACE_PORT], n);
+        WATCHED_COUNTER.store(n, Ordering::SeqCst);
+        let _ = WATCHED_COUNTER.load(Ordering::SeqCst);
+        WATCHED_HALFWORD.store(n as u16, Ordering::SeqCst);
+        let _ = WATCHED_BYTE.load(Ordering::SeqCst);
+
+        n = n.wrapping_add(1);
+        wait();
+    }
+}
@@ -0,0 +1,127 @@
+//! Workloads that make each DWT event counter wrap
+
+use core::hint::black_box;
+
+use cortex_m::peripheral::{ITM, NVIC};
+use stm32f1xx_hal::pac::{interrupt, Interrupt};
+use tracetest::{
+    event_counters::{EventCounter, Marker, EVENT_COUNTER_PORT},
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::write_word;
+
+static mut LSU_BUFFER: [u32; 64] = [0; 64];
+
+/// The target of the exception storm. Entry and exit are all that is wanted.
+#[interrupt]
+fn EXTI2() {}
+
+fn cpi_workload() {
+    let mut acc = 0u32;
+    for divisor in 1..=64u32 {
+        acc = acc.wrapping_add(black_box(0xdead_beef) / black_box(divisor));
+    }
+    black_box(acc);
+}
+
+fn exc_workload() {
+    NVIC::pend(Interrupt::EXTI2);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+fn lsu_workload() {
+    let buffer = unsafe { &mut *core::ptr::addr_of_mut!(LSU_BUFFER) };
+    for word in buffer.iter_mut() {
+        unsafe {
+            let value = core::ptr::read_volatile(word);
+            core::ptr::write_volatile(word, value.wrapping_add(1));
+        }
+    }
+}
+
+#[cfg(target_arch = "arm")]
+fn fold_workload() {
+    unsafe {
+        core::arch::asm!(
+            "movs {n}, #64",
+            "2:",
+            "cmp {n}, #32",
+            "ite lt",
+            "addlt {acc}, {acc}, #1",
+            "subge {acc}, {acc}, #1",
+            "subs {n}, {n}, #1",
+            "bne 2b",
+            n = out(reg) _,
+            acc = inout(reg) 0u32 => _,
+            options(nomem, nostack),
+        );
+    }
+}
+
+#[cfg(not(target_arch = "arm"))]
+fn fold_workload() {}
+
+/// Run each counter's workload for `hold_ticks` ticks in turn, forever
+///
+/// `ticked` returns whether a timer tick has passed since it was last called. The
+/// SysTick interrupt must be enabled so that the sleep phase wakes up.
+pub fn run<R: Registers>(
+    swo: &mut SwoHandle<R>,
+    itm: &mut ITM,
+    hold_ticks: u32,
+    mut ticked: impl FnMut() -> bool,
+) -> ! {
+    let base = *swo.config();
+    unsafe { NVIC::unmask(Interrupt::EXTI2) };
+    loop {
+        for counter in EventCounter::ALL.iter().copied() {
+            let dwt = counter.dwt_config(base.dwt());
+            swo.reconfigure(&base.to_builder().dwt(dwt).build().unwrap());
+            for other in EventCounter::ALL.iter() {
+                swo.registers().write(other.register(), 0);
+            }
+
+            let marker = Marker {
+                counter,
+                ticks: hold_ticks,
+            };
+            for word in marker.words().iter() {
+                write_word(&mut itm.stim[EVENT_COUNTER_PORT], *word);
+            }
+
+            /* Start on a tick boundary */
+            while !ticked() {}
+            for _ in 0..hold_ticks {
+                match counter {
+                    EventCounter::Sleep => {
+                        cortex_m::asm::wfi();
+                        while !ticked() {}
+                    }
+                    EventCounter::Cpi => {
+                        while !ticked() {
+                            cpi_workload()
+                        }
+                    }
+                    EventCounter::Exc => {
+                        while !ticked() {
+                            exc_workload()
+                        }
+                    }
+                    EventCounter::Lsu => {
+                        while !ticked() {
+                            lsu_workload()
+                        }
+                    }
+                    EventCounter::Fold => {
+                        while !ticked() {
+                            fold_workload()
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! A fixed round of nested, tail-chained and timer interrupts for exception trace
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use cortex_m::peripheral::{scb::SystemHandler, ITM, NVIC, SCB};
+use cortex_m_rt::exception;
+use stm32f1xx_hal::{
+    pac::{self, interrupt, Interrupt},
+    rcc::Clocks,
+    timer::Timer,
+};
+use tracetest::{
+    dwt::{DwtConfig, Enable},
+    exceptions::{
+        describe_round, EXTI0_PRIORITY, EXTI1_PRIORITY, PENDSV_PRIORITY, TIM2_PRIORITY,
+        TIM3_PRIORITY,
+    },
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::Stimuli;
+
+/// Delay from starting TIM2 to its interrupt, in microseconds
+const TIM2_DELAY_US: u16 = 100;
+/// Delay from TIM2's handler starting TIM3 to TIM3's interrupt, in microseconds
+const TIM3_DELAY_US: u16 = 50;
+
+/// Set while EXTI0 should pend EXTI1 from inside its handler
+static NEST: AtomicBool = AtomicBool::new(false);
+static TIM2_DONE: AtomicBool = AtomicBool::new(false);
+static TIM3_DONE: AtomicBool = AtomicBool::new(false);
+
+fn tim2() -> &'static pac::tim2::RegisterBlock {
+    unsafe { &*pac::TIM2::ptr() }
+}
+
+fn tim3() -> &'static pac::tim3::RegisterBlock {
+    unsafe { &*pac::TIM3::ptr() }
+}
+
+/// Wait for any pended exception to be taken before carrying on
+fn settle() {
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
+
+#[interrupt]
+fn EXTI0() {
+    if NEST.load(Ordering::SeqCst) {
+        NVIC::pend(Interrupt::EXTI1);
+        settle();
+    }
+}
+
+#[interrupt]
+fn EXTI1() {}
+
+#[exception]
+fn PendSV() {}
+
+#[interrupt]
+fn TIM2() {
+    tim2().sr.modify(|_, w| w.uif().clear_bit());
+    tim3().cr1.modify(|_, w| w.cen().set_bit());
+    while !TIM3_DONE.load(Ordering::SeqCst) {
+        core::hint::spin_loop();
+    }
+    TIM2_DONE.store(true, Ordering::SeqCst);
+}
+
+#[interrupt]
+fn TIM3() {
+    tim3().sr.modify(|_, w| w.uif().clear_bit());
+    TIM3_DONE.store(true, Ordering::SeqCst);
+}
+
+/// Put a timer in one-pulse mode with a 1 MHz count and an update interrupt
+macro_rules! one_pulse {
+    ($tim:expr, $timer_clock:expr, $delay_us:expr) => {{
+        let tim = $tim;
+        tim.psc
+            .write(|w| w.psc().bits(($timer_clock / 1_000_000 - 1) as u16));
+        tim.arr.write(|w| w.arr().bits($delay_us));
+        tim.cr1.write(|w| w.opm().set_bit());
+        /* Load the prescaler, then throw away the update flag that sets */
+        tim.egr.write(|w| w.ug().set_bit());
+        tim.sr.write(|w| w.uif().clear_bit());
+        tim.dier.write(|w| w.uie().set_bit());
+    }};
+}
+
+/// Run one round of interrupts per call to `wait`, forever
+#[allow(clippy::too_many_arguments)]
+pub fn run<R: Registers>(
+    swo: &mut SwoHandle<R>,
+    itm: &mut ITM,
+    nvic: &mut NVIC,
+    scb: &mut SCB,
+    tim2_peripheral: pac::TIM2,
+    tim3_peripheral: pac::TIM3,
+    clocks: &Clocks,
+    mut wait: impl FnMut(),
+) -> ! {
+    let config = swo
+        .config()
+        .to_builder()
+        .dwt(DwtConfig {
+            exception_trace: Enable::On,
+            ..*swo.config().dwt()
+        })
+        .build()
+        .unwrap();
+    swo.reconfigure(&config);
+
+    /* Enable the timers' clocks, then program them directly */
+    Timer::new(tim2_peripheral, clocks).release();
+    Timer::new(tim3_peripheral, clocks).release();
+    let timer_clock = clocks.pclk1_tim().to_Hz();
+    one_pulse!(tim2(), timer_clock, TIM2_DELAY_US);
+    one_pulse!(tim3(), timer_clock, TIM3_DELAY_US);
+
+    unsafe {
+        nvic.set_priority(Interrupt::EXTI0, EXTI0_PRIORITY);
+        nvic.set_priority(Interrupt::EXTI1, EXTI1_PRIORITY);
+        nvic.set_priority(Interrupt::TIM2, TIM2_PRIORITY);
+        nvic.set_priority(Interrupt::TIM3, TIM3_PRIORITY);
+        scb.set_priority(SystemHandler::PendSV, PENDSV_PRIORITY);
+        NVIC::unmask(Interrupt::EXTI0);
+        NVIC::unmask(Interrupt::EXTI1);
+        NVIC::unmask(Interrupt::TIM2);
+        NVIC::unmask(Interrupt::TIM3);
+    }
+
+    let mut round = 0u32;
+    loop {
+        describe_round(&mut Stimuli(itm), round);
+        /* Keep the description's packets out of the way of the exception trace */
+        swo.wait_idle();
+
+        /* Nesting */
+        NEST.store(true, Ordering::SeqCst);
+        NVIC::pend(Interrupt::EXTI0);
+        settle();
+        NEST.store(false, Ordering::SeqCst);
+
+        /* Tail-chaining */
+        cortex_m::interrupt::free(|_| {
+            SCB::set_pendsv();
+            NVIC::pend(Interrupt::EXTI0);
+            NVIC::pend(Interrupt::EXTI1);
+        });
+        settle();
+
+        /* Hardware timers */
+        TIM2_DONE.store(false, Ordering::SeqCst);
+        TIM3_DONE.store(false, Ordering::SeqCst);
+        tim2().cr1.modify(|_, w| w.cen().set_bit());
+        while !TIM2_DONE.load(Ordering::SeqCst) {
+            core::hint::spin_loop();
+        }
+
+        round = round.wrapping_add(1);
+        wait();
+    }
+}
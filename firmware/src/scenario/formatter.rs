@@ -0,0 +1,30 @@
+//! ITM trace ID switching under the TPIU formatter
+
+use cortex_m::peripheral::ITM;
+use tracetest::{
+    formatter::{send_phase, ETM_TRACE_ID, TRACE_IDS},
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::Stimuli;
+
+/// Step through every trace ID, one per call to `wait`, forever
+pub fn run<R: Registers>(swo: &mut SwoHandle<R>, itm: &mut ITM, mut wait: impl FnMut()) -> ! {
+    let base = *swo.config();
+    loop {
+        for id in TRACE_IDS.iter().copied() {
+            let config = base
+                .to_builder()
+                .formatter(true)
+                .itm_trace_id(id)
+                .etm_trace_id(ETM_TRACE_ID)
+                .build()
+                .unwrap();
+            swo.reconfigure(&config);
+
+            send_phase(&mut Stimuli(itm), id);
+            wait();
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! The hello-world loop with every message framed
+
+use cortex_m::peripheral::ITM;
+use tracetest::{
+    framing::{send_tick, Framer, REPORT_PORT},
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::Stimuli;
+
+/// Send one framed greeting and one framed counter per call to `wait`, forever,
+/// reporting both ports' totals after each
+pub fn run<R: Registers>(swo: &mut SwoHandle<R>, itm: &mut ITM, mut wait: impl FnMut()) -> ! {
+    let config = swo
+        .config()
+        .to_builder()
+        .stimulus_ports(swo.config().stimulus_ports() | 1 << REPORT_PORT)
+        .build()
+        .unwrap();
+    swo.reconfigure(&config);
+
+    let mut hello = Framer::new();
+    let mut counter = Framer::new();
+    let mut tick = 0u32;
+    loop {
+        send_tick(&mut Stimuli(itm), &mut hello, &mut counter, tick);
+        tick = tick.wrapping_add(1);
+        wait();
+    }
+}
@@ -0,0 +1,59 @@
+//! Test scenarios run by the firmware
+//!
+//! Each scenario takes over the main loop. Which one runs is chosen with Cargo features.
+
+pub mod bert;
+pub mod data_trace;
+pub mod event_counters;
+pub mod exceptions;
+pub mod formatter;
+pub mod framing;
+pub mod pc_sampling;
+pub mod saturation;
+pub mod sweep;
+pub mod timestamps;
+pub mod widths;
+
+use cortex_m::peripheral::{itm::Stim, ITM};
+use tracetest::itm::{Payload, Stimulus};
+
+/// Write a byte to a stimulus port, waiting for room in the ITM FIFO
+pub fn write_byte(stim: &mut Stim, byte: u8) {
+    while !stim.is_fifo_ready() {}
+    stim.write_u8(byte);
+}
+
+/// Write a halfword to a stimulus port, waiting for room in the ITM FIFO
+pub fn write_halfword(stim: &mut Stim, halfword: u16) {
+    while !stim.is_fifo_ready() {}
+    stim.write_u16(halfword);
+}
+
+/// Write a word to a stimulus port, waiting for room in the ITM FIFO
+pub fn write_word(stim: &mut Stim, word: u32) {
+    while !stim.is_fifo_ready() {}
+    stim.write_u32(word);
+}
+
+/// The ITM's stimulus ports, for the scripts in the scenarios' library modules
+pub struct Stimuli<'a>(pub &'a mut ITM);
+
+impl Stimulus for Stimuli<'_> {
+    fn write(&mut self, port: usize, payload: Payload) {
+        let stim = &mut self.0.stim[port];
+        match payload {
+            Payload::U8(value) => write_byte(stim, value),
+            Payload::U16(value) => write_halfword(stim, value),
+            Payload::U32(value) => write_word(stim, value),
+        }
+    }
+}
+
+/// Busy-wait for at least `cycles` core clock cycles
+pub fn delay_cycles(mut cycles: u64) {
+    while cycles > 0 {
+        let chunk = cycles.min(u32::MAX as u64);
+        cortex_m::asm::delay(chunk as u32);
+        cycles -= chunk;
+    }
+}
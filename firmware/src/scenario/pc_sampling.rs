@@ -0,0 +1,68 @@
+//! A workload with known hot functions and sleep periods for PC sampling
+
+use cortex_m::peripheral::{DWT, ITM};
+use tracetest::{
+    pc_sampling::{
+        dwt_config, Descriptor, HOT_A_QUARTERS, HOT_B_QUARTERS, PC_SAMPLING_PORT, RATES,
+    },
+    regs::Registers,
+    swo::SwoHandle,
+};
+
+use super::write_word;
+
+/// Spin until `cycles` have passed since `start`
+#[inline(always)]
+fn spin_until(start: u32, cycles: u32) {
+    while DWT::cycle_count().wrapping_sub(start) < cycles {}
+}
+
+#[inline(never)]
+fn pc_sample_hot_a(start: u32, cycles: u32) {
+    spin_until(start, cycles);
+}
+
+#[inline(never)]
+fn pc_sample_hot_b(start: u32, cycles: u32) {
+    spin_until(start, cycles);
+}
+
+/// Run the PC sampling workload forever
+///
+/// The SysTick interrupt must be enabled and fire every `tick_cycles` so that WFI wakes
+/// up once per tick.
+pub fn run<R: Registers>(
+    swo: &mut SwoHandle<R>,
+    itm: &mut ITM,
+    tick_cycles: u32,
+    hold_ticks: u32,
+) -> ! {
+    let base = *swo.config();
+    let quarter = tick_cycles / 4;
+    loop {
+        for (cyc_tap, post_preset) in RATES.iter().copied() {
+            let dwt = dwt_config(cyc_tap, post_preset);
+            swo.reconfigure(&base.to_builder().dwt(dwt).build().unwrap());
+
+            let descriptor = Descriptor {
+                sample_period: dwt.pc_sample_period(),
+                tick_cycles,
+                hot_a: pc_sample_hot_a as fn(u32, u32) as usize as u32,
+                hot_b: pc_sample_hot_b as fn(u32, u32) as usize as u32,
+                hold_ticks,
+            };
+            for word in descriptor.words().iter() {
+                write_word(&mut itm.stim[PC_SAMPLING_PORT], *word);
+            }
+
+            /* Line the first tick up with the timer */
+            cortex_m::asm::wfi();
+            for _ in 0..hold_ticks {
+                let start = DWT::cycle_count();
+                pc_sample_hot_a(start, quarter * HOT_A_QUARTERS);
+                pc_sample_hot_b(start, quarter * (HOT_A_QUARTERS + HOT_B_QUARTERS));
+                cortex_m::asm::wfi();
+            }
+        }
+    }
+}
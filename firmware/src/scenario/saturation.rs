@@ -0,0 +1,54 @@
+//! Deliberately overrun the ITM and report the offered load
+
+use cortex_m::peripheral::{DWT, ITM};
+use tracetest::{
+    config::TimestampPrescaler,
+    regs::Registers,
+    saturation::{stress_word, Report, PHASES, REPORT_PORT, STRESS_PORTS, WRITES_PER_PHASE},
+    swo::SwoHandle,
+};
+
+use super::write_word;
+
+/// Run every phase in turn, one per call to `wait`, forever
+pub fn run<R: Registers>(swo: &mut SwoHandle<R>, itm: &mut ITM, mut wait: impl FnMut()) -> ! {
+    let base = *swo.config();
+    loop {
+        for (index, phase) in PHASES.iter().enumerate() {
+            let mut config = base
+                .to_builder()
+                .stimulus_ports(base.stimulus_ports() | phase.stimulus_ports() | 1 << REPORT_PORT)
+                .dwt(phase.dwt_config(base.dwt()));
+            config = if phase.timestamps {
+                config.local_timestamps(TimestampPrescaler::Div1)
+            } else {
+                config.no_local_timestamps()
+            };
+            let config = config.build().unwrap();
+            swo.reconfigure(&config);
+
+            let start = DWT::cycle_count();
+            for n in 0..WRITES_PER_PHASE {
+                let port = STRESS_PORTS[n as usize % phase.ports];
+                /* No FIFO check: writes made while it is full are dropped */
+                itm.stim[port].write_u32(stress_word(index, n));
+            }
+            let cycles = DWT::cycle_count().wrapping_sub(start);
+
+            /* Quiet the DWT so that the report is not lost in the same way */
+            let report = Report::new(index, cycles, &config);
+            swo.reconfigure(
+                &config
+                    .to_builder()
+                    .dwt(PHASES[0].dwt_config(base.dwt()))
+                    .no_local_timestamps()
+                    .build()
+                    .unwrap(),
+            );
+            for word in report.words().iter() {
+                write_word(&mut itm.stim[REPORT_PORT], *word);
+            }
+            wait();
+        }
+    }
+}
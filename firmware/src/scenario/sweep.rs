@@ -0,0 +1,47 @@
+//! Step through the sweep table, announcing each setting on the sweep port
+
+use cortex_m::{iprintln, peripheral::ITM};
+use tracetest::{
+    regs::Registers,
+    sweep::{Descriptor, SWEEP_TABLE},
+    swo::SwoHandle,
+};
+
+use super::Stimuli;
+
+/// Run the sweep forever, staying on each step for `hold_ticks` calls to `wait`
+///
+/// The preamble and descriptor are repeated once per tick so that a probe which starts
+/// listening part-way through a step still sees them.
+pub fn run<R: Registers>(
+    swo: &mut SwoHandle<R>,
+    itm: &mut ITM,
+    hold_ticks: u32,
+    mut wait: impl FnMut(),
+) -> ! {
+    loop {
+        for (index, step) in SWEEP_TABLE.iter().enumerate() {
+            let config = match swo
+                .config()
+                .to_builder()
+                .baud(step.baud)
+                .protocol(step.protocol)
+                .build()
+            {
+                Ok(config) => config,
+                Err(e) => {
+                    /* Say so at the old setting rather than silently skipping the step */
+                    iprintln!(&mut itm.stim[0], "sweep step {} skipped: {}", index, e);
+                    continue;
+                }
+            };
+            swo.reconfigure(&config);
+
+            let descriptor = Descriptor::new(index, SWEEP_TABLE.len(), &config);
+            for _ in 0..hold_ticks {
+                descriptor.announce(&mut Stimuli(itm));
+                wait();
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Bursts and idle gaps that exercise every local timestamp packet format
+
+use cortex_m::peripheral::ITM;
+use tracetest::{
+    config::{SwoConfig, TimestampPrescaler},
+    regs::Registers,
+    swo::SwoHandle,
+    timestamps::{
+        marker, BURST_LEN, GAP_TICKS, OVERFLOW_GAP_TICKS, PHASE_BURST, PHASE_OVERFLOW,
+        TIMESTAMP_PORT,
+    },
+};
+
+use super::{delay_cycles, write_byte, write_word};
+
+fn with_prescaler(config: &SwoConfig, prescaler: TimestampPrescaler) -> SwoConfig {
+    config
+        .to_builder()
+        .local_timestamps(prescaler)
+        .build()
+        .unwrap()
+}
+
+/// Run the timestamp exercise forever, as described in [`tracetest::timestamps`]
+pub fn run<R: Registers>(swo: &mut SwoHandle<R>, itm: &mut ITM) -> ! {
+    let base = *swo.config();
+    loop {
+        for prescaler in TimestampPrescaler::ALL.iter().copied() {
+            swo.reconfigure(&with_prescaler(&base, prescaler));
+            let stim = &mut itm.stim[TIMESTAMP_PORT];
+
+            write_word(stim, marker(prescaler, PHASE_BURST));
+            for byte in 0..BURST_LEN {
+                write_byte(stim, byte as u8);
+            }
+
+            for (index, gap) in GAP_TICKS.iter().enumerate() {
+                write_word(stim, marker(prescaler, index as u8 + 1));
+                delay_cycles(*gap as u64 * prescaler.divisor() as u64);
+                write_byte(stim, index as u8);
+            }
+        }
+
+        swo.reconfigure(&with_prescaler(&base, TimestampPrescaler::Div1));
+        let stim = &mut itm.stim[TIMESTAMP_PORT];
+        write_word(stim, marker(TimestampPrescaler::Div1, PHASE_OVERFLOW));
+        delay_cycles(OVERFLOW_GAP_TICKS as u64);
+        write_byte(stim, 0xff);
+    }
+}
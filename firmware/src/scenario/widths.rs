@@ -0,0 +1,27 @@
+//! One round of mixed-width stimulus writes per tick
+
+use cortex_m::peripheral::ITM;
+use tracetest::{
+    regs::Registers,
+    swo::SwoHandle,
+    widths::{self, stimulus_ports},
+};
+
+use super::Stimuli;
+
+/// Make the writes in [`widths::ROUND`] once per call to `wait`, forever
+pub fn run<R: Registers>(swo: &mut SwoHandle<R>, itm: &mut ITM, mut wait: impl FnMut()) -> ! {
+    let config = swo
+        .config()
+        .to_builder()
+        .stimulus_ports(swo.config().stimulus_ports() | stimulus_ports())
+        .no_local_timestamps()
+        .build()
+        .unwrap();
+    swo.reconfigure(&config);
+
+    loop {
+        widths::send_round(&mut Stimuli(itm));
+        wait();
+    }
+}
@@ -0,0 +1,168 @@
+//! Golden ITM traces of the firmware's scenarios
+//!
+//! Each [`Fixture`] is the byte stream one scenario puts into the ITM, built by running
+//! the same functions the firmware runs, such as `tracetest::hello::send_tick`, against
+//! an [`Encoder`] instead of the stimulus ports. A change to a scenario therefore shows
+//! up as a change to its fixture, and `tests/golden.rs` fails until the checked-in copies
+//! in `golden/` are regenerated with
+//!
+//! ```text
+//! cargo run -p tracetest-fixtures --target x86_64-unknown-linux-gnu
+//! ```
+//!
+//! Only output that does not depend on timing is covered. The scenarios built around
+//! timestamps, PC samples, data trace, event counters and overflow have none worth
+//! fixing in place, see `golden/README.md`.
+
+use std::fmt::Write;
+
+use tracetest::{
+    bert::{self, Lfsr, Prbs},
+    config::{ClockSource, SwoConfig},
+    exceptions::{self, ROUND},
+    formatter::{self, TRACE_IDS},
+    framing::{self, Framer},
+    hello,
+    itm::{Payload, Stimulus},
+    sweep::{Descriptor, SweepStep, BASIC_TABLE, FULL_TABLE},
+    widths,
+};
+use tracetest_decode::Decoder;
+
+/// Clock the firmware's trace runs from, the STM32F103's 8 MHz HSI
+pub const HCLK: u32 = 8_000_000;
+
+/// Ticks of the hello-world loops in their fixtures, two of each kind
+pub const HELLO_TICKS: u32 = 4;
+
+/// Sequence words before the descriptor in the BERT fixtures
+pub const BERT_WORDS: u32 = 64;
+
+/// Hardware source discriminator of exception trace packets
+const EXCEPTION_TRACE: u8 = 1;
+
+/// Encodes stimulus writes and hardware packets as they appear in the ITM stream
+#[derive(Default)]
+pub struct Encoder(Vec<u8>);
+
+impl Encoder {
+    pub fn new() -> Self {
+        Encoder(Vec::new())
+    }
+
+    fn source(&mut self, header: u8, payload: Payload) {
+        self.0.push(header | payload.size_bits());
+        self.0
+            .extend_from_slice(&payload.value().to_le_bytes()[..payload.size()]);
+    }
+
+    /// A packet from DWT source `discriminator`
+    pub fn hardware(&mut self, discriminator: u8, payload: Payload) {
+        self.source(discriminator << 3 | 0b100, payload);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Stimulus for Encoder {
+    fn write(&mut self, port: usize, payload: Payload) {
+        self.source((port as u8) << 3, payload);
+    }
+}
+
+/// The expected ITM stream of one scenario
+pub struct Fixture {
+    /// File name in `golden/`, without the extension
+    pub name: &'static str,
+    pub bytes: Vec<u8>,
+}
+
+fn fixture(name: &'static str, script: impl FnOnce(&mut Encoder)) -> Fixture {
+    let mut encoder = Encoder::new();
+    script(&mut encoder);
+    Fixture {
+        name,
+        bytes: encoder.into_bytes(),
+    }
+}
+
+/// One announcement for each step of `table`, as with the firmware's default clock
+fn sweep(out: &mut Encoder, table: &[SweepStep]) {
+    for (index, step) in table.iter().enumerate() {
+        let config = SwoConfig::builder(ClockSource::Hclk(HCLK), step.baud)
+            .protocol(step.protocol)
+            .build()
+            .expect("every sweep step is reachable from HCLK");
+        Descriptor::new(index, table.len(), &config).announce(out);
+    }
+}
+
+/// [`BERT_WORDS`] words of `prbs`, then the descriptor sent at the next tick
+fn bert(out: &mut Encoder, prbs: Prbs) {
+    let mut lfsr = Lfsr::new(prbs);
+    let mut descriptor = bert::Descriptor {
+        prbs,
+        words_sent: 0,
+    };
+    for _ in 0..BERT_WORDS {
+        bert::send_word(out, &mut lfsr, &mut descriptor);
+    }
+    descriptor.send(out);
+}
+
+/// Every fixture, in a fixed order
+pub fn all() -> Vec<Fixture> {
+    vec![
+        fixture("hello", |out| {
+            for tick in 0..HELLO_TICKS {
+                hello::send_tick(out, tick);
+            }
+        }),
+        fixture("widths", widths::send_round),
+        fixture("formatter", |out| {
+            for id in TRACE_IDS.iter() {
+                formatter::send_phase(out, *id);
+            }
+        }),
+        fixture("sweep", |out| sweep(out, BASIC_TABLE)),
+        fixture("sweep-full", |out| sweep(out, FULL_TABLE)),
+        fixture("bert-prbs7", |out| bert(out, Prbs::Prbs7)),
+        fixture("bert-prbs15", |out| bert(out, Prbs::Prbs15)),
+        fixture("bert-prbs31", |out| bert(out, Prbs::Prbs31)),
+        fixture("exceptions", |out| {
+            exceptions::describe_round(out, 0);
+            for event in ROUND.iter() {
+                out.hardware(EXCEPTION_TRACE, Payload::U16(event.payload()));
+            }
+        }),
+        fixture("framing", |out| {
+            let mut hello = Framer::new();
+            let mut counter = Framer::new();
+            for tick in 0..HELLO_TICKS {
+                framing::send_tick(out, &mut hello, &mut counter, tick);
+            }
+        }),
+    ]
+}
+
+/// The packets in `bytes`, one per line with the offset of their first byte
+pub fn listing(bytes: &[u8]) -> String {
+    let mut decoder = Decoder::new();
+    let mut text = String::new();
+    let mut start = 0;
+    for byte in bytes.iter() {
+        for item in decoder.push(*byte) {
+            match item {
+                Ok(packet) => writeln!(text, "{:>6}  {:?}", start, packet).unwrap(),
+                Err(error) => writeln!(text, "!! {}", error).unwrap(),
+            }
+            start = decoder.offset();
+        }
+    }
+    if let Some(error) = decoder.finish() {
+        writeln!(text, "!! {}", error).unwrap();
+    }
+    text
+}
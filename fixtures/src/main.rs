@@ -0,0 +1,33 @@
+//! Writes the golden traces
+//!
+//! ```text
+//! tracetest-fixtures [DIR]
+//! ```
+//!
+//! For each fixture this writes the raw ITM bytes to `NAME.bin` and the decoded packets
+//! to `NAME.txt` in `DIR`, which is this crate's `golden/` unless given.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use tracetest_fixtures::{all, listing};
+
+fn main() {
+    let dir = env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("golden"));
+    for fixture in all() {
+        let bin = dir.join(format!("{}.bin", fixture.name));
+        let txt = dir.join(format!("{}.txt", fixture.name));
+        let written =
+            fs::write(&bin, &fixture.bytes).and_then(|()| fs::write(&txt, listing(&fixture.bytes)));
+        if let Err(error) = written {
+            eprintln!("tracetest-fixtures: {}: {}", dir.display(), error);
+            process::exit(1);
+        }
+        println!("{:<12} {:>6} bytes", fixture.name, fixture.bytes.len());
+    }
+}
@@ -0,0 +1,42 @@
+//! The checked-in golden traces must match what the scenarios produce now
+
+use std::fs;
+use std::path::PathBuf;
+
+use tracetest_fixtures::{all, listing};
+
+fn golden(file: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("golden")
+        .join(file);
+    fs::read(&path).unwrap_or_else(|error| panic!("{}: {}", path.display(), error))
+}
+
+#[test]
+fn golden_traces_are_up_to_date() {
+    for fixture in all() {
+        assert!(
+            golden(&format!("{}.bin", fixture.name)) == fixture.bytes,
+            "golden/{}.bin is stale, regenerate it with `cargo run -p tracetest-fixtures`",
+            fixture.name
+        );
+        assert_eq!(
+            String::from_utf8(golden(&format!("{}.txt", fixture.name))).unwrap(),
+            listing(&fixture.bytes),
+            "golden/{}.txt is stale",
+            fixture.name
+        );
+    }
+}
+
+#[test]
+fn golden_traces_decode_cleanly() {
+    for fixture in all() {
+        assert!(!fixture.bytes.is_empty(), "{} is empty", fixture.name);
+        assert!(
+            !listing(&fixture.bytes).contains("!!"),
+            "{} has malformed packets",
+            fixture.name
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Checks a decoded stream from the firmware's `bert` scenario
+//!
+//! The payload bytes on [`BERT_PORT`] go to a [`Checker`]. Nothing is checked until the
+//! first [`Descriptor`] on [`DESCRIPTOR_PORT`] says which sequence is running, which
+//! costs at most one tick of the capture.
+
+use tracetest::{
+    bert::{Checker, Descriptor, Report, BERT_PORT, DESCRIPTOR_MAGIC, DESCRIPTOR_PORT},
+    mailbox::{Ack, ACK_PORT},
+};
+use tracetest_decode::{Packet, Payload};
+
+use crate::check::AckReader;
+
+/// What a packet was recognised as
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    /// The last word of a descriptor
+    Descriptor(Descriptor),
+    /// Part of the sequence, a descriptor or an acknowledgement, or a sync packet
+    Other,
+    /// The last word of a mailbox acknowledgement
+    Ack(Ack),
+    Unexpected(String),
+}
+
+/// Follows the BERT stream one packet at a time
+pub struct BertChecker {
+    checker: Option<Checker>,
+    /// Descriptor words received so far, starting from [`DESCRIPTOR_MAGIC`]
+    words: Vec<u32>,
+    acks: AckReader,
+}
+
+impl BertChecker {
+    pub fn new() -> Self {
+        BertChecker {
+            checker: None,
+            words: Vec::new(),
+            acks: AckReader::new(),
+        }
+    }
+
+    /// Totals of the sequence being checked, once a descriptor has named it
+    pub fn report(&self) -> Option<Report> {
+        self.checker.as_ref().map(Checker::report)
+    }
+
+    pub fn check(&mut self, packet: &Packet) -> Verdict {
+        match *packet {
+            Packet::Sync => Verdict::Other,
+            Packet::Instrumentation { port, payload } if port as usize == BERT_PORT => {
+                if let Some(checker) = self.checker.as_mut() {
+                    let (bytes, len) = payload.bytes();
+                    for byte in bytes[..len].iter() {
+                        checker.push(*byte);
+                    }
+                }
+                Verdict::Other
+            }
+            Packet::Instrumentation {
+                port,
+                payload: Payload::U32(word),
+            } if port as usize == DESCRIPTOR_PORT => self.descriptor(word),
+            Packet::Instrumentation { port, payload } if port as usize == ACK_PORT => {
+                match self.acks.push(payload) {
+                    Ok(Some(ack)) => Verdict::Ack(ack),
+                    Ok(None) => Verdict::Other,
+                    Err(message) => Verdict::Unexpected(message),
+                }
+            }
+            Packet::Instrumentation { port, payload } => Verdict::Unexpected(format!(
+                "{:?} on port {} is not part of the BERT stream",
+                payload, port
+            )),
+            other => Verdict::Unexpected(format!("unexpected packet {:?}", other)),
+        }
+    }
+
+    fn descriptor(&mut self, word: u32) -> Verdict {
+        if word == DESCRIPTOR_MAGIC {
+            self.words.clear();
+        } else if self.words.is_empty() {
+            return Verdict::Unexpected(format!(
+                "descriptor word {:#010x} without {:#010x} before it",
+                word, DESCRIPTOR_MAGIC
+            ));
+        }
+        self.words.push(word);
+        if self.words.len() < 3 {
+            return Verdict::Other;
+        }
+
+        let words = [self.words[0], self.words[1], self.words[2]];
+        self.words.clear();
+        match Descriptor::from_words(&words) {
+            Some(descriptor) => {
+                /* A different sequence starts from scratch */
+                if self.checker.as_ref().map(Checker::prbs) != Some(descriptor.prbs) {
+                    self.checker = Some(Checker::new(descriptor.prbs));
+                }
+                Verdict::Descriptor(descriptor)
+            }
+            None => Verdict::Unexpected(format!("{:?} is not a descriptor", words)),
+        }
+    }
+}
+
+impl Default for BertChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// One line summing up `report`
+pub fn summary(report: &Report) -> String {
+    format!(
+        "{} bits, {} errors (BER {:.3e}), {} slips over {} bits, {} resyncs",
+        report.bits,
+        report.errors,
+        report.ber(),
+        report.slips,
+        report.slipped_bits,
+        report.resyncs
+    )
+}
@@ -0,0 +1,234 @@
+//! Checks a decoded stream against the firmware's hello-world loop
+//!
+//! The expected packets come from `tracetest::hello` and the same [`TraceWrite`] chunking
+//! the firmware's `ItmPort`s use, so the checker follows any change to the loop.
+
+use tracetest::{
+    hello::{COUNTER_PORTS, HELLO_BYTE, HELLO_MESSAGE, HELLO_PORT},
+    itm::TraceWrite,
+    mailbox::{Ack, ACK_MAGIC, ACK_PORT},
+};
+use tracetest_decode::{Packet, Payload};
+
+/// Records the packets a run of stimulus writes would produce
+#[derive(Default)]
+struct Recorder(Vec<Payload>);
+
+impl TraceWrite for Recorder {
+    fn write_u8(&mut self, value: u8) {
+        self.0.push(Payload::U8(value));
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.0.push(Payload::U16(value));
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.0.push(Payload::U32(value));
+    }
+}
+
+/// What a packet was recognised as
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    /// Part of [`HELLO_MESSAGE`]
+    Message,
+    /// The single [`HELLO_BYTE`]
+    HelloByte,
+    /// A counter word carrying this tick
+    Counter(u32),
+    /// The last word of a mailbox acknowledgement
+    Ack(Ack),
+    /// Expected, but nothing to show, such as a sync packet
+    Other,
+    Unexpected(String),
+}
+
+/// Puts mailbox acknowledgements back together from their words on [`ACK_PORT`]
+#[derive(Default)]
+pub struct AckReader {
+    /// Words received so far, starting from [`ACK_MAGIC`]
+    words: Vec<u32>,
+}
+
+impl AckReader {
+    pub fn new() -> Self {
+        AckReader::default()
+    }
+
+    /// Take the next packet on [`ACK_PORT`], returning the acknowledgement it completes
+    pub fn push(&mut self, payload: Payload) -> Result<Option<Ack>, String> {
+        let word = match payload {
+            Payload::U32(word) => word,
+            other => {
+                return Err(format!(
+                    "expected an acknowledgement word on port {}, got {:?}",
+                    ACK_PORT, other
+                ))
+            }
+        };
+        if word == ACK_MAGIC {
+            self.words.clear();
+        } else if self.words.is_empty() {
+            return Err(format!(
+                "acknowledgement word {:#010x} without {:#010x} before it",
+                word, ACK_MAGIC
+            ));
+        }
+        self.words.push(word);
+        if self.words.len() < 3 {
+            return Ok(None);
+        }
+
+        let words = [self.words[0], self.words[1], self.words[2]];
+        self.words.clear();
+        Ack::from_words(&words)
+            .map(Some)
+            .ok_or_else(|| format!("{:?} is not an acknowledgement", words))
+    }
+}
+
+/// Follows the hello-world loop one packet at a time
+///
+/// It picks up wherever the capture starts: port [`HELLO_PORT`] locks on to the first
+/// packet that belongs to the loop, and each counter port to its first word. The
+/// mailbox's acknowledgements on [`ACK_PORT`] are accepted too.
+pub struct HelloChecker {
+    /// Every packet on [`HELLO_PORT`] over one pair of ticks
+    cycle: Vec<Payload>,
+    /// Index into `cycle` of the next packet expected
+    position: Option<usize>,
+    /// Parity of the tick the counters should be carrying, once known
+    parity: Option<u32>,
+    /// Last tick seen on each counter port
+    ticks: [Option<u32>; COUNTER_PORTS.len()],
+    acks: AckReader,
+}
+
+impl HelloChecker {
+    pub fn new() -> Self {
+        let mut recorder = Recorder::default();
+        recorder.write_all(HELLO_MESSAGE.as_bytes());
+        recorder.write_u8(HELLO_BYTE);
+        HelloChecker {
+            cycle: recorder.0,
+            position: None,
+            parity: None,
+            ticks: [None; COUNTER_PORTS.len()],
+            acks: AckReader::new(),
+        }
+    }
+
+    pub fn check(&mut self, packet: &Packet) -> Verdict {
+        match *packet {
+            Packet::Sync => Verdict::Other,
+            Packet::Instrumentation { port, payload } if port as usize == HELLO_PORT => {
+                self.hello(payload)
+            }
+            Packet::Instrumentation { port, payload } if port as usize == ACK_PORT => {
+                match self.acks.push(payload) {
+                    Ok(Some(ack)) => Verdict::Ack(ack),
+                    Ok(None) => Verdict::Other,
+                    Err(message) => Verdict::Unexpected(message),
+                }
+            }
+            Packet::Instrumentation { port, payload } => {
+                match COUNTER_PORTS.iter().position(|p| *p == port as usize) {
+                    Some(index) => self.counter(index, payload),
+                    None => Verdict::Unexpected(format!("write to unused port {}", port)),
+                }
+            }
+            other => Verdict::Unexpected(format!("unexpected packet {:?}", other)),
+        }
+    }
+
+    fn hello(&mut self, payload: Payload) -> Verdict {
+        let index = match self.position {
+            Some(position) if self.cycle[position] == payload => position,
+            expected => {
+                /* Lock on, or back on, wherever this packet fits */
+                let found = self.cycle.iter().position(|p| *p == payload);
+                self.position = found.map(|index| (index + 1) % self.cycle.len());
+                match (expected, found) {
+                    (None, Some(index)) => index,
+                    (Some(position), _) => {
+                        return Verdict::Unexpected(format!(
+                            "expected {:?} on port {}, got {:?}",
+                            self.cycle[position], HELLO_PORT, payload
+                        ))
+                    }
+                    (None, None) => {
+                        return Verdict::Unexpected(format!(
+                            "{:?} on port {} is not part of the loop",
+                            payload, HELLO_PORT
+                        ))
+                    }
+                }
+            }
+        };
+
+        self.position = Some((index + 1) % self.cycle.len());
+        if index + 1 == self.cycle.len() {
+            self.parity = Some(1);
+            Verdict::HelloByte
+        } else {
+            if index + 2 == self.cycle.len() {
+                self.parity = Some(0);
+            }
+            Verdict::Message
+        }
+    }
+
+    fn counter(&mut self, index: usize, payload: Payload) -> Verdict {
+        let port = COUNTER_PORTS[index];
+        let word = match payload {
+            Payload::U32(word) => word,
+            other => {
+                return Verdict::Unexpected(format!(
+                    "expected a counter word on port {}, got {:?}",
+                    port, other
+                ))
+            }
+        };
+        if word >> 24 != port as u32 {
+            return Verdict::Unexpected(format!(
+                "counter word {:#010x} on port {} is for port {}",
+                word,
+                port,
+                word >> 24
+            ));
+        }
+
+        let tick = word & 0x00ff_ffff;
+        let last = self.ticks[index].replace(tick);
+        if let Some(last) = last {
+            let next = (last + 1) & 0x00ff_ffff;
+            if tick != next {
+                return Verdict::Unexpected(format!(
+                    "port {} went from tick {} to {}",
+                    port, last, tick
+                ));
+            }
+        }
+        match self.parity {
+            Some(parity) if tick & 1 != parity => Verdict::Unexpected(format!(
+                "port {} sent tick {} after the {} on port {}",
+                port,
+                tick,
+                if parity == 0 {
+                    "greeting"
+                } else {
+                    "single byte"
+                },
+                HELLO_PORT
+            )),
+            _ => Verdict::Counter(tick),
+        }
+    }
+}
+
+impl Default for HelloChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,7 @@
+//! The checks behind `swodump`
+//!
+//! [`check::HelloChecker`] follows the firmware's hello-world loop and
+//! [`bert::BertChecker`] its `bert` scenario, one decoded packet at a time.
+
+pub mod bert;
+pub mod check;
@@ -0,0 +1,336 @@
+//! Reads raw SWO bytes, decodes them and prints what each stimulus port said
+//!
+//! ```text
+//! swodump [--packets] [--no-check | --bert] [SOURCE]
+//! ```
+//!
+//! `SOURCE` is one of:
+//!
+//! | source                 | reads from                                          |
+//! |------------------------|-----------------------------------------------------|
+//! | `-` or nothing         | stdin                                               |
+//! | a path                 | a capture file                                      |
+//! | `serial:PATH[@BAUD]`   | a UART adapter on the SWO pin, 4 Mbaud by default   |
+//! | `tcp:HOST:PORT`        | a probe's SWO server, such as OpenOCD's             |
+//!
+//! A UART adapter can only read NRZ. The firmware starts out sending Manchester, so
+//! switch it to NRZ at the adapter's baud rate with the mailbox's `SetBaud` command
+//! before reading from `serial:`.
+//!
+//! Text written to each stimulus port is put back together into lines. Unless
+//! `--no-check` is given, the stream is also checked against the firmware's hello-world
+//! loop: the greeting and single byte on port 0, the counters on the other ports and the
+//! mailbox's acknowledgements on port 29 are shown as such, and anything else is flagged
+//! with `!!`. `--packets` also prints every
+//! packet as it is decoded.
+//!
+//! `--bert` checks the `bert` scenario instead. Each descriptor on port 0 is shown with
+//! the totals of the bit error rate checker so far, and the final totals are printed at
+//! the end. Any bit error, slip or resync is flagged, as is a capture without a
+//! descriptor.
+//!
+//! The exit status is 1 if anything was flagged.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::process;
+use std::time::Duration;
+
+use swodump::{
+    bert::{self, BertChecker},
+    check::{HelloChecker, Verdict},
+};
+use tracetest::{bert::DESCRIPTOR_PORT, mailbox::ACK_PORT};
+use tracetest_decode::{Decoder, Packet};
+
+/// Baud rate used for serial sources that do not give one, the firmware's default
+const DEFAULT_BAUD: u32 = 4_000_000;
+
+/// Number of stimulus ports, one line buffer each
+const PORTS: usize = 32;
+
+enum Source {
+    Stdin,
+    File(String),
+    Serial { path: String, baud: u32 },
+    Tcp(String),
+}
+
+impl Source {
+    fn parse(arg: &str) -> Result<Source, String> {
+        if arg == "-" {
+            Ok(Source::Stdin)
+        } else if let Some(serial) = arg.strip_prefix("serial:") {
+            let (path, baud) = match serial.rsplit_once('@') {
+                Some((path, baud)) => (
+                    path,
+                    baud.parse()
+                        .map_err(|_| format!("bad baud rate {:?}", baud))?,
+                ),
+                None => (serial, DEFAULT_BAUD),
+            };
+            Ok(Source::Serial {
+                path: path.to_string(),
+                baud,
+            })
+        } else if let Some(address) = arg.strip_prefix("tcp:") {
+            Ok(Source::Tcp(address.to_string()))
+        } else {
+            Ok(Source::File(arg.to_string()))
+        }
+    }
+
+    fn open(&self) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Source::Stdin => Box::new(io::stdin()),
+            Source::File(path) => Box::new(File::open(path)?),
+            Source::Serial { path, baud } => Box::new(
+                serialport::new(path, *baud)
+                    .timeout(Duration::from_secs(3600))
+                    .open()?,
+            ),
+            Source::Tcp(address) => Box::new(TcpStream::connect(address)?),
+        })
+    }
+}
+
+struct Options {
+    source: Source,
+    packets: bool,
+    check: bool,
+    bert: bool,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: swodump [--packets] [--no-check | --bert] [- | PATH | serial:PATH[@BAUD] | tcp:HOST:PORT]"
+    );
+    process::exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        source: Source::Stdin,
+        packets: false,
+        check: true,
+        bert: false,
+    };
+    let mut source = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--packets" => options.packets = true,
+            "--no-check" => options.check = false,
+            "--bert" => options.bert = true,
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with("--") => usage(),
+            _ if source.is_none() => source = Some(arg),
+            _ => usage(),
+        }
+    }
+    if options.bert && !options.check {
+        eprintln!("swodump: --no-check and --bert cannot be used together");
+        usage();
+    }
+    if let Some(source) = source {
+        options.source = Source::parse(&source).unwrap_or_else(|error| {
+            eprintln!("swodump: {}", error);
+            usage()
+        });
+    }
+    options
+}
+
+/// Printable form of a byte within a line of text
+fn escape(byte: u8, out: &mut String) {
+    match byte {
+        b'\t' | b' '..=b'~' => out.push(byte as char),
+        _ => out.push_str(&format!("\\x{:02x}", byte)),
+    }
+}
+
+/// Prints everything decoded, with text put back together per port
+struct Printer<W: Write> {
+    out: W,
+    lines: Vec<String>,
+    flagged: u64,
+}
+
+impl<W: Write> Printer<W> {
+    fn new(out: W) -> Self {
+        Printer {
+            out,
+            lines: vec![String::new(); PORTS],
+            flagged: 0,
+        }
+    }
+
+    fn text(&mut self, port: u8, bytes: &[u8]) -> io::Result<()> {
+        for byte in bytes {
+            if *byte == b'\n' {
+                self.flush_line(port)?;
+            } else {
+                escape(*byte, &mut self.lines[port as usize]);
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_line(&mut self, port: u8) -> io::Result<()> {
+        let line = std::mem::take(&mut self.lines[port as usize]);
+        writeln!(self.out, "[{:2}] {}", port, line)
+    }
+
+    /// Print a line for `port` that is not part of its text, after any text so far
+    fn note(&mut self, port: u8, note: std::fmt::Arguments<'_>) -> io::Result<()> {
+        if !self.lines[port as usize].is_empty() {
+            self.flush_line(port)?;
+        }
+        writeln!(self.out, "[{:2}] {}", port, note)
+    }
+
+    fn flag(&mut self, offset: u64, message: &str) -> io::Result<()> {
+        self.flagged += 1;
+        writeln!(self.out, "!! at byte {}: {}", offset, message)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        for port in 0..PORTS as u8 {
+            if !self.lines[port as usize].is_empty() {
+                self.flush_line(port)?;
+            }
+        }
+        self.out.flush()
+    }
+}
+
+fn run(options: &Options) -> io::Result<u64> {
+    let mut input = options.source.open()?;
+    let stdout = io::stdout();
+    let mut printer = Printer::new(BufWriter::new(stdout.lock()));
+    let mut decoder = Decoder::new();
+    let mut checker = HelloChecker::new();
+    let mut bert = BertChecker::new();
+    let mut buffer = [0; 4096];
+
+    loop {
+        let len = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            /* A serial port that stays quiet for a long time just times out */
+            Err(error)
+                if error.kind() == io::ErrorKind::Interrupted
+                    || error.kind() == io::ErrorKind::TimedOut =>
+            {
+                continue
+            }
+            Err(error) => return Err(error),
+        };
+        for byte in buffer[..len].iter() {
+            for item in decoder.push(*byte) {
+                /* Offset of the byte that completed the packet */
+                let offset = decoder.offset() - 1;
+                let packet = match item {
+                    Ok(packet) => packet,
+                    Err(error) => {
+                        printer.flagged += 1;
+                        writeln!(printer.out, "!! {}", error)?;
+                        continue;
+                    }
+                };
+                if options.packets {
+                    writeln!(printer.out, "{:>10}  {:?}", offset, packet)?;
+                }
+
+                if options.bert {
+                    match bert.check(&packet) {
+                        bert::Verdict::Descriptor(descriptor) => {
+                            let report = bert.report().unwrap_or_default();
+                            printer.note(
+                                DESCRIPTOR_PORT as u8,
+                                format_args!(
+                                    "{:?}, {} words sent: {}",
+                                    descriptor.prbs,
+                                    descriptor.words_sent,
+                                    bert::summary(&report)
+                                ),
+                            )?
+                        }
+                        bert::Verdict::Ack(ack) => printer.note(
+                            ACK_PORT as u8,
+                            format_args!("ack {} {:?}", ack.seq, ack.status),
+                        )?,
+                        bert::Verdict::Unexpected(message) => printer.flag(offset, &message)?,
+                        bert::Verdict::Other => {}
+                    }
+                    continue;
+                }
+
+                let verdict = if options.check {
+                    checker.check(&packet)
+                } else {
+                    Verdict::Other
+                };
+                match (packet, verdict) {
+                    (Packet::Instrumentation { port, payload }, Verdict::HelloByte) => {
+                        printer.note(port, format_args!("{:#04x}", payload.value()))?
+                    }
+                    (Packet::Instrumentation { port, .. }, Verdict::Counter(tick)) => {
+                        printer.note(port, format_args!("tick {}", tick))?
+                    }
+                    (Packet::Instrumentation { port, .. }, Verdict::Ack(ack)) => {
+                        printer.note(port, format_args!("ack {} {:?}", ack.seq, ack.status))?
+                    }
+                    /* Words of an acknowledgement before its last one */
+                    (Packet::Instrumentation { port, .. }, Verdict::Other)
+                        if port as usize == ACK_PORT => {}
+                    (Packet::Instrumentation { port, payload }, verdict) => {
+                        let (bytes, len) = payload.bytes();
+                        printer.text(port, &bytes[..len])?;
+                        if let Verdict::Unexpected(message) = verdict {
+                            printer.flag(offset, &message)?;
+                        }
+                    }
+                    (_, Verdict::Unexpected(message)) => printer.flag(offset, &message)?,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let end = decoder.offset();
+    if let Some(error) = decoder.finish() {
+        printer.flagged += 1;
+        writeln!(printer.out, "!! {}", error)?;
+    }
+    if options.bert {
+        match bert.report() {
+            Some(report) => {
+                writeln!(printer.out, "BERT: {}", bert::summary(&report))?;
+                if report.errors > 0 || report.slips > 0 || report.resyncs > 0 {
+                    printer.flag(end, "the BERT stream was damaged")?;
+                }
+            }
+            None => printer.flag(end, "no BERT descriptor in the capture")?,
+        }
+    }
+    printer.finish()?;
+    Ok(printer.flagged)
+}
+
+fn main() {
+    let options = parse_args();
+    match run(&options) {
+        Ok(0) => {}
+        Ok(flagged) => {
+            eprintln!("swodump: {} unexpected packets or errors", flagged);
+            process::exit(1);
+        }
+        Err(error) if error.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(error) => {
+            eprintln!("swodump: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! The hello-world checker against the firmware's own loop, whole and damaged
+
+use swodump::check::{HelloChecker, Verdict};
+use tracetest::{
+    hello::{self, counter_word, COUNTER_PORTS},
+    itm::{self, Stimulus},
+    mailbox::{Ack, Status, ACK_PORT},
+};
+use tracetest_decode::{Packet, Payload};
+
+/// The packets of a run of stimulus writes
+#[derive(Default)]
+struct Packets(Vec<Packet>);
+
+impl Stimulus for Packets {
+    fn write(&mut self, port: usize, payload: itm::Payload) {
+        let payload = match payload {
+            itm::Payload::U8(value) => Payload::U8(value),
+            itm::Payload::U16(value) => Payload::U16(value),
+            itm::Payload::U32(value) => Payload::U32(value),
+        };
+        self.0.push(Packet::Instrumentation {
+            port: port as u8,
+            payload,
+        });
+    }
+}
+
+/// The packets of ticks `0..ticks` of the loop
+fn ticks(ticks: u32) -> Vec<Packet> {
+    let mut packets = Packets::default();
+    for tick in 0..ticks {
+        hello::send_tick(&mut packets, tick);
+    }
+    packets.0
+}
+
+/// Messages of every packet flagged
+fn unexpected(packets: &[Packet]) -> Vec<String> {
+    let mut checker = HelloChecker::new();
+    packets
+        .iter()
+        .filter_map(|packet| match checker.check(packet) {
+            Verdict::Unexpected(message) => Some(message),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_counter(packet: &Packet, port: usize, tick: u32) -> bool {
+    *packet
+        == Packet::Instrumentation {
+            port: port as u8,
+            payload: Payload::U32(counter_word(port, tick)),
+        }
+}
+
+#[test]
+fn clean_loop() {
+    let packets = ticks(6);
+    let mut checker = HelloChecker::new();
+    let verdicts: Vec<_> = packets.iter().map(|packet| checker.check(packet)).collect();
+    assert!(verdicts
+        .iter()
+        .all(|v| !matches!(v, Verdict::Unexpected(_))));
+    assert_eq!(
+        verdicts
+            .iter()
+            .filter(|v| **v == Verdict::HelloByte)
+            .count(),
+        3
+    );
+    assert!(verdicts.contains(&Verdict::Counter(5)));
+}
+
+#[test]
+fn capture_starting_mid_greeting() {
+    let packets = ticks(6);
+    for start in 1..6 {
+        assert_eq!(
+            unexpected(&packets[start..]),
+            Vec::<String>::new(),
+            "{}",
+            start
+        );
+    }
+}
+
+#[test]
+fn dropped_counter_word() {
+    let mut packets = ticks(6);
+    let port = COUNTER_PORTS[2];
+    let dropped = packets
+        .iter()
+        .position(|packet| is_counter(packet, port, 3))
+        .unwrap();
+    packets.remove(dropped);
+    let flagged = unexpected(&packets);
+    assert_eq!(flagged.len(), 1, "{:?}", flagged);
+    assert!(
+        flagged[0].contains(&format!("port {} went from tick 2 to 4", port)),
+        "{}",
+        flagged[0]
+    );
+}
+
+#[test]
+fn tick_parity_mismatch() {
+    /* Counters one tick ahead of the greeting and single byte */
+    let mut packets = Packets::default();
+    for tick in 0..4 {
+        hello::send_tick(&mut packets, tick);
+    }
+    for packet in packets.0.iter_mut() {
+        if let Packet::Instrumentation {
+            port,
+            payload: Payload::U32(word),
+        } = packet
+        {
+            if COUNTER_PORTS.contains(&(*port as usize)) {
+                *word = counter_word(*port as usize, (*word & 0x00ff_ffff) + 1);
+            }
+        }
+    }
+    let flagged = unexpected(&packets.0);
+    assert_eq!(flagged.len(), 4 * COUNTER_PORTS.len());
+    assert!(
+        flagged.iter().all(|message| message.contains("after the")),
+        "{:?}",
+        flagged
+    );
+}
+
+#[test]
+fn mailbox_acks() {
+    let mut packets = ticks(2);
+    let ack = Ack {
+        seq: 1,
+        status: Status::Ok,
+    };
+    let words = ack.words().map(|word| Packet::Instrumentation {
+        port: ACK_PORT as u8,
+        payload: Payload::U32(word),
+    });
+    packets.splice(3..3, words);
+
+    let mut checker = HelloChecker::new();
+    let verdicts: Vec<_> = packets.iter().map(|packet| checker.check(packet)).collect();
+    assert!(verdicts.contains(&Verdict::Ack(ack)));
+    assert!(verdicts
+        .iter()
+        .all(|v| !matches!(v, Verdict::Unexpected(_))));
+
+    /* Anything else on the port is still flagged */
+    let stray = Packet::Instrumentation {
+        port: ACK_PORT as u8,
+        payload: Payload::U32(5),
+    };
+    assert!(matches!(checker.check(&stray), Verdict::Unexpected(_)));
+}
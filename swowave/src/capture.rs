@@ -0,0 +1,82 @@
+//! A single logic signal, as loaded from a capture file
+
+/// Levels of one signal over the length of a capture, kept as the times it changes
+pub struct Signal {
+    /// Length of one time unit, in seconds
+    pub timescale: f64,
+    /// Level at the start of the capture
+    pub initial: bool,
+    /// Times at which the level changes, in ascending order
+    pub edges: Vec<u64>,
+    /// Time at which the capture ends
+    pub end: u64,
+}
+
+impl Signal {
+    /// Build a signal from its level at a series of times, in ascending order
+    pub fn from_levels(timescale: f64, levels: impl IntoIterator<Item = (u64, bool)>) -> Self {
+        let mut signal = Signal {
+            timescale,
+            initial: false,
+            edges: Vec::new(),
+            end: 0,
+        };
+        let mut current = None;
+        for (time, level) in levels {
+            match current {
+                None => signal.initial = level,
+                Some(current) if current != level => signal.edges.push(time),
+                Some(_) => {}
+            }
+            current = Some(level);
+            signal.end = time;
+        }
+        signal
+    }
+
+    /// Level just after edge `index`
+    fn level_after(&self, index: usize) -> bool {
+        self.initial ^ (index & 1 == 0)
+    }
+
+    /// Level at `time`
+    pub fn level_at(&self, time: f64) -> bool {
+        let passed = self.edges.partition_point(|edge| *edge as f64 <= time);
+        self.initial ^ (passed % 2 == 1)
+    }
+
+    /// Index of the first edge at or after `time`
+    pub fn edge_index_after(&self, time: f64) -> usize {
+        self.edges.partition_point(|edge| (*edge as f64) < time)
+    }
+
+    /// Index of the first edge at or after `time` that goes to `level`
+    pub fn next_edge_to(&self, time: f64, level: bool) -> Option<usize> {
+        (self.edge_index_after(time)..self.edges.len())
+            .find(|index| self.level_after(*index) == level)
+    }
+
+    /// The edge closest to `time`, if there is one within `window` of it
+    pub fn edge_near(&self, time: f64, window: f64) -> Option<f64> {
+        let index = self.edge_index_after(time - window);
+        self.edges
+            .get(index)
+            .map(|edge| *edge as f64)
+            .filter(|edge| (edge - time).abs() <= window)
+    }
+
+    /// Every complete stretch at one level, as the level and its length. The stretches
+    /// before the first edge and after the last are cut short by the capture, so they
+    /// are left out.
+    pub fn runs(&self) -> impl Iterator<Item = (bool, u64)> + '_ {
+        self.edges
+            .windows(2)
+            .enumerate()
+            .map(move |(index, pair)| (self.level_after(index), pair[1] - pair[0]))
+    }
+
+    /// Length of the capture in seconds
+    pub fn duration(&self) -> f64 {
+        self.end as f64 * self.timescale
+    }
+}
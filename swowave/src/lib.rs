@@ -0,0 +1,9 @@
+//! Capture loading and line decoding behind `swowave`
+//!
+//! [`vcd`] and [`sigrok`] load one [`capture::Signal`] from a capture file, and
+//! [`line`] measures its timing and recovers the bytes.
+
+pub mod capture;
+pub mod line;
+pub mod sigrok;
+pub mod vcd;
@@ -0,0 +1,224 @@
+//! Timing measurement and line decoding for the two SWO protocols
+//!
+//! NRZ is framed like a UART: the line idles high, and each byte is a low start bit,
+//! eight data bits starting with bit 0, and a high stop bit.
+//!
+//! With Manchester the line idles low. Every bit period has a transition in the middle:
+//! a 1 is high then low, a 0 is low then high. A frame is a 1 start bit followed by
+//! data bytes, bit 0 first, back to back, and ends when a bit period has no transition
+//! in the middle.
+
+use tracetest::swo::SwoProtocol;
+
+use crate::capture::Signal;
+
+/// Shortest interval the line code uses, as a fraction of a bit period
+fn unit_fraction(protocol: SwoProtocol) -> f64 {
+    match protocol {
+        SwoProtocol::Manchester => 0.5,
+        SwoProtocol::Nrz => 1.0,
+    }
+}
+
+/// Longest run the line code makes while sending data, in units. Anything longer is
+/// idle time.
+fn max_units(protocol: SwoProtocol) -> u64 {
+    match protocol {
+        SwoProtocol::Manchester => 2,
+        /* A zero byte: start bit and eight data bits */
+        SwoProtocol::Nrz => 9,
+    }
+}
+
+/// Line timing measured from a capture, in seconds
+#[derive(Clone, Copy, Debug)]
+pub struct Timing {
+    pub bit_period: f64,
+    /// The shortest interval of the line code: a whole bit period for NRZ, half of one
+    /// for Manchester
+    pub unit: f64,
+    /// Root mean square difference between each run and a whole number of units
+    pub jitter_rms: f64,
+    /// Largest such difference
+    pub jitter_peak: f64,
+    /// Share of each one-unit high and low pair spent high
+    pub duty_cycle: f64,
+    /// Runs the figures are based on
+    pub runs: usize,
+}
+
+/// Measure the line timing, starting from the bit period it is meant to have
+///
+/// Every run between two edges is rounded to a whole number of units. The unit is then
+/// taken as the average over all runs short enough to be data rather than idle, and the
+/// rounding repeated with that. The configured rate only has to be within about a
+/// quarter of the real one.
+pub fn measure(signal: &Signal, protocol: SwoProtocol, bit_period: f64) -> Option<Timing> {
+    let mut unit = bit_period * unit_fraction(protocol) / signal.timescale;
+    let classify = |unit: f64| {
+        signal.runs().filter_map(move |(level, length)| {
+            let units = (length as f64 / unit).round() as u64;
+            if (1..=max_units(protocol)).contains(&units) {
+                Some((level, length as f64, units))
+            } else {
+                None
+            }
+        })
+    };
+
+    for _ in 0..3 {
+        let (length, units) = classify(unit).fold((0.0, 0), |(length, units), run| {
+            (length + run.1, units + run.2)
+        });
+        if units == 0 {
+            return None;
+        }
+        unit = length / units as f64;
+    }
+
+    let mut runs = 0;
+    let mut square_sum = 0.0;
+    let mut peak = 0.0f64;
+    let (mut high, mut high_count, mut low, mut low_count) = (0.0, 0, 0.0, 0);
+    for (level, length, units) in classify(unit) {
+        let error = length - units as f64 * unit;
+        runs += 1;
+        square_sum += error * error;
+        peak = peak.max(error.abs());
+        if units == 1 {
+            if level {
+                high += length;
+                high_count += 1;
+            } else {
+                low += length;
+                low_count += 1;
+            }
+        }
+    }
+    let duty_cycle = if high_count > 0 && low_count > 0 {
+        let high = high / high_count as f64;
+        let low = low / low_count as f64;
+        high / (high + low)
+    } else {
+        0.5
+    };
+
+    Some(Timing {
+        bit_period: unit / unit_fraction(protocol) * signal.timescale,
+        unit: unit * signal.timescale,
+        jitter_rms: (square_sum / runs as f64).sqrt() * signal.timescale,
+        jitter_peak: peak * signal.timescale,
+        duty_cycle,
+        runs,
+    })
+}
+
+/// Something wrong with the line, found while decoding
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LineError {
+    /// NRZ stop bit was low
+    Framing,
+    /// A Manchester frame ended part way through a byte, after this many bits
+    PartialByte(u8),
+}
+
+/// A line error, `time` seconds into the capture
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimedError {
+    pub time: f64,
+    pub error: LineError,
+}
+
+/// Bytes recovered from a capture
+#[derive(Default)]
+pub struct Decoded {
+    pub bytes: Vec<u8>,
+    pub errors: Vec<TimedError>,
+}
+
+/// Recover the bytes sent with `protocol`, given the measured bit period in seconds
+pub fn decode(signal: &Signal, protocol: SwoProtocol, bit_period: f64) -> Decoded {
+    let period = bit_period / signal.timescale;
+    match protocol {
+        SwoProtocol::Nrz => decode_nrz(signal, period),
+        SwoProtocol::Manchester => decode_manchester(signal, period),
+    }
+}
+
+fn decode_nrz(signal: &Signal, period: f64) -> Decoded {
+    let mut decoded = Decoded::default();
+    let mut from = 0.0;
+    while let Some(index) = signal.next_edge_to(from, false) {
+        let start = signal.edges[index] as f64;
+        let stop = start + 9.5 * period;
+        if stop > signal.end as f64 {
+            break;
+        }
+        /* A glitch rather than a start bit */
+        if signal.level_at(start + 0.5 * period) {
+            from = start + 1.0;
+            continue;
+        }
+
+        let byte = (0..8).fold(0u8, |byte, bit| {
+            let level = signal.level_at(start + (1.5 + bit as f64) * period);
+            byte | (level as u8) << bit
+        });
+        if signal.level_at(stop) {
+            decoded.bytes.push(byte);
+        } else {
+            decoded.errors.push(TimedError {
+                time: start * signal.timescale,
+                error: LineError::Framing,
+            });
+        }
+        from = stop;
+    }
+    decoded
+}
+
+fn decode_manchester(signal: &Signal, period: f64) -> Decoded {
+    let mut decoded = Decoded::default();
+    let mut from = 0.0;
+    while let Some(index) = signal.next_edge_to(from, true) {
+        /* The start bit's rising edge begins its bit period */
+        let mut cell = signal.edges[index] as f64;
+        if !signal.level_at(cell + 0.25 * period) || signal.level_at(cell + 0.75 * period) {
+            from = cell + 1.0;
+            continue;
+        }
+
+        let mut byte = 0u8;
+        let mut bits = 0u8;
+        loop {
+            /* Follow the mid-bit transitions so that clock drift does not add up */
+            if let Some(middle) = signal.edge_near(cell + 0.5 * period, 0.25 * period) {
+                cell = middle - 0.5 * period;
+            }
+            cell += period;
+            if cell + period > signal.end as f64 {
+                break;
+            }
+            let first = signal.level_at(cell + 0.25 * period);
+            let second = signal.level_at(cell + 0.75 * period);
+            if first == second {
+                break;
+            }
+            byte |= (first as u8) << bits;
+            bits += 1;
+            if bits == 8 {
+                decoded.bytes.push(byte);
+                byte = 0;
+                bits = 0;
+            }
+        }
+        if bits != 0 {
+            decoded.errors.push(TimedError {
+                time: cell * signal.timescale,
+                error: LineError::PartialByte(bits),
+            });
+        }
+        from = cell + 0.5 * period;
+    }
+    decoded
+}
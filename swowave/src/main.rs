@@ -0,0 +1,208 @@
+//! Recovers SWO bytes from a logic analyzer capture of the SWO pin
+//!
+//! ```text
+//! swowave [--nrz] [--baud BAUD] [--signal NAME] [--output FILE] [--packets] CAPTURE
+//! ```
+//!
+//! `CAPTURE` is a VCD file (`.vcd`) or a sigrok session (`.sr`). The signal is the one
+//! called `NAME`, or the first one in the file. The line is decoded as Manchester unless
+//! `--nrz` is given, as chosen by `SwoProtocol` in the firmware.
+//!
+//! Before decoding, the real bit period is measured from the capture and compared with
+//! the one expected at `BAUD`, 4 Mbaud unless given, along with the jitter of the edges
+//! and the duty cycle. The recovered bytes then go through the packet decoder, which
+//! prints how many packets it found, or every packet with `--packets`. `--output` also
+//! saves the bytes, for example to look at with `swodump`. The exit status is 1 if the
+//! line or the packets had errors.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+use tracetest::swo::SwoProtocol;
+use tracetest_decode::Decoder;
+
+use swowave::{
+    capture::Signal,
+    line::{self, Timing},
+    sigrok, vcd,
+};
+
+/// Baud rate expected when none is given, the firmware's default
+const DEFAULT_BAUD: u32 = 4_000_000;
+
+/// Fewest samples per unit of the line code that can be decoded reliably
+const MIN_SAMPLES_PER_UNIT: f64 = 4.0;
+
+struct Options {
+    capture: String,
+    protocol: SwoProtocol,
+    baud: u32,
+    signal: Option<String>,
+    output: Option<String>,
+    packets: bool,
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: swowave [--nrz] [--baud BAUD] [--signal NAME] [--output FILE] [--packets] CAPTURE"
+    );
+    process::exit(2);
+}
+
+fn parse_args() -> Options {
+    let mut capture = None;
+    let mut options = Options {
+        capture: String::new(),
+        protocol: SwoProtocol::Manchester,
+        baud: DEFAULT_BAUD,
+        signal: None,
+        output: None,
+        packets: false,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--nrz" => options.protocol = SwoProtocol::Nrz,
+            "--packets" => options.packets = true,
+            "--baud" => {
+                options.baud = match args.next().and_then(|baud| baud.parse().ok()) {
+                    Some(baud) if baud > 0 => baud,
+                    _ => usage(),
+                }
+            }
+            "--signal" => options.signal = Some(args.next().unwrap_or_else(|| usage())),
+            "--output" => options.output = Some(args.next().unwrap_or_else(|| usage())),
+            _ if arg.starts_with("--") => usage(),
+            _ if capture.is_none() => capture = Some(arg),
+            _ => usage(),
+        }
+    }
+    options.capture = capture.unwrap_or_else(|| usage());
+    options
+}
+
+fn load(options: &Options) -> Result<Signal, String> {
+    let path = Path::new(&options.capture);
+    let name = options.signal.as_deref();
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("vcd") => {
+            let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+            vcd::load(&text, name)
+        }
+        Some("sr") => sigrok::load(path, name),
+        _ => Err("captures must be .vcd or .sr files".to_string()),
+    }
+}
+
+/// A time in seconds, in the most readable unit
+fn time(seconds: f64) -> String {
+    match seconds.abs() {
+        t if t >= 1.0 => format!("{:.3} s", seconds),
+        t if t >= 1e-3 => format!("{:.3} ms", seconds * 1e3),
+        t if t >= 1e-6 => format!("{:.3} us", seconds * 1e6),
+        _ => format!("{:.1} ns", seconds * 1e9),
+    }
+}
+
+fn report_timing(options: &Options, timing: &Timing) {
+    let expected = 1.0 / options.baud as f64;
+    println!(
+        "measured:   {:.0} baud ({} per bit, {:+.2}% from expected)",
+        1.0 / timing.bit_period,
+        time(timing.bit_period),
+        (expected / timing.bit_period - 1.0) * 100.0
+    );
+    println!(
+        "jitter:     {} RMS, {} peak ({:.1}% of a {}), over {} runs",
+        time(timing.jitter_rms),
+        time(timing.jitter_peak),
+        timing.jitter_peak / timing.unit * 100.0,
+        match options.protocol {
+            SwoProtocol::Manchester => "half bit",
+            SwoProtocol::Nrz => "bit",
+        },
+        timing.runs
+    );
+    println!("duty cycle: {:.1}% high", timing.duty_cycle * 100.0);
+}
+
+fn run(options: &Options) -> Result<u64, String> {
+    let signal = load(options)?;
+    println!(
+        "capture:    {} edges over {}, resolution {}",
+        signal.edges.len(),
+        time(signal.duration()),
+        time(signal.timescale)
+    );
+    println!(
+        "expected:   {:?} at {} baud ({} per bit)",
+        options.protocol,
+        options.baud,
+        time(1.0 / options.baud as f64)
+    );
+
+    let timing = line::measure(&signal, options.protocol, 1.0 / options.baud as f64)
+        .ok_or("no edges at anything like the expected rate")?;
+    report_timing(options, &timing);
+    if timing.unit / signal.timescale < MIN_SAMPLES_PER_UNIT {
+        println!(
+            "warning:    only {:.1} samples per {}, decoding may be unreliable",
+            timing.unit / signal.timescale,
+            time(timing.unit)
+        );
+    }
+
+    let decoded = line::decode(&signal, options.protocol, timing.bit_period);
+    println!(
+        "line:       {} bytes, {} errors",
+        decoded.bytes.len(),
+        decoded.errors.len()
+    );
+    for error in decoded.errors.iter() {
+        println!("!! at {}: {:?}", time(error.time), error.error);
+    }
+    if let Some(output) = options.output.as_ref() {
+        fs::write(output, &decoded.bytes).map_err(|error| format!("{}: {}", output, error))?;
+    }
+
+    let mut decoder = Decoder::new();
+    let mut packets = 0;
+    let mut errors = 0;
+    for byte in decoded.bytes.iter() {
+        for item in decoder.push(*byte) {
+            match item {
+                Ok(packet) => {
+                    packets += 1;
+                    if options.packets {
+                        println!("{:>10}  {:?}", decoder.offset() - 1, packet);
+                    }
+                }
+                Err(error) => {
+                    errors += 1;
+                    println!("!! {}", error);
+                }
+            }
+        }
+    }
+    if let Some(error) = decoder.finish() {
+        errors += 1;
+        println!("!! {}", error);
+    }
+    println!("packets:    {} decoded, {} errors", packets, errors);
+
+    Ok(decoded.errors.len() as u64 + errors)
+}
+
+fn main() {
+    let options = parse_args();
+    match run(&options) {
+        Ok(0) => {}
+        Ok(_) => process::exit(1),
+        Err(error) => {
+            eprintln!("swowave: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! sigrok session files, as saved by PulseView and `sigrok-cli -o capture.sr`
+//!
+//! A session file is a zip archive. Its `metadata` file says the sample rate, how many
+//! bytes each sample takes and what each channel is called. The samples themselves are
+//! in `logic-1-1`, `logic-1-2` and so on, one bit per channel.
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use zip::ZipArchive;
+
+use crate::capture::Signal;
+
+/// Samples per second from a value such as `24 MHz`
+fn parse_samplerate(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("bad sample rate {:?}", text))?;
+    let unit = match unit.trim() {
+        "" | "Hz" => 1.0,
+        "kHz" => 1e3,
+        "MHz" => 1e6,
+        "GHz" => 1e9,
+        other => return Err(format!("unknown sample rate unit {:?}", other)),
+    };
+    Ok(number * unit)
+}
+
+/// Load the channel called `name` from a session file, or the first channel if no name
+/// is given
+pub fn load(path: &Path, name: Option<&str>) -> Result<Signal, String> {
+    let file = File::open(path).map_err(|error| error.to_string())?;
+    let mut archive = ZipArchive::new(file).map_err(|error| error.to_string())?;
+
+    let mut metadata = String::new();
+    archive
+        .by_name("metadata")
+        .map_err(|_| "no metadata in session file".to_string())?
+        .read_to_string(&mut metadata)
+        .map_err(|error| error.to_string())?;
+
+    let mut samplerate = None;
+    let mut unitsize = 1;
+    let mut capturefile = "logic-1".to_string();
+    let mut channel = if name.is_none() { Some(0) } else { None };
+    let mut in_device = false;
+    for line in metadata.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_device = line == "[device 1]";
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) if in_device => (key.trim(), value.trim()),
+            _ => continue,
+        };
+        match key {
+            "samplerate" => samplerate = Some(parse_samplerate(value)?),
+            "unitsize" => unitsize = value.parse().map_err(|_| "bad unitsize")?,
+            "capturefile" => capturefile = value.to_string(),
+            _ => {
+                /* Channels are `probe1`, `probe2` and so on, numbered from 1 */
+                if let Some(number) = key.strip_prefix("probe") {
+                    if Some(value) == name {
+                        channel = number.parse::<usize>().ok().map(|n| n - 1);
+                    }
+                }
+            }
+        }
+    }
+    let samplerate = samplerate.ok_or("no sample rate in session metadata")?;
+    let channel = channel.ok_or_else(|| format!("no channel called {:?}", name.unwrap_or("")))?;
+    if channel >= unitsize * 8 {
+        return Err(format!("channel {} is outside the samples", channel + 1));
+    }
+
+    /* Newer files split the samples into numbered chunks */
+    let mut data = Vec::new();
+    if let Ok(mut file) = archive.by_name(&capturefile) {
+        file.read_to_end(&mut data)
+            .map_err(|error| error.to_string())?;
+    }
+    for chunk in 1.. {
+        match archive.by_name(&format!("{}-{}", capturefile, chunk)) {
+            Ok(mut file) => file
+                .read_to_end(&mut data)
+                .map_err(|error| error.to_string())?,
+            Err(_) => break,
+        };
+    }
+    if data.is_empty() {
+        return Err(format!("no samples in {}", capturefile));
+    }
+
+    let byte = channel / 8;
+    let mask = 1 << (channel % 8);
+    let levels = data
+        .chunks_exact(unitsize)
+        .enumerate()
+        .map(|(n, sample)| (n as u64, sample[byte] & mask != 0));
+    Ok(Signal::from_levels(1.0 / samplerate, levels))
+}
@@ -0,0 +1,109 @@
+//! Value change dump files, as written by most logic analyzer software
+
+use crate::capture::Signal;
+
+/// Seconds in one unit of a `$timescale` such as `1ns` or `10 us`
+fn parse_timescale(text: &str) -> Result<f64, String> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("timescale {:?} has no unit", text))?;
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("bad timescale {:?}", text))?;
+    let unit = match unit.trim() {
+        "s" => 1.0,
+        "ms" => 1e-3,
+        "us" => 1e-6,
+        "ns" => 1e-9,
+        "ps" => 1e-12,
+        "fs" => 1e-15,
+        other => return Err(format!("unknown timescale unit {:?}", other)),
+    };
+    Ok(number * unit)
+}
+
+struct Var<'a> {
+    size: u32,
+    id: &'a str,
+    name: &'a str,
+}
+
+/// Load the signal called `name` from a VCD file, or the first 1-bit signal if no name
+/// is given
+pub fn load(text: &str, name: Option<&str>) -> Result<Signal, String> {
+    let mut tokens = text.split_whitespace();
+    let mut timescale = 1e-9;
+    let mut vars = Vec::new();
+    let mut id = None;
+    let mut time = 0u64;
+    let mut levels = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "$timescale" => {
+                let words: Vec<&str> = tokens.by_ref().take_while(|t| *t != "$end").collect();
+                timescale = parse_timescale(&words.concat())?;
+            }
+            "$var" => {
+                let words: Vec<&str> = tokens.by_ref().take_while(|t| *t != "$end").collect();
+                if words.len() < 4 {
+                    return Err(format!("short $var declaration {:?}", words.join(" ")));
+                }
+                vars.push(Var {
+                    size: words[1].parse().unwrap_or(0),
+                    id: words[2],
+                    name: words[3],
+                });
+            }
+            "$enddefinitions" => {
+                tokens.by_ref().take_while(|t| *t != "$end").for_each(drop);
+                let var = match name {
+                    Some(name) => vars.iter().find(|var| var.name == name),
+                    None => vars.iter().find(|var| var.size == 1),
+                };
+                id = Some(var.map(|var| var.id).ok_or_else(|| match name {
+                    Some(name) => format!("no signal called {:?}", name),
+                    None => "no 1-bit signal".to_string(),
+                })?);
+            }
+            /* Blocks that hold nothing needed here */
+            "$comment" | "$date" | "$version" | "$scope" | "$upscope" => {
+                tokens.by_ref().take_while(|t| *t != "$end").for_each(drop);
+            }
+            /* The value changes inside these are read like any others */
+            "$dumpvars" | "$dumpall" | "$dumpon" | "$dumpoff" | "$end" => {}
+            _ if token.starts_with('#') => {
+                time = token[1..]
+                    .parse()
+                    .map_err(|_| format!("bad time {:?}", token))?;
+            }
+            _ if token.starts_with(['b', 'B', 'r', 'R']) => {
+                let target = tokens.next().ok_or("value change without an identifier")?;
+                if Some(target) == id {
+                    levels.push((time, token[1..].ends_with('1')));
+                }
+            }
+            _ => {
+                let (value, target) = token.split_at(1);
+                if Some(target) == id {
+                    match value {
+                        "0" => levels.push((time, false)),
+                        "1" => levels.push((time, true)),
+                        /* Unknown and high-impedance keep the last level */
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    if id.is_none() {
+        return Err("no $enddefinitions".to_string());
+    }
+    if levels.is_empty() {
+        return Err("the signal never has a value".to_string());
+    }
+    levels.push((time, levels[levels.len() - 1].1));
+    Ok(Signal::from_levels(timescale, levels))
+}
@@ -0,0 +1,124 @@
+//! Small VCD files and sigrok sessions loaded into a `Signal`
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+
+use swowave::{sigrok, vcd};
+use zip::{write::FileOptions, ZipWriter};
+
+const VCD: &str = "\
+$date today $end
+$version a logic analyzer $end
+$timescale 10 ns $end
+$scope module top $end
+$var wire 8 # bus $end
+$var wire 1 ! clk $end
+$var wire 1 % swo $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+b00000000 #
+0!
+1%
+$end
+#3
+1!
+0%
+b00000001 #
+#5
+x%
+0!
+#7
+1%
+#9
+1%
+#12
+";
+
+#[test]
+fn vcd_signals() {
+    /* The first 1-bit signal, unless another is named */
+    let clk = vcd::load(VCD, None).unwrap();
+    assert_eq!(clk.timescale, 10e-9);
+    assert!(!clk.initial);
+    assert_eq!(clk.edges, vec![3, 5]);
+    assert_eq!(clk.end, 12);
+
+    /* Unknown keeps the last level, and a change to the same level is no edge */
+    let swo = vcd::load(VCD, Some("swo")).unwrap();
+    assert!(swo.initial);
+    assert_eq!(swo.edges, vec![3, 7]);
+    assert_eq!(swo.end, 12);
+    assert!(!swo.level_at(6.0) && swo.level_at(8.0));
+    assert!((swo.duration() - 120e-9).abs() < 1e-15);
+}
+
+#[test]
+fn vcd_errors() {
+    assert_eq!(
+        vcd::load(VCD, Some("data")).err(),
+        Some("no signal called \"data\"".to_string())
+    );
+    let bad_unit = VCD.replace("10 ns", "10 xs");
+    assert!(vcd::load(&bad_unit, None).is_err());
+    let no_changes = VCD.split("#0").next().unwrap();
+    assert!(vcd::load(no_changes, None).is_err());
+    assert!(vcd::load("#0 1!", None).is_err());
+}
+
+/// A session file holding `samples` on two channels, `swo` and `clk`, at 2 MHz
+fn session(name: &str, samples: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("swowave-{}-{}.sr", process::id(), name));
+    let mut zip = ZipWriter::new(File::create(&path).unwrap());
+    let options = FileOptions::default();
+    zip.start_file("version", options).unwrap();
+    zip.write_all(b"2").unwrap();
+    zip.start_file("metadata", options).unwrap();
+    zip.write_all(
+        b"[global]\n\
+          sigrok version=0.5.2\n\
+          \n\
+          [device 1]\n\
+          capturefile=logic-1\n\
+          total probes=2\n\
+          samplerate=2 MHz\n\
+          total analog=0\n\
+          probe1=swo\n\
+          probe2=clk\n\
+          unitsize=1\n",
+    )
+    .unwrap();
+    /* Split over two chunks, as newer versions save them */
+    let (first, second) = samples.split_at(samples.len() / 2);
+    zip.start_file("logic-1-1", options).unwrap();
+    zip.write_all(first).unwrap();
+    zip.start_file("logic-1-2", options).unwrap();
+    zip.write_all(second).unwrap();
+    zip.finish().unwrap();
+    path
+}
+
+#[test]
+fn sigrok_channels() {
+    let samples = [0b00, 0b11, 0b10, 0b01, 0b01, 0b11, 0b00, 0b10];
+    let path = session("channels", &samples);
+
+    let swo = sigrok::load(&path, None).unwrap();
+    assert_eq!(swo.timescale, 0.5e-6);
+    assert!(!swo.initial);
+    assert_eq!(swo.edges, vec![1, 2, 3, 6]);
+    assert_eq!(swo.end, 7);
+
+    let clk = sigrok::load(&path, Some("clk")).unwrap();
+    assert!(!clk.initial);
+    assert_eq!(clk.edges, vec![1, 3, 5, 6, 7]);
+
+    assert_eq!(
+        sigrok::load(&path, Some("data")).err(),
+        Some("no channel called \"data\"".to_string())
+    );
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,164 @@
+//! Bytes encoded onto a line with a known clock error and jitter, then measured and
+//! decoded again
+
+use swowave::{
+    capture::Signal,
+    line::{self, LineError},
+};
+use tracetest::swo::SwoProtocol;
+
+/// Length of one time unit of the made up captures, in seconds
+const TIMESCALE: f64 = 100e-12;
+
+/// Bit period the line is configured for: 237 ns, a little over 4.2 Mbaud
+const BIT_PERIOD: f64 = 237e-9;
+
+fn test_bytes() -> Vec<u8> {
+    let mut bytes = vec![0x00, 0xff, 0x55, 0xaa, 0x01, 0x80];
+    bytes.extend_from_slice(b"hello, world");
+    bytes.extend(0..=255u8);
+    bytes
+}
+
+/// Levels of `bytes` sent with `protocol`, one entry per half bit period
+fn half_bits(protocol: SwoProtocol, bytes: &[u8]) -> Vec<bool> {
+    let bits = |byte: u8| (0..8).map(move |bit| byte >> bit & 1 != 0);
+    let mut halves = Vec::new();
+    match protocol {
+        SwoProtocol::Nrz => {
+            halves.extend([true; 4]);
+            for byte in bytes.iter() {
+                halves.extend([false; 2]);
+                for bit in bits(*byte) {
+                    halves.extend([bit; 2]);
+                }
+                halves.extend([true; 2]);
+                /* Some bytes follow straight on, some after idle time */
+                if *byte & 3 == 0 {
+                    halves.extend([true; 24]);
+                }
+            }
+            halves.extend([true; 4]);
+        }
+        SwoProtocol::Manchester => {
+            halves.extend([false; 4]);
+            for frame in bytes.chunks(4) {
+                halves.extend([true, false]);
+                for bit in frame.iter().flat_map(|byte| bits(*byte)) {
+                    halves.extend([bit, !bit]);
+                }
+                halves.extend([false; 5]);
+            }
+        }
+    }
+    halves
+}
+
+/// A capture of `halves`, with the sender's clock `clock_error` fast or slow and each
+/// edge moved by up to `jitter` seconds either way
+fn signal(halves: &[bool], clock_error: f64, jitter: f64) -> Signal {
+    let half = BIT_PERIOD / 2.0 * (1.0 + clock_error) / TIMESCALE;
+    let jitter = jitter / TIMESCALE;
+    let mut state = 0x2545_f491u32;
+    let mut levels = vec![(0, halves[0])];
+    for (index, pair) in halves.windows(2).enumerate() {
+        if pair[0] != pair[1] {
+            /* xorshift, for the same jitter on every run */
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let offset = (state as f64 / u32::MAX as f64 * 2.0 - 1.0) * jitter;
+            let time = (index + 1) as f64 * half + offset;
+            levels.push((time.round() as u64, pair[1]));
+        }
+    }
+    let end = (halves.len() as f64 * half) as u64;
+    levels.push((end, halves[halves.len() - 1]));
+    Signal::from_levels(TIMESCALE, levels)
+}
+
+fn round_trip(protocol: SwoProtocol, clock_error: f64, jitter: f64) {
+    let bytes = test_bytes();
+    let signal = signal(&half_bits(protocol, &bytes), clock_error, jitter);
+    let timing = line::measure(&signal, protocol, BIT_PERIOD).unwrap();
+
+    let actual = BIT_PERIOD * (1.0 + clock_error);
+    let context = format!("{:?} {:+}: {:?}", protocol, clock_error, timing);
+    assert!(
+        (timing.bit_period - actual).abs() < actual * 1e-3,
+        "{}",
+        context
+    );
+    /* A run has an edge at each end, and either can move */
+    assert!(
+        timing.jitter_peak <= 2.0 * jitter + TIMESCALE,
+        "{}",
+        context
+    );
+    assert!(timing.jitter_rms <= timing.jitter_peak, "{}", context);
+    assert!((timing.duty_cycle - 0.5).abs() < 0.02, "{}", context);
+
+    let decoded = line::decode(&signal, protocol, timing.bit_period);
+    assert_eq!(decoded.errors, Vec::new(), "{}", context);
+    assert_eq!(decoded.bytes, bytes, "{}", context);
+}
+
+#[test]
+fn clean_lines() {
+    for protocol in [SwoProtocol::Manchester, SwoProtocol::Nrz] {
+        round_trip(protocol, 0.0, 0.0);
+    }
+}
+
+#[test]
+fn clock_error_and_jitter() {
+    for protocol in [SwoProtocol::Manchester, SwoProtocol::Nrz] {
+        for clock_error in [-0.05, 0.05] {
+            round_trip(protocol, clock_error, 2e-9);
+        }
+    }
+}
+
+#[test]
+fn timing_of_a_clean_line() {
+    let signal = signal(&half_bits(SwoProtocol::Manchester, &test_bytes()), 0.0, 0.0);
+    let timing = line::measure(&signal, SwoProtocol::Manchester, BIT_PERIOD).unwrap();
+    assert!((timing.unit - BIT_PERIOD / 2.0).abs() < TIMESCALE);
+    assert!(timing.jitter_peak <= TIMESCALE);
+    assert!(timing.runs > 1000);
+
+    /* Nothing but idle has no runs to measure */
+    let idle = Signal::from_levels(TIMESCALE, [(0, false), (100_000, false)]);
+    assert!(line::measure(&idle, SwoProtocol::Manchester, BIT_PERIOD).is_none());
+}
+
+#[test]
+fn nrz_framing_error() {
+    /* The second byte's stop bit is low, and the idle time after it finds the third */
+    let mut halves = half_bits(SwoProtocol::Nrz, &[0x41, 0xc0, 0x43]);
+    let stop = 4 + 20 + 18;
+    assert_eq!(&halves[stop..stop + 2], &[true, true]);
+    halves[stop] = false;
+    halves[stop + 1] = false;
+
+    let signal = signal(&halves, 0.0, 0.0);
+    let decoded = line::decode(&signal, SwoProtocol::Nrz, BIT_PERIOD);
+    assert_eq!(decoded.errors.len(), 1);
+    assert_eq!(decoded.errors[0].error, LineError::Framing);
+    assert_eq!(decoded.bytes, vec![0x41, 0x43]);
+}
+
+#[test]
+fn manchester_partial_byte() {
+    /* A frame cut off after three bits of its second byte */
+    let mut halves = half_bits(SwoProtocol::Manchester, &[0x5a, 0xc3]);
+    let cut = 4 + 2 + 2 * 8 + 2 * 3;
+    halves.truncate(cut);
+    halves.extend([false; 8]);
+
+    let signal = signal(&halves, 0.0, 0.0);
+    let decoded = line::decode(&signal, SwoProtocol::Manchester, BIT_PERIOD);
+    assert_eq!(decoded.bytes, vec![0x5a]);
+    assert_eq!(decoded.errors.len(), 1);
+    assert_eq!(decoded.errors[0].error, LineError::PartialByte(3));
+}
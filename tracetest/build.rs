@@ -0,0 +1,24 @@
+//! Sets a `cfg` for the Cortex-M architecture being built for, so that `cpu::Core::TARGET`
+//! can follow the target selected in `.cargo/config`
+
+use std::env;
+
+/// Target prefixes and the `cfg` each one sets, as in the `cortex-m` crate
+const ARCHITECTURES: [(&str, &str); 5] = [
+    ("thumbv6m-", "armv6m"),
+    ("thumbv7m-", "armv7m"),
+    ("thumbv7em-", "armv7em"),
+    ("thumbv8m.base-", "armv8m_base"),
+    ("thumbv8m.main-", "armv8m_main"),
+];
+
+fn main() {
+    let target = env::var("TARGET").unwrap();
+    for (prefix, cfg) in ARCHITECTURES.iter() {
+        println!("cargo:rustc-check-cfg=cfg({})", cfg);
+        if target.starts_with(prefix) {
+            println!("cargo:rustc-cfg={}", cfg);
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+}
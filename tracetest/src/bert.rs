@@ -0,0 +1,361 @@
+//! Bit error rate tester
+//!
+//! The firmware streams a pseudo-random binary sequence on [`BERT_PORT`] as fast as the
+//! ITM will take it, four bytes to a packet. Each byte holds the next eight bits of the
+//! sequence, earliest bit in bit 0, so the payload bits go out on the wire in sequence
+//! order. Once per tick it also writes a [`Descriptor`] to [`DESCRIPTOR_PORT`] saying
+//! which sequence is running.
+//!
+//! On the host, the payload bytes received on [`BERT_PORT`] are fed one at a time to a
+//! [`Checker`]. It locks onto the sequence from the received bits alone, so it does not
+//! matter where in the stream the capture starts.
+
+use crate::itm::{Payload, Stimulus};
+
+pub const BERT_PORT: usize = 3;
+pub const DESCRIPTOR_PORT: usize = 0;
+
+/// First word of every descriptor, "BRT1" in little-endian order
+pub const DESCRIPTOR_MAGIC: u32 = u32::from_le_bytes(*b"BRT1");
+
+/// Bytes checked together before deciding whether lock has been lost
+pub const BLOCK_BYTES: u32 = 16;
+
+/// A block with more bit errors than this is taken as loss of lock rather than noise.
+/// Uncorrelated data gets about half of its bits wrong.
+pub const LOCK_LOSS_ERRORS: u32 = BLOCK_BYTES * 8 / 4;
+
+/// How far ahead of the old position a new lock is searched for before it is counted as
+/// a resync rather than a slip
+pub const MAX_SLIP_BITS: u32 = 8 * 1024;
+
+/// A maximal-length sequence, named after the degree of its polynomial
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Prbs {
+    /// x^7 + x^6 + 1
+    Prbs7,
+    /// x^15 + x^14 + 1
+    Prbs15,
+    /// x^31 + x^28 + 1
+    Prbs31,
+}
+
+impl Prbs {
+    pub const ALL: [Prbs; 3] = [Prbs::Prbs7, Prbs::Prbs15, Prbs::Prbs31];
+
+    /// Degree of the polynomial, which is also the number of bits of state
+    pub fn degree(self) -> u32 {
+        match self {
+            Prbs::Prbs7 => 7,
+            Prbs::Prbs15 => 15,
+            Prbs::Prbs31 => 31,
+        }
+    }
+
+    /// Degree of the middle term of the polynomial
+    fn tap(self) -> u32 {
+        match self {
+            Prbs::Prbs7 => 6,
+            Prbs::Prbs15 => 14,
+            Prbs::Prbs31 => 28,
+        }
+    }
+
+    /// Number of bits before the sequence repeats
+    pub fn period(self) -> u32 {
+        (1 << self.degree()) - 1
+    }
+
+    pub fn from_degree(degree: u32) -> Option<Prbs> {
+        Prbs::ALL
+            .iter()
+            .copied()
+            .find(|prbs| prbs.degree() == degree)
+    }
+}
+
+/// Generator for one of the [`Prbs`] sequences
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Lfsr {
+    prbs: Prbs,
+    /// The last `degree` bits of the sequence, most recent in bit 0
+    state: u32,
+}
+
+impl Lfsr {
+    /// Start the sequence from the all-ones state
+    pub fn new(prbs: Prbs) -> Self {
+        Lfsr {
+            prbs,
+            state: prbs.period(),
+        }
+    }
+
+    /// Continue the sequence from the last `degree` bits seen, most recent in bit 0
+    pub fn from_state(prbs: Prbs, state: u32) -> Self {
+        Lfsr {
+            prbs,
+            state: state & prbs.period(),
+        }
+    }
+
+    pub fn prbs(&self) -> Prbs {
+        self.prbs
+    }
+
+    pub fn state(&self) -> u32 {
+        self.state
+    }
+
+    pub fn next_bit(&mut self) -> u8 {
+        let bit =
+            ((self.state >> (self.prbs.degree() - 1)) ^ (self.state >> (self.prbs.tap() - 1))) & 1;
+        self.state = ((self.state << 1) | bit) & self.prbs.period();
+        bit as u8
+    }
+
+    /// The next eight bits, earliest in bit 0
+    pub fn next_byte(&mut self) -> u8 {
+        (0..8).fold(0, |byte, n| byte | self.next_bit() << n)
+    }
+
+    /// The next four bytes, earliest in the lowest byte
+    pub fn next_word(&mut self) -> u32 {
+        u32::from_le_bytes([
+            self.next_byte(),
+            self.next_byte(),
+            self.next_byte(),
+            self.next_byte(),
+        ])
+    }
+}
+
+/// Sent on [`DESCRIPTOR_PORT`] once per tick
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Descriptor {
+    pub prbs: Prbs,
+    /// Payload words sent on [`BERT_PORT`] since the stream started, wrapping
+    pub words_sent: u32,
+}
+
+impl Descriptor {
+    pub fn words(&self) -> [u32; 3] {
+        [DESCRIPTOR_MAGIC, self.prbs.degree(), self.words_sent]
+    }
+
+    /// Write this descriptor to [`DESCRIPTOR_PORT`]
+    pub fn send(&self, out: &mut impl Stimulus) {
+        for word in self.words().iter() {
+            out.write(DESCRIPTOR_PORT, Payload::U32(*word));
+        }
+    }
+
+    pub fn from_words(words: &[u32; 3]) -> Option<Self> {
+        if words[0] != DESCRIPTOR_MAGIC {
+            return None;
+        }
+        Some(Descriptor {
+            prbs: Prbs::from_degree(words[1])?,
+            words_sent: words[2],
+        })
+    }
+}
+
+/// Send the next word of `lfsr` on [`BERT_PORT`], counting it in `descriptor`
+pub fn send_word(out: &mut impl Stimulus, lfsr: &mut Lfsr, descriptor: &mut Descriptor) {
+    out.write(BERT_PORT, Payload::U32(lfsr.next_word()));
+    descriptor.words_sent = descriptor.words_sent.wrapping_add(1);
+}
+
+/// Totals kept by a [`Checker`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Report {
+    /// Bits compared against the sequence while locked
+    pub bits: u64,
+    /// Of those, the bits that were wrong
+    pub errors: u64,
+    /// Times lock was lost and found again a short way further along the sequence, as
+    /// happens when packets are dropped
+    pub slips: u32,
+    /// Total distance skipped by those slips, in bits
+    pub slipped_bits: u64,
+    /// Times lock was lost and found again anywhere else, or not found within
+    /// [`MAX_SLIP_BITS`]
+    pub resyncs: u32,
+}
+
+impl Report {
+    /// Bit error rate over everything compared so far
+    pub fn ber(&self) -> f64 {
+        if self.bits == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.bits as f64
+        }
+    }
+}
+
+/// Bytes and bit errors of one block
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+struct Block {
+    bytes: u32,
+    errors: u32,
+}
+
+impl Block {
+    fn add(&mut self, other: Block) {
+        self.bytes += other.bytes;
+        self.errors += other.errors;
+    }
+
+    fn count(self, report: &mut Report) {
+        report.bits += self.bytes as u64 * 8;
+        report.errors += self.errors as u64;
+    }
+}
+
+/// Checks a received byte stream against a [`Prbs`]
+///
+/// Bytes are compared in blocks of [`BLOCK_BYTES`]. A block with more than
+/// [`LOCK_LOSS_ERRORS`] wrong bits makes the checker drop lock. It then takes the next
+/// `degree` received bits as the new state and looks for that state a little further
+/// along the old sequence to tell a slip from a resync.
+///
+/// A packet lost partway through a block leaves the rest of that block wrong, but often
+/// by too few bits to lose lock there. So a block with errors is only counted once the
+/// block after it has kept lock. If lock is lost instead, both blocks are taken out of
+/// the totals, and only put back if lock is found again at the same place in the
+/// sequence, which makes the loss a burst of errors rather than a slip.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Checker {
+    prbs: Prbs,
+    /// Where the received stream is expected to be, advanced for every byte received
+    reference: Option<Lfsr>,
+    locked: bool,
+    /// Received bits collected towards a new state, most recent in bit 0
+    history: u32,
+    history_bits: u32,
+    /// The block being compared
+    block: Block,
+    /// The last complete block, counted in [`Checker::report`] but not yet in `report`
+    previous: Block,
+    /// Blocks taken out of the totals when lock was last lost
+    lost: Block,
+    report: Report,
+}
+
+impl Checker {
+    pub fn new(prbs: Prbs) -> Self {
+        Checker {
+            prbs,
+            reference: None,
+            locked: false,
+            history: 0,
+            history_bits: 0,
+            block: Block::default(),
+            previous: Block::default(),
+            lost: Block::default(),
+            report: Report::default(),
+        }
+    }
+
+    pub fn prbs(&self) -> Prbs {
+        self.prbs
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Totals so far. The last complete block is included, but may still be taken back
+    /// out if lock is lost in the next one.
+    pub fn report(&self) -> Report {
+        let mut report = self.report;
+        self.previous.count(&mut report);
+        report
+    }
+
+    /// Check the next payload byte from [`BERT_PORT`]
+    pub fn push(&mut self, byte: u8) {
+        if self.locked {
+            self.check(byte);
+        } else {
+            self.acquire(byte);
+        }
+    }
+
+    fn check(&mut self, byte: u8) {
+        let expected = self.reference.as_mut().map_or(0, Lfsr::next_byte);
+        self.block.errors += (expected ^ byte).count_ones();
+        self.block.bytes += 1;
+        if self.block.bytes < BLOCK_BYTES {
+            return;
+        }
+
+        let block = core::mem::take(&mut self.block);
+        let previous = core::mem::take(&mut self.previous);
+        if block.errors > LOCK_LOSS_ERRORS {
+            self.locked = false;
+            self.history_bits = 0;
+            self.lost = block;
+            /* A clean block was in step, so only one with errors can hold the start of
+             * whatever lost lock */
+            if previous.errors == 0 {
+                previous.count(&mut self.report);
+            } else {
+                self.lost.add(previous);
+            }
+        } else {
+            previous.count(&mut self.report);
+            self.previous = block;
+        }
+    }
+
+    fn acquire(&mut self, byte: u8) {
+        let degree = self.prbs.degree();
+        for n in 0..8 {
+            if let Some(reference) = self.reference.as_mut() {
+                reference.next_bit();
+            }
+            self.history = (self.history << 1) | ((byte >> n) & 1) as u32;
+            self.history_bits += 1;
+            if self.history_bits < degree {
+                continue;
+            }
+
+            let mut lfsr = Lfsr::from_state(self.prbs, self.history);
+            if let Some(reference) = self.reference {
+                let lost = core::mem::take(&mut self.lost);
+                match slip_distance(reference, lfsr.state()) {
+                    /* Still in step, so the lost blocks were real errors */
+                    Some(0) => lost.count(&mut self.report),
+                    Some(bits) => {
+                        self.report.slips += 1;
+                        self.report.slipped_bits += bits as u64;
+                    }
+                    None => self.report.resyncs += 1,
+                }
+            }
+            /* Skip the rest of this byte so that checking starts on a byte boundary */
+            for _ in n + 1..8 {
+                lfsr.next_bit();
+            }
+            self.reference = Some(lfsr);
+            self.locked = true;
+            return;
+        }
+    }
+}
+
+/// How many bits past `reference` the sequence reaches `state`, if it does so within
+/// [`MAX_SLIP_BITS`] or one period, whichever is shorter
+fn slip_distance(mut reference: Lfsr, state: u32) -> Option<u32> {
+    let limit = MAX_SLIP_BITS.min(reference.prbs().period() - 1);
+    for bits in 0..=limit {
+        if reference.state() == state {
+            return Some(bits);
+        }
+        reference.next_bit();
+    }
+    None
+}
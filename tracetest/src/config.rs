@@ -0,0 +1,629 @@
+//! Validated SWO configuration
+//!
+//! The TPIU derives the SWO bit rate by dividing its input clock by `ACPR + 1`. Not every
+//! baud rate can be reached from every clock, so [`SwoConfigBuilder::build`] picks the
+//! closest divisor and refuses configurations that would put the wrong rate on the wire.
+//!
+//! The same configuration can instead select one of the synchronous parallel trace port
+//! modes. These have no prescaler: TRACECLK runs at half of TRACECLKIN and data changes on
+//! both edges, so each data pin carries one bit per TRACECLKIN cycle.
+
+use core::fmt;
+
+use crate::cpu::Core;
+use crate::dwt::{DwtConfig, DwtConfigError};
+use crate::regs::{ItmTcr, TraceMode, TracePins};
+use crate::swo::SwoProtocol;
+
+/// Largest divisor that fits in the 13-bit `TPIU_ACPR.PRESCALER` field
+pub const MAX_DIVISOR: u32 = 0x2000;
+
+/// `TPIU_SPPR` value that selects the synchronous trace port
+const TPIU_SPPR_SYNC: u32 = 0;
+
+/// Default tolerance between requested and achieved baud rate, in percent
+pub const DEFAULT_TOLERANCE_PERCENT: f32 = 3.0;
+
+/// Clock feeding the TPIU's asynchronous prescaler
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ClockSource {
+    /// The core clock, in Hz. TRACECLKIN is tied to HCLK on the STM32F1.
+    Hclk(u32),
+    /// A dedicated trace clock, in Hz
+    TraceClkIn(u32),
+}
+
+impl ClockSource {
+    pub fn frequency(self) -> u32 {
+        match self {
+            ClockSource::Hclk(hz) | ClockSource::TraceClkIn(hz) => hz,
+        }
+    }
+}
+
+/// Divider between the timestamp clock and the local timestamp counter
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimestampPrescaler {
+    Div1,
+    Div4,
+    Div16,
+    Div64,
+}
+
+impl TimestampPrescaler {
+    pub const ALL: [TimestampPrescaler; 4] = [
+        TimestampPrescaler::Div1,
+        TimestampPrescaler::Div4,
+        TimestampPrescaler::Div16,
+        TimestampPrescaler::Div64,
+    ];
+
+    /// Clock cycles per timestamp tick
+    pub fn divisor(self) -> u32 {
+        match self {
+            TimestampPrescaler::Div1 => 1,
+            TimestampPrescaler::Div4 => 4,
+            TimestampPrescaler::Div16 => 16,
+            TimestampPrescaler::Div64 => 64,
+        }
+    }
+
+    /// Value of the `ITM_TCR.TSPrescale` field
+    pub fn tsprescale(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Clock that drives the local timestamp counter
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TimestampClock {
+    /// The processor clock. The prescaler has no effect.
+    Processor,
+    /// The asynchronous TPIU clock, selected by `ITM_TCR.SWOENA`
+    Swo,
+}
+
+/// How often the ITM emits global timestamp packets, from `ITM_TCR.GTSFREQ`
+///
+/// Global timestamps need a system timestamp generator. Parts without one, such as the
+/// STM32F1, accept the setting but never emit the packets.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GlobalTimestampFrequency {
+    Disabled,
+    Every128Cycles,
+    Every8192Cycles,
+    /// After every packet, if the output FIFO is empty
+    EveryPacket,
+}
+
+/// Reasons a [`SwoConfigBuilder`] can refuse to produce a configuration
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SwoConfigError {
+    /// A baud rate of zero was requested
+    ZeroBaudRate,
+    /// The clock is too slow to reach the requested rate
+    BaudTooHigh { requested: u32, max: u32 },
+    /// The requested rate needs a divisor larger than the prescaler can hold
+    BaudTooLow { requested: u32, min: u32 },
+    /// The nearest achievable rate is further from the request than the tolerance allows
+    OutOfTolerance {
+        requested: u32,
+        achieved: u32,
+        error_percent: f32,
+    },
+    /// A timestamp prescaler other than 1 was requested with the processor clock
+    PrescalerNeedsSwoClock,
+    /// The DWT configuration is inconsistent
+    Dwt(DwtConfigError),
+    /// ATB trace IDs must be in the range 0x01 to 0x6f
+    InvalidTraceId(u8),
+    /// `ITM_TPR` only has one bit for each of the four groups of 8 stimulus ports
+    InvalidPrivilegeMask(u8),
+    /// Data trace watchpoints were requested for a core whose DWT comparators are laid
+    /// out differently from the ARMv7-M ones
+    DataTraceUnsupported(Core),
+    /// An ETM trace ID was requested for a core without an ETMv3
+    EtmUnsupported(Core),
+}
+
+impl fmt::Display for SwoConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SwoConfigError::ZeroBaudRate => write!(f, "baud rate must be nonzero"),
+            SwoConfigError::BaudTooHigh { requested, max } => write!(
+                f,
+                "{} baud is above the maximum of {} baud for this clock",
+                requested, max
+            ),
+            SwoConfigError::BaudTooLow { requested, min } => write!(
+                f,
+                "{} baud is below the minimum of {} baud for this clock",
+                requested, min
+            ),
+            SwoConfigError::OutOfTolerance {
+                requested,
+                achieved,
+                error_percent,
+            } => write!(
+                f,
+                "{} baud requested but only {} baud is achievable ({:+.2}%)",
+                requested, achieved, error_percent
+            ),
+            SwoConfigError::PrescalerNeedsSwoClock => write!(
+                f,
+                "the timestamp prescaler only applies to the SWO timestamp clock"
+            ),
+            SwoConfigError::InvalidTraceId(id) => {
+                write!(f, "trace ID {:#04x} is outside 0x01 to 0x6f", id)
+            }
+            SwoConfigError::InvalidPrivilegeMask(mask) => write!(
+                f,
+                "privilege mask {:#x} has bits above the four port groups",
+                mask
+            ),
+            SwoConfigError::Dwt(DwtConfigError::NeedsCycleCounter) => write!(
+                f,
+                "DWT synchronisation and PC sampling need the cycle counter"
+            ),
+            SwoConfigError::Dwt(DwtConfigError::PostPresetOutOfRange(preset)) => {
+                write!(f, "POSTPRESET of {} does not fit in 4 bits", preset)
+            }
+            SwoConfigError::Dwt(DwtConfigError::MisalignedWatchpoint(index)) => write!(
+                f,
+                "watchpoint {} is not a power-of-two size at an aligned address",
+                index
+            ),
+            SwoConfigError::Dwt(DwtConfigError::UnsupportedWatchpoint(index)) => write!(
+                f,
+                "watchpoint {} asks for PC output on only reads or only writes",
+                index
+            ),
+            SwoConfigError::DataTraceUnsupported(core) => write!(
+                f,
+                "data trace watchpoints are not supported on the {:?}",
+                core
+            ),
+            SwoConfigError::EtmUnsupported(core) => {
+                write!(f, "the {:?} has no ETMv3 to program a trace ID into", core)
+            }
+        }
+    }
+}
+
+/// A validated SWO configuration, ready to be passed to [`swo_setup`](crate::swo::swo_setup)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SwoConfig {
+    clock: ClockSource,
+    protocol: SwoProtocol,
+    requested_baud: u32,
+    divisor: u32,
+    tolerance_percent: f32,
+    stimulus_ports: u32,
+    local_timestamps: Option<TimestampPrescaler>,
+    timestamp_clock: TimestampClock,
+    global_timestamps: GlobalTimestampFrequency,
+    dwt: DwtConfig,
+    formatter: bool,
+    itm_trace_id: u8,
+    etm_trace_id: Option<u8>,
+    trace_mode: TraceMode,
+    trace_pins: TracePins,
+    privilege_mask: u8,
+    core: Core,
+}
+
+impl SwoConfig {
+    /// Start building a configuration for `baud` from the given clock
+    pub fn builder(clock: ClockSource, baud: u32) -> SwoConfigBuilder {
+        SwoConfigBuilder {
+            clock,
+            baud,
+            protocol: SwoProtocol::Manchester,
+            tolerance_percent: DEFAULT_TOLERANCE_PERCENT,
+            stimulus_ports: 1,
+            local_timestamps: None,
+            timestamp_clock: TimestampClock::Swo,
+            global_timestamps: GlobalTimestampFrequency::Disabled,
+            dwt: DwtConfig::default(),
+            formatter: false,
+            itm_trace_id: 1,
+            etm_trace_id: None,
+            trace_mode: TraceMode::Async,
+            trace_pins: TracePins::Untouched,
+            privilege_mask: 0xf,
+            core: Core::TARGET,
+        }
+    }
+
+    /// A builder that starts from this configuration, for making variations of it
+    pub fn to_builder(&self) -> SwoConfigBuilder {
+        SwoConfigBuilder {
+            clock: self.clock,
+            baud: self.requested_baud,
+            protocol: self.protocol,
+            tolerance_percent: self.tolerance_percent,
+            stimulus_ports: self.stimulus_ports,
+            local_timestamps: self.local_timestamps,
+            timestamp_clock: self.timestamp_clock,
+            global_timestamps: self.global_timestamps,
+            dwt: self.dwt,
+            formatter: self.formatter,
+            itm_trace_id: self.itm_trace_id,
+            etm_trace_id: self.etm_trace_id,
+            trace_mode: self.trace_mode,
+            trace_pins: self.trace_pins,
+            privilege_mask: self.privilege_mask,
+            core: self.core,
+        }
+    }
+
+    pub fn clock(&self) -> ClockSource {
+        self.clock
+    }
+
+    pub fn protocol(&self) -> SwoProtocol {
+        self.protocol
+    }
+
+    /// SWO, or the width of the synchronous trace port
+    pub fn trace_mode(&self) -> TraceMode {
+        self.trace_mode
+    }
+
+    pub fn trace_pins(&self) -> TracePins {
+        self.trace_pins
+    }
+
+    /// The rate asked for. The synchronous modes have no prescaler and ignore the baud
+    /// rate given to the builder, so there this is the rate they run at.
+    pub fn requested_baud(&self) -> u32 {
+        match self.trace_mode {
+            TraceMode::Async => self.requested_baud,
+            TraceMode::Sync1 | TraceMode::Sync2 | TraceMode::Sync4 => self.achieved_baud(),
+        }
+    }
+
+    /// The baud rate that will actually appear on the wire. In the synchronous modes this
+    /// is the bit rate of each data pin.
+    pub fn achieved_baud(&self) -> u32 {
+        self.clock.frequency() / self.divisor
+    }
+
+    /// Difference between the achieved and requested rates, in percent of the request.
+    /// Always zero in the synchronous modes.
+    pub fn error_percent(&self) -> f32 {
+        error_percent(self.requested_baud(), self.achieved_baud())
+    }
+
+    /// Largest difference between the requested and achieved rates that was accepted, in
+    /// percent
+    pub fn tolerance_percent(&self) -> f32 {
+        self.tolerance_percent
+    }
+
+    /// Most trace bytes per second that the port can carry
+    ///
+    /// The formatter, when on, spends one byte of every 16-byte frame on IDs and flags.
+    pub fn line_capacity(&self) -> u32 {
+        let bits_per_second = self.achieved_baud() as u64;
+        let bytes_per_second = match self.trace_mode {
+            TraceMode::Async => bits_per_second / self.protocol.bits_per_byte() as u64,
+            TraceMode::Sync1 => bits_per_second / 8,
+            TraceMode::Sync2 => bits_per_second * 2 / 8,
+            TraceMode::Sync4 => bits_per_second * 4 / 8,
+        };
+        if self.formatter {
+            (bytes_per_second * 15 / 16) as u32
+        } else {
+            bytes_per_second as u32
+        }
+    }
+
+    /// Value to program into `TPIU_ACPR`
+    pub fn acpr(&self) -> u32 {
+        self.divisor - 1
+    }
+
+    /// Value to program into `TPIU_SPPR`
+    pub fn sppr(&self) -> u32 {
+        match self.trace_mode {
+            TraceMode::Async => self.protocol.tpiu_sppr_value(),
+            TraceMode::Sync1 | TraceMode::Sync2 | TraceMode::Sync4 => TPIU_SPPR_SYNC,
+        }
+    }
+
+    /// Value to program into `TPIU_CSPSR`, a one-hot mask of the port width
+    pub fn cspsr(&self) -> u32 {
+        match self.trace_mode {
+            TraceMode::Async | TraceMode::Sync1 => 1 << 0,
+            TraceMode::Sync2 => 1 << 1,
+            TraceMode::Sync4 => 1 << 3,
+        }
+    }
+
+    /// Bitmask of enabled stimulus ports, as written to `ITM_TER`
+    pub fn stimulus_ports(&self) -> u32 {
+        self.stimulus_ports
+    }
+
+    /// Value to program into `ITM_TPR`
+    pub fn itm_tpr(&self) -> u32 {
+        self.privilege_mask as u32
+    }
+
+    /// The local timestamp prescaler, or `None` if local timestamps are off
+    pub fn local_timestamps(&self) -> Option<TimestampPrescaler> {
+        self.local_timestamps
+    }
+
+    pub fn timestamp_clock(&self) -> TimestampClock {
+        self.timestamp_clock
+    }
+
+    pub fn global_timestamps(&self) -> GlobalTimestampFrequency {
+        self.global_timestamps
+    }
+
+    pub fn dwt(&self) -> &DwtConfig {
+        &self.dwt
+    }
+
+    /// Whether the TPIU wraps the trace in formatter frames
+    pub fn formatter(&self) -> bool {
+        self.formatter
+    }
+
+    /// ATB ID of the ITM, used to tell its data apart in formatter frames
+    pub fn itm_trace_id(&self) -> u8 {
+        self.itm_trace_id
+    }
+
+    /// ATB ID to program into the ETM, or `None` to leave the ETM alone
+    pub fn etm_trace_id(&self) -> Option<u8> {
+        self.etm_trace_id
+    }
+
+    /// The core the configuration is for
+    pub fn core(&self) -> Core {
+        self.core
+    }
+
+    /// Value to program into `ITM_TCR`
+    pub fn itm_tcr(&self) -> ItmTcr {
+        ItmTcr {
+            itmena: true,
+            tsena: self.local_timestamps.is_some(),
+            syncena: true,
+            txena: true,
+            swoena: self.timestamp_clock == TimestampClock::Swo,
+            trace_bus_id: self.itm_trace_id,
+            gtsfreq: self.global_timestamps as u8,
+            tsprescale: self
+                .local_timestamps
+                .map_or(0, TimestampPrescaler::tsprescale),
+            busy: false,
+        }
+    }
+}
+
+/// Builder for [`SwoConfig`]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SwoConfigBuilder {
+    clock: ClockSource,
+    baud: u32,
+    protocol: SwoProtocol,
+    tolerance_percent: f32,
+    stimulus_ports: u32,
+    local_timestamps: Option<TimestampPrescaler>,
+    timestamp_clock: TimestampClock,
+    global_timestamps: GlobalTimestampFrequency,
+    dwt: DwtConfig,
+    formatter: bool,
+    itm_trace_id: u8,
+    etm_trace_id: Option<u8>,
+    trace_mode: TraceMode,
+    trace_pins: TracePins,
+    privilege_mask: u8,
+    core: Core,
+}
+
+impl SwoConfigBuilder {
+    pub fn clock(mut self, clock: ClockSource) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn baud(mut self, baud: u32) -> Self {
+        self.baud = baud;
+        self
+    }
+
+    pub fn protocol(mut self, protocol: SwoProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Send trace over SWO or over the 1-, 2- or 4-bit synchronous trace port. Defaults to
+    /// [`TraceMode::Async`].
+    ///
+    /// The synchronous modes ignore the baud rate and protocol, and always turn the
+    /// formatter on since the port has no other way to mark idle cycles.
+    pub fn trace_mode(mut self, trace_mode: TraceMode) -> Self {
+        self.trace_mode = trace_mode;
+        self
+    }
+
+    /// How to hand the trace pins to the TPIU. Defaults to [`TracePins::Untouched`], for
+    /// parts that need no such step or where the debugger takes care of it.
+    pub fn trace_pins(mut self, trace_pins: TracePins) -> Self {
+        self.trace_pins = trace_pins;
+        self
+    }
+
+    /// Largest acceptable difference between the requested and achieved rates, in percent
+    pub fn tolerance_percent(mut self, tolerance_percent: f32) -> Self {
+        self.tolerance_percent = tolerance_percent;
+        self
+    }
+
+    /// Bitmask of stimulus ports to enable. Defaults to port 0 only.
+    pub fn stimulus_ports(mut self, mask: u32) -> Self {
+        self.stimulus_ports = mask;
+        self
+    }
+
+    /// Stimulus port groups that only privileged code may write to. Bit `n` covers
+    /// ports `8 * n` to `8 * n + 7`. Defaults to `0xf`, all of them.
+    pub fn privilege_mask(mut self, mask: u8) -> Self {
+        self.privilege_mask = mask;
+        self
+    }
+
+    /// Emit local timestamp packets, counting at the given rate
+    ///
+    /// The counter only runs while `DEMCR.TRCENA` is set, which [`swo_setup`] always
+    /// does. Timestamps follow DWT packets as well as stimulus writes since `TXENA` is on.
+    ///
+    /// [`swo_setup`]: crate::swo::swo_setup
+    pub fn local_timestamps(mut self, prescaler: TimestampPrescaler) -> Self {
+        self.local_timestamps = Some(prescaler);
+        self
+    }
+
+    pub fn no_local_timestamps(mut self) -> Self {
+        self.local_timestamps = None;
+        self
+    }
+
+    /// Clock for the local timestamp counter. Defaults to [`TimestampClock::Swo`].
+    pub fn timestamp_clock(mut self, clock: TimestampClock) -> Self {
+        self.timestamp_clock = clock;
+        self
+    }
+
+    pub fn global_timestamps(mut self, frequency: GlobalTimestampFrequency) -> Self {
+        self.global_timestamps = frequency;
+        self
+    }
+
+    /// Packet sources to enable in the DWT
+    pub fn dwt(mut self, dwt: DwtConfig) -> Self {
+        self.dwt = dwt;
+        self
+    }
+
+    /// Wrap the trace stream in 16-byte TPIU formatter frames that carry the ID of
+    /// each source, rather than sending the ITM stream as-is
+    pub fn formatter(mut self, formatter: bool) -> Self {
+        self.formatter = formatter;
+        self
+    }
+
+    /// ATB ID of the ITM. Defaults to 1.
+    pub fn itm_trace_id(mut self, id: u8) -> Self {
+        self.itm_trace_id = id;
+        self
+    }
+
+    /// Program the ETM's ATB ID as well. The ETM is otherwise left powered down.
+    pub fn etm_trace_id(mut self, id: u8) -> Self {
+        self.etm_trace_id = Some(id);
+        self
+    }
+
+    /// The core to set trace up on. Defaults to [`Core::TARGET`], the one the build
+    /// target and features select.
+    pub fn core(mut self, core: Core) -> Self {
+        self.core = core;
+        self
+    }
+
+    /// Choose the divisor closest to the requested rate and check that it is usable
+    pub fn build(self) -> Result<SwoConfig, SwoConfigError> {
+        let clock_frequency = self.clock.frequency();
+        if self.timestamp_clock == TimestampClock::Processor
+            && self
+                .local_timestamps
+                .is_some_and(|p| p != TimestampPrescaler::Div1)
+        {
+            return Err(SwoConfigError::PrescalerNeedsSwoClock);
+        }
+        self.dwt.validate().map_err(SwoConfigError::Dwt)?;
+        if !self.core.has_v7m_comparators() && self.dwt.watchpoints.iter().any(Option::is_some) {
+            return Err(SwoConfigError::DataTraceUnsupported(self.core));
+        }
+        if self.etm_trace_id.is_some() && !self.core.has_etm_v3() {
+            return Err(SwoConfigError::EtmUnsupported(self.core));
+        }
+        for id in core::iter::once(self.itm_trace_id).chain(self.etm_trace_id) {
+            if !(0x01..=0x6f).contains(&id) {
+                return Err(SwoConfigError::InvalidTraceId(id));
+            }
+        }
+        if self.privilege_mask > 0xf {
+            return Err(SwoConfigError::InvalidPrivilegeMask(self.privilege_mask));
+        }
+        if self.trace_mode != TraceMode::Async {
+            return Ok(SwoConfig {
+                formatter: true,
+                ..self.unchecked(1)
+            });
+        }
+        if self.baud == 0 {
+            return Err(SwoConfigError::ZeroBaudRate);
+        }
+        if self.baud > clock_frequency {
+            return Err(SwoConfigError::BaudTooHigh {
+                requested: self.baud,
+                max: clock_frequency,
+            });
+        }
+
+        /* Round to the nearest divisor rather than truncating */
+        let divisor = ((clock_frequency as u64 + self.baud as u64 / 2) / self.baud as u64) as u32;
+        if divisor > MAX_DIVISOR {
+            return Err(SwoConfigError::BaudTooLow {
+                requested: self.baud,
+                min: clock_frequency.div_ceil(MAX_DIVISOR),
+            });
+        }
+
+        let config = self.unchecked(divisor);
+        let error_percent = config.error_percent();
+        if error_percent.abs() > self.tolerance_percent {
+            return Err(SwoConfigError::OutOfTolerance {
+                requested: self.baud,
+                achieved: config.achieved_baud(),
+                error_percent,
+            });
+        }
+        Ok(config)
+    }
+
+    /// The configuration with `divisor`, before the rate has been checked
+    fn unchecked(&self, divisor: u32) -> SwoConfig {
+        SwoConfig {
+            clock: self.clock,
+            protocol: self.protocol,
+            requested_baud: self.baud,
+            divisor,
+            tolerance_percent: self.tolerance_percent,
+            stimulus_ports: self.stimulus_ports,
+            local_timestamps: self.local_timestamps,
+            timestamp_clock: self.timestamp_clock,
+            global_timestamps: self.global_timestamps,
+            dwt: self.dwt,
+            formatter: self.formatter,
+            itm_trace_id: self.itm_trace_id,
+            etm_trace_id: self.etm_trace_id,
+            trace_mode: self.trace_mode,
+            trace_pins: self.trace_pins,
+            privilege_mask: self.privilege_mask,
+            core: self.core,
+        }
+    }
+}
+
+fn error_percent(requested: u32, achieved: u32) -> f32 {
+    (achieved as f32 - requested as f32) * 100.0 / requested as f32
+}
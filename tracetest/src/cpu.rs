@@ -0,0 +1,221 @@
+//! The Cortex-M cores that trace can be set up on
+//!
+//! The trace registers sit at the same addresses on every ARMv7-M and ARMv8-M Mainline
+//! core, but the cores differ in the details:
+//!
+//! | core       | architecture       | software lock | TPIU part number | ETM   |
+//! |------------|--------------------|---------------|------------------|-------|
+//! | Cortex-M3  | ARMv7-M            | yes           | `0x923`          | ETMv3 |
+//! | Cortex-M4  | ARMv7E-M           | yes           | `0x9a1`          | ETMv3 |
+//! | Cortex-M7  | ARMv7E-M           | yes, enforced | `0x9a9`          | ETMv4 |
+//! | Cortex-M33 | ARMv8-M Mainline   | no            | `0xd21`          | ETMv4 |
+//! | Cortex-M55 | ARMv8.1-M Mainline | no            | `0xd22`          | ETMv4 |
+//!
+//! The software lock column is for the core's own ITM and DWT. The Cortex-M7 ignores
+//! writes to them until their Lock Access Registers have been given the unlock key, and
+//! the ARMv8-M cores have no Lock Access Registers. A CoreSight SoC-400 TPIU has one
+//! whichever core it is next to, so rather than going by the core,
+//! [`swo_setup`](crate::swo::swo_setup) reads each component's Lock Status Register
+//! and only unlocks those that say they have a lock.
+//!
+//! On ARMv8-M the DWT comparators also lose `DWT_MASKn` and use a different
+//! `DWT_FUNCTIONn` encoding, which [`Watchpoint`](crate::dwt::Watchpoint) does not
+//! produce, so data trace watchpoints are refused for those cores.
+//!
+//! ARMv6-M and ARMv8-M Baseline cores have no ITM at all. The crate refuses to build for
+//! their targets, and [`Core::from_cpuid`] refuses them at run time.
+//!
+//! [`Core::TARGET`] follows the build target: `thumbv7m` is the Cortex-M3, `thumbv7em`
+//! the Cortex-M4 and `thumbv8m.main` the Cortex-M33. The `cortex-m7` and `cortex-m55`
+//! features pick the other core of the same architecture. Builds for anything else, such
+//! as the host, default to the Cortex-M3 unless one of those features is on.
+
+use core::fmt;
+
+use crate::regs::{Register, Registers};
+
+#[cfg(any(armv6m, armv8m_base))]
+compile_error!(
+    "ARMv6-M and ARMv8-M Baseline cores have no ITM, so there is no SWO trace to set up"
+);
+
+#[cfg(all(armv7m, any(feature = "cortex-m7", feature = "cortex-m55")))]
+compile_error!("thumbv7m is only the Cortex-M3. Build for thumbv7em or thumbv8m.main instead.");
+
+#[cfg(all(armv7em, feature = "cortex-m55"))]
+compile_error!("the Cortex-M55 needs a thumbv8m.main target");
+
+#[cfg(all(armv8m_main, feature = "cortex-m7"))]
+compile_error!("the Cortex-M7 needs a thumbv7em target");
+
+/// A core with an ITM
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Core {
+    CortexM3,
+    CortexM4,
+    CortexM7,
+    CortexM33,
+    CortexM55,
+}
+
+/// Which version of the architecture a [`Core`] implements
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Architecture {
+    /// ARMv7-M, including the DSP extension
+    V7M,
+    /// ARMv8-M and ARMv8.1-M Mainline
+    V8MMain,
+}
+
+/// Reasons [`Core::check`] or [`Core::from_cpuid`] refuse the part they are running on
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CoreError {
+    /// The part is an ARMv6-M or ARMv8-M Baseline core, which has no ITM. Holds
+    /// `CPUID.PARTNO`.
+    NoItm(u16),
+    /// `CPUID.PARTNO` is not a core this crate knows
+    UnknownCore(u16),
+    /// The part is a different core from the one the configuration was built for
+    WrongCore { expected: Core, found: Core },
+    /// The TPIU's part number is not one this core is known to have, so the TPIU may be
+    /// elsewhere or missing. Holds the part number read.
+    UnknownTpiu(u16),
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::NoItm(partno) => write!(
+                f,
+                "core {:#05x} is ARMv6-M or ARMv8-M Baseline and has no ITM",
+                partno
+            ),
+            CoreError::UnknownCore(partno) => write!(f, "unknown core {:#05x}", partno),
+            CoreError::WrongCore { expected, found } => {
+                write!(
+                    f,
+                    "built for the {:?} but running on the {:?}",
+                    expected, found
+                )
+            }
+            CoreError::UnknownTpiu(partno) => {
+                write!(
+                    f,
+                    "TPIU part number {:#05x} is not one this core has",
+                    partno
+                )
+            }
+        }
+    }
+}
+
+/// `CPUID.PARTNO` of the cores without an ITM: the Cortex-M0, M0+, M1 and M23
+const NO_ITM_PARTNOS: [u16; 4] = [0xc20, 0xc60, 0xc21, 0xd20];
+
+impl Core {
+    /// The core selected by the build target and features
+    pub const TARGET: Core = if cfg!(feature = "cortex-m55") {
+        Core::CortexM55
+    } else if cfg!(feature = "cortex-m7") {
+        Core::CortexM7
+    } else if cfg!(armv8m_main) {
+        Core::CortexM33
+    } else if cfg!(armv7em) {
+        Core::CortexM4
+    } else {
+        Core::CortexM3
+    };
+
+    pub const ALL: [Core; 5] = [
+        Core::CortexM3,
+        Core::CortexM4,
+        Core::CortexM7,
+        Core::CortexM33,
+        Core::CortexM55,
+    ];
+
+    pub fn architecture(self) -> Architecture {
+        match self {
+            Core::CortexM3 | Core::CortexM4 | Core::CortexM7 => Architecture::V7M,
+            Core::CortexM33 | Core::CortexM55 => Architecture::V8MMain,
+        }
+    }
+
+    /// `CPUID.PARTNO` of this core
+    pub fn cpuid_partno(self) -> u16 {
+        match self {
+            Core::CortexM3 => 0xc23,
+            Core::CortexM4 => 0xc24,
+            Core::CortexM7 => 0xc27,
+            Core::CortexM33 => 0xd21,
+            Core::CortexM55 => 0xd22,
+        }
+    }
+
+    /// Identify the core from the value of `CPUID`
+    pub fn from_cpuid(cpuid: u32) -> Result<Core, CoreError> {
+        let partno = ((cpuid >> 4) & 0xfff) as u16;
+        if NO_ITM_PARTNOS.contains(&partno) {
+            return Err(CoreError::NoItm(partno));
+        }
+        Core::ALL
+            .iter()
+            .copied()
+            .find(|core| core.cpuid_partno() == partno)
+            .ok_or(CoreError::UnknownCore(partno))
+    }
+
+    /// Part numbers the TPIU at `0xE0040000` may have on this core
+    ///
+    /// Besides the core's own TPIU, the Cortex-M7 and the ARMv8-M cores may be built
+    /// with a CoreSight SoC-400 TPIU, part number `0x912`, instead.
+    pub fn tpiu_part_numbers(self) -> &'static [u16] {
+        match self {
+            Core::CortexM3 => &[0x923],
+            Core::CortexM4 => &[0x9a1],
+            Core::CortexM7 => &[0x9a9, 0x912],
+            Core::CortexM33 => &[0xd21, 0x912],
+            Core::CortexM55 => &[0xd22, 0x912],
+        }
+    }
+
+    /// Whether the DWT comparators use the ARMv7-M `DWT_MASKn` and `DWT_FUNCTIONn`
+    /// encoding that data trace [`Watchpoint`](crate::dwt::Watchpoint)s are written for
+    pub fn has_v7m_comparators(self) -> bool {
+        self.architecture() == Architecture::V7M
+    }
+
+    /// Whether the ETM is the ETMv3 whose `ETMCR` and `ETMTRACEIDR` the setup programs
+    /// when given an ETM trace ID. The other cores have an ETMv4, which is laid out
+    /// differently.
+    pub fn has_etm_v3(self) -> bool {
+        matches!(self, Core::CortexM3 | Core::CortexM4)
+    }
+
+    /// Check that the part is this core, with a TPIU where it is expected
+    ///
+    /// Only reads registers, so it is safe to run before
+    /// [`swo_setup`](crate::swo::swo_setup) to refuse a firmware image built for the
+    /// wrong core.
+    pub fn check<R: Registers>(self, regs: &mut R) -> Result<(), CoreError> {
+        let found = Core::from_cpuid(regs.read(Register::ScbCpuid))?;
+        if found != self {
+            return Err(CoreError::WrongCore {
+                expected: self,
+                found,
+            });
+        }
+        let tpiu =
+            (regs.read(Register::TpiuPidr0) & 0xff) | (regs.read(Register::TpiuPidr1) & 0xf) << 8;
+        if !self.tpiu_part_numbers().contains(&(tpiu as u16)) {
+            return Err(CoreError::UnknownTpiu(tpiu as u16));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Core {
+    fn default() -> Self {
+        Core::TARGET
+    }
+}
@@ -0,0 +1,67 @@
+//! Data trace exercise
+//!
+//! Three statics in the firmware are watched by the DWT comparators, each set up for a
+//! different data trace output. Once per tick the firmware writes the tick number `n` to
+//! [`DATA_TRACE_PORT`] and then, in this order:
+//!
+//! 1. stores `n` to the 32-bit counter. Comparator 0 emits a PC value packet and a data
+//!    value packet carrying `n`.
+//! 2. loads the counter back. Comparator 1 emits a data value packet carrying `n`.
+//! 3. stores the low 16 bits of `n` to the halfword. Comparator 2 emits an address
+//!    offset packet.
+//! 4. loads the byte. Comparator 3 emits a PC value packet.
+//!
+//! Before the first tick the firmware writes a [`Descriptor`] with the addresses of the
+//! three statics to the same port.
+
+use crate::dwt::{DataTraceOutput, WatchAccess, Watchpoint};
+use crate::regs::DWT_COMPARATORS;
+
+pub const DATA_TRACE_PORT: usize = 0;
+
+/// First word of the descriptor, "DAT1" in little-endian order
+pub const DESCRIPTOR_MAGIC: u32 = u32::from_le_bytes(*b"DAT1");
+
+/// Addresses of the watched statics
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Descriptor {
+    pub counter: u32,
+    pub halfword: u32,
+    pub byte: u32,
+}
+
+impl Descriptor {
+    pub fn words(&self) -> [u32; 4] {
+        [DESCRIPTOR_MAGIC, self.counter, self.halfword, self.byte]
+    }
+
+    /// The comparator setup described in the module documentation
+    pub fn watchpoints(&self) -> [Option<Watchpoint>; DWT_COMPARATORS] {
+        [
+            Some(Watchpoint {
+                address: self.counter,
+                size: 4,
+                access: WatchAccess::Write,
+                output: DataTraceOutput::DataAndPc,
+            }),
+            Some(Watchpoint {
+                address: self.counter,
+                size: 4,
+                access: WatchAccess::Read,
+                output: DataTraceOutput::Data,
+            }),
+            Some(Watchpoint {
+                address: self.halfword,
+                size: 2,
+                access: WatchAccess::ReadWrite,
+                output: DataTraceOutput::AddressOffset,
+            }),
+            Some(Watchpoint {
+                address: self.byte,
+                size: 1,
+                access: WatchAccess::ReadWrite,
+                output: DataTraceOutput::Pc,
+            }),
+        ]
+    }
+}
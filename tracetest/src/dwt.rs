@@ -0,0 +1,287 @@
+//! DWT packet sources
+//!
+//! [`DwtConfig`] names each of the `DWT_CTRL` features that produce packets, so that
+//! synchronisation, PC sampling, exception trace and the event counters can be turned
+//! on independently. It also holds the [`Watchpoint`]s that turn the DWT comparators
+//! into data trace sources.
+
+use crate::regs::{DwtCtrl, DwtFunction, DWT_COMPARATORS};
+
+/// Which CYCCNT bit clocks the POSTCNT timer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CycTap {
+    /// POSTCNT counts down every 64 cycles
+    Bit6,
+    /// POSTCNT counts down every 1024 cycles
+    Bit10,
+}
+
+impl CycTap {
+    /// Cycles per POSTCNT tick
+    pub fn cycles(self) -> u32 {
+        match self {
+            CycTap::Bit6 => 1 << 6,
+            CycTap::Bit10 => 1 << 10,
+        }
+    }
+}
+
+/// What the setup does with one enable bit of `DWT_CTRL`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Enable {
+    /// Leave the bit as it was before setup
+    #[default]
+    Keep,
+    On,
+    Off,
+}
+
+impl Enable {
+    /// The bit's new value, given its value before setup
+    pub fn apply(self, current: bool) -> bool {
+        match self {
+            Enable::Keep => current,
+            Enable::On => true,
+            Enable::Off => false,
+        }
+    }
+}
+
+impl From<bool> for Enable {
+    fn from(on: bool) -> Self {
+        if on {
+            Enable::On
+        } else {
+            Enable::Off
+        }
+    }
+}
+
+/// Which CYCCNT bit drives synchronisation packets
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum SyncTap {
+    /// Leave SYNCTAP as it was before setup
+    #[default]
+    Keep,
+    Disabled,
+    Bit24,
+    Bit26,
+    Bit28,
+}
+
+impl SyncTap {
+    /// Cycles between synchronisation packets, or `None` if they are off or left as
+    /// they were
+    pub fn period_cycles(self) -> Option<u32> {
+        match self {
+            SyncTap::Keep | SyncTap::Disabled => None,
+            SyncTap::Bit24 => Some(1 << 24),
+            SyncTap::Bit26 => Some(1 << 26),
+            SyncTap::Bit28 => Some(1 << 28),
+        }
+    }
+
+    /// Value for `DWT_CTRL.SYNCTAP`, given its value before setup
+    fn bits(self, current: u8) -> u8 {
+        match self {
+            SyncTap::Keep => current,
+            SyncTap::Disabled => 0,
+            SyncTap::Bit24 => 1,
+            SyncTap::Bit26 => 2,
+            SyncTap::Bit28 => 3,
+        }
+    }
+}
+
+/// Which accesses a [`Watchpoint`] traces
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchAccess {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// What a [`Watchpoint`] emits on a match
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataTraceOutput {
+    /// A data trace PC value packet. Only available for [`WatchAccess::ReadWrite`].
+    Pc,
+    /// A data trace address offset packet
+    AddressOffset,
+    /// A data trace data value packet
+    Data,
+    /// A PC value packet followed by a data value packet
+    DataAndPc,
+    /// An address offset packet followed by a data value packet
+    DataAndAddressOffset,
+}
+
+/// A DWT comparator set up to emit data trace when an address is accessed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Watchpoint {
+    pub address: u32,
+    /// Size of the watched region in bytes. Must be a power of two and the address must
+    /// be aligned to it.
+    pub size: u32,
+    pub access: WatchAccess,
+    pub output: DataTraceOutput,
+}
+
+impl Watchpoint {
+    /// Value for `DWT_MASKn`: the number of low address bits to ignore
+    pub fn mask(&self) -> u32 {
+        self.size.trailing_zeros()
+    }
+
+    /// Value for `DWT_FUNCTIONn`, or `None` if the combination is not supported
+    ///
+    /// Follows the ARMv7-M encoding of FUNCTION and EMITRANGE for data trace.
+    pub fn function(&self) -> Option<DwtFunction> {
+        use DataTraceOutput::*;
+        use WatchAccess::*;
+        let (function, emitrange) = match (self.access, self.output) {
+            (ReadWrite, Pc) => (0b0001, false),
+            (ReadWrite, AddressOffset) => (0b0001, true),
+            (ReadWrite, Data) => (0b0010, false),
+            (ReadWrite, DataAndAddressOffset) => (0b0010, true),
+            (ReadWrite, DataAndPc) => (0b0011, false),
+            (_, Pc) => return None,
+            (Read, Data) => (0b1100, false),
+            (Read, AddressOffset) => (0b1100, true),
+            (Write, Data) => (0b1101, false),
+            (Write, AddressOffset) => (0b1101, true),
+            (Read, DataAndPc) => (0b1110, false),
+            (Read, DataAndAddressOffset) => (0b1110, true),
+            (Write, DataAndPc) => (0b1111, false),
+            (Write, DataAndAddressOffset) => (0b1111, true),
+        };
+        Some(DwtFunction {
+            function,
+            emitrange,
+            ..DwtFunction::default()
+        })
+    }
+}
+
+/// Reasons a [`DwtConfig`] cannot be programmed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DwtConfigError {
+    /// Synchronisation, PC sampling and cycle events all run off CYCCNT
+    NeedsCycleCounter,
+    /// POSTPRESET is a 4-bit field
+    PostPresetOutOfRange(u8),
+    /// The watchpoint on this comparator has a size that is not a power of two, or an
+    /// address that is not aligned to it
+    MisalignedWatchpoint(usize),
+    /// The watchpoint on this comparator asks for an output the DWT cannot produce for
+    /// its access type
+    UnsupportedWatchpoint(usize),
+}
+
+/// The packet-generating features of `DWT_CTRL`
+///
+/// Each enable is an [`Enable`], so a source can be turned on, turned off, or left as a
+/// debugger had it. The default reproduces what the firmware has always programmed:
+/// POSTCNT tapped from CYCCNT bit 10 with a preset of 15, and every enable and SYNCTAP
+/// kept. In particular it does not turn CYCCNT or SYNCTAP on, so the DWT does not send
+/// periodic synchronisation packets unless a debugger had already asked for them. See
+/// [`DwtConfig::dwt_ctrl`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DwtConfig {
+    /// CYCCNTENA
+    pub cycle_counter: Enable,
+    /// CYCTAP
+    pub cyc_tap: CycTap,
+    /// POSTPRESET, also loaded into POSTINIT so the first period is full length
+    pub post_preset: u8,
+    /// SYNCTAP
+    pub sync_tap: SyncTap,
+    /// PCSAMPLENA
+    pub pc_sampling: Enable,
+    /// EXCTRCENA
+    pub exception_trace: Enable,
+    /// CPIEVTENA
+    pub cpi_events: Enable,
+    /// EXCEVTENA
+    pub exc_events: Enable,
+    /// SLEEPEVTENA
+    pub sleep_events: Enable,
+    /// LSUEVTENA
+    pub lsu_events: Enable,
+    /// FOLDEVTENA
+    pub fold_events: Enable,
+    /// Data trace watchpoints, one per comparator
+    pub watchpoints: [Option<Watchpoint>; DWT_COMPARATORS],
+}
+
+impl Default for DwtConfig {
+    fn default() -> Self {
+        DwtConfig {
+            cycle_counter: Enable::Keep,
+            cyc_tap: CycTap::Bit10,
+            post_preset: 15,
+            sync_tap: SyncTap::Keep,
+            pc_sampling: Enable::Keep,
+            exception_trace: Enable::Keep,
+            cpi_events: Enable::Keep,
+            exc_events: Enable::Keep,
+            sleep_events: Enable::Keep,
+            lsu_events: Enable::Keep,
+            fold_events: Enable::Keep,
+            watchpoints: [None; DWT_COMPARATORS],
+        }
+    }
+}
+
+impl DwtConfig {
+    pub fn validate(&self) -> Result<(), DwtConfigError> {
+        if self.post_preset > 0xf {
+            return Err(DwtConfigError::PostPresetOutOfRange(self.post_preset));
+        }
+        /* Left as it was, the cycle counter may well be off */
+        let sources = self.sync_tap.period_cycles().is_some() || self.pc_sampling == Enable::On;
+        if self.cycle_counter != Enable::On && sources {
+            return Err(DwtConfigError::NeedsCycleCounter);
+        }
+        for (index, watchpoint) in self.watchpoints.iter().enumerate() {
+            if let Some(watchpoint) = watchpoint {
+                if !watchpoint.size.is_power_of_two() || watchpoint.address % watchpoint.size != 0 {
+                    return Err(DwtConfigError::MisalignedWatchpoint(index));
+                }
+                if watchpoint.function().is_none() {
+                    return Err(DwtConfigError::UnsupportedWatchpoint(index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cycles between PC samples when [`DwtConfig::pc_sampling`] is on
+    pub fn pc_sample_period(&self) -> u32 {
+        self.cyc_tap.cycles() * (self.post_preset as u32 + 1)
+    }
+
+    /// Apply this configuration on top of the `DWT_CTRL` value found before setup
+    ///
+    /// The POSTCNT fields and CYCTAP are always overwritten. The cycle counter, SYNCTAP
+    /// and the packet sources are set or cleared as the configuration says, and the ones
+    /// it keeps are left as they were, so a cycle counter or event counters that a
+    /// debugger already started keep running.
+    pub fn dwt_ctrl(&self, current: DwtCtrl) -> DwtCtrl {
+        DwtCtrl {
+            cyccntena: self.cycle_counter.apply(current.cyccntena),
+            postpreset: self.post_preset,
+            postinit: self.post_preset,
+            cyctap: self.cyc_tap == CycTap::Bit10,
+            synctap: self.sync_tap.bits(current.synctap),
+            pcsamplena: self.pc_sampling.apply(current.pcsamplena),
+            exctrcena: self.exception_trace.apply(current.exctrcena),
+            cpievtena: self.cpi_events.apply(current.cpievtena),
+            excevtena: self.exc_events.apply(current.excevtena),
+            sleepevtena: self.sleep_events.apply(current.sleepevtena),
+            lsuevtena: self.lsu_events.apply(current.lsuevtena),
+            foldevtena: self.fold_events.apply(current.foldevtena),
+            ..current
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! DWT event counter exercise
+//!
+//! The five 8-bit DWT event counters each emit an event counter packet when they wrap.
+//! The firmware enables one counter at a time, clears all of them, and runs a workload
+//! that drives that counter hard:
+//!
+//! | counter | workload                                              |
+//! |---------|-------------------------------------------------------|
+//! | CPI     | back-to-back integer divides                          |
+//! | EXC     | a storm of software-pended interrupts                 |
+//! | SLEEP   | WFI until the next timer tick                         |
+//! | LSU     | volatile loads and stores over a buffer               |
+//! | FOLD    | a loop of IT blocks, whose IT instructions are folded |
+//!
+//! Because only one counter is enabled, every event counter packet in a phase should
+//! carry just that counter's bit. Each phase starts with a [`Marker`] on
+//! [`EVENT_COUNTER_PORT`] and lasts the number of ticks given in it.
+
+use crate::dwt::DwtConfig;
+use crate::regs::Register;
+
+pub const EVENT_COUNTER_PORT: usize = 0;
+
+/// First word of every marker, "EVC1" in little-endian order
+pub const MARKER_MAGIC: u32 = u32::from_le_bytes(*b"EVC1");
+
+/// One of the DWT event counters
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EventCounter {
+    Cpi,
+    Exc,
+    Sleep,
+    Lsu,
+    Fold,
+}
+
+impl EventCounter {
+    /// Every counter, in the order the firmware exercises them
+    pub const ALL: [EventCounter; 5] = [
+        EventCounter::Cpi,
+        EventCounter::Exc,
+        EventCounter::Sleep,
+        EventCounter::Lsu,
+        EventCounter::Fold,
+    ];
+
+    /// This counter's bit in the event counter packet payload
+    pub fn packet_bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    /// The counter register itself
+    pub fn register(self) -> Register {
+        match self {
+            EventCounter::Cpi => Register::DwtCpicnt,
+            EventCounter::Exc => Register::DwtExccnt,
+            EventCounter::Sleep => Register::DwtSleepcnt,
+            EventCounter::Lsu => Register::DwtLsucnt,
+            EventCounter::Fold => Register::DwtFoldcnt,
+        }
+    }
+
+    /// `base` with only this counter enabled
+    pub fn dwt_config(self, base: &DwtConfig) -> DwtConfig {
+        DwtConfig {
+            cpi_events: (self == EventCounter::Cpi).into(),
+            exc_events: (self == EventCounter::Exc).into(),
+            sleep_events: (self == EventCounter::Sleep).into(),
+            lsu_events: (self == EventCounter::Lsu).into(),
+            fold_events: (self == EventCounter::Fold).into(),
+            ..*base
+        }
+    }
+}
+
+/// Announces the start of a phase
+///
+/// Encoded as three words: [`MARKER_MAGIC`], the counter's packet bit, and the number of
+/// ticks the phase lasts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Marker {
+    pub counter: EventCounter,
+    pub ticks: u32,
+}
+
+impl Marker {
+    pub fn words(&self) -> [u32; 3] {
+        [MARKER_MAGIC, self.counter.packet_bit() as u32, self.ticks]
+    }
+}
@@ -0,0 +1,115 @@
+//! Exception trace exercise
+//!
+//! With `DWT_CTRL.EXCTRCENA` set, the DWT emits a hardware-source packet each time an
+//! exception is entered, exited or returned to. The firmware runs the same round of
+//! interrupts once per tick, so the order of those packets is fixed. Before each round
+//! it writes [`DESCRIPTOR_MAGIC`], the round number and the number of events as 32-bit
+//! writes to [`EXCEPTION_PORT`], then each event of [`ROUND`] as a 16-bit write. Each
+//! 16-bit value is encoded exactly like the payload of the matching exception trace
+//! packet, so the host can compare them directly.
+//!
+//! A round has three phases:
+//!
+//! 1. Nesting. EXTI0 is pended from thread mode and its handler pends the higher
+//!    priority EXTI1, which preempts it.
+//! 2. Tail-chaining. EXTI1, EXTI0 and PendSV are pended with interrupts masked. When
+//!    they are unmasked they run back to back in priority order without returning to
+//!    thread mode in between.
+//! 3. Hardware timers. TIM2 fires once and starts TIM3, which has a higher priority and
+//!    preempts TIM2's handler.
+
+use crate::itm::{Payload, Stimulus};
+
+use self::ExceptionEvent::{Enter, Exit, Return};
+
+/// Exception number of PendSV
+pub const PENDSV: u16 = 14;
+/// Exception number of EXTI0 (IRQ 6 on the STM32F103)
+pub const EXTI0: u16 = 16 + 6;
+/// Exception number of EXTI1 (IRQ 7 on the STM32F103)
+pub const EXTI1: u16 = 16 + 7;
+/// Exception number of TIM2 (IRQ 28 on the STM32F103)
+pub const TIM2: u16 = 16 + 28;
+/// Exception number of TIM3 (IRQ 29 on the STM32F103)
+pub const TIM3: u16 = 16 + 29;
+/// "Exception number" reported when returning to thread mode
+pub const THREAD: u16 = 0;
+
+/// NVIC priorities, in the upper four bits that the STM32F1 implements
+pub const EXTI1_PRIORITY: u8 = 0x40;
+pub const TIM3_PRIORITY: u8 = 0x60;
+pub const TIM2_PRIORITY: u8 = 0xa0;
+pub const EXTI0_PRIORITY: u8 = 0xc0;
+pub const PENDSV_PRIORITY: u8 = 0xe0;
+
+pub const EXCEPTION_PORT: usize = 0;
+
+/// First word of every round description, "EXC1" in little-endian order
+pub const DESCRIPTOR_MAGIC: u32 = u32::from_le_bytes(*b"EXC1");
+
+/// One exception trace event
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ExceptionEvent {
+    Enter(u16),
+    Exit(u16),
+    Return(u16),
+}
+
+impl ExceptionEvent {
+    /// The two payload bytes of the exception trace packet, as a little-endian value
+    pub fn payload(self) -> u16 {
+        let (function, number) = match self {
+            Enter(number) => (1, number),
+            Exit(number) => (2, number),
+            Return(number) => (3, number),
+        };
+        (number & 0x1ff) | function << 12
+    }
+
+    /// Decode an exception trace packet payload
+    pub fn from_payload(payload: u16) -> Option<Self> {
+        let number = payload & 0x1ff;
+        match (payload >> 12) & 0x3 {
+            1 => Some(Enter(number)),
+            2 => Some(Exit(number)),
+            3 => Some(Return(number)),
+            _ => None,
+        }
+    }
+}
+
+/// Every exception trace event of one round, in order
+pub const ROUND: [ExceptionEvent; 19] = [
+    /* Nesting */
+    Enter(EXTI0),
+    Enter(EXTI1),
+    Exit(EXTI1),
+    Return(EXTI0),
+    Exit(EXTI0),
+    Return(THREAD),
+    /* Tail-chaining */
+    Enter(EXTI1),
+    Exit(EXTI1),
+    Enter(EXTI0),
+    Exit(EXTI0),
+    Enter(PENDSV),
+    Exit(PENDSV),
+    Return(THREAD),
+    /* Hardware timers */
+    Enter(TIM2),
+    Enter(TIM3),
+    Exit(TIM3),
+    Return(TIM2),
+    Exit(TIM2),
+    Return(THREAD),
+];
+
+/// Write the description of round number `round` to [`EXCEPTION_PORT`]
+pub fn describe_round(out: &mut impl Stimulus, round: u32) {
+    for word in [DESCRIPTOR_MAGIC, round, ROUND.len() as u32].iter() {
+        out.write(EXCEPTION_PORT, Payload::U32(*word));
+    }
+    for event in ROUND.iter() {
+        out.write(EXCEPTION_PORT, Payload::U16(event.payload()));
+    }
+}
@@ -0,0 +1,55 @@
+//! TPIU formatter exercise
+//!
+//! With the formatter on, the TPIU wraps the trace in 16-byte frames. Each frame starts
+//! on a 32-bit boundary and interleaves ID-change bytes with up to 15 bytes of data, so
+//! the host has to find frame alignment before any of the ITM stream makes sense. The
+//! TPIU also sends a full frame synchronisation packet, `0x7fff_ffff`, now and then.
+//!
+//! The firmware steps the ITM through each ID in [`TRACE_IDS`]. For each ID it
+//! reconfigures the ITM, writes a [`marker`] to [`FORMATTER_PORT`], and then writes
+//! [`PATTERN_LEN`] single bytes given by [`pattern_byte`]. The ETM is given
+//! [`ETM_TRACE_ID`] but left powered down, so no data should ever arrive under that ID.
+//!
+//! A phase is 10 bytes of marker packets plus 512 bytes of pattern packets, which is not
+//! a multiple of 15, so each ID change lands at a different offset within a frame.
+//! Every byte received under one ID must belong to the phase announced by the marker
+//! most recently received under that ID.
+
+use crate::itm::{Payload, Stimulus};
+
+pub const FORMATTER_PORT: usize = 0;
+
+/// First word of every marker, "FMT1" in little-endian order
+pub const MARKER_MAGIC: u32 = u32::from_le_bytes(*b"FMT1");
+
+/// ITM trace IDs in the order the firmware steps through them
+///
+/// These cover the lowest and highest legal IDs and IDs with one bit set in each half of
+/// the ID byte.
+pub const TRACE_IDS: [u8; 4] = [0x01, 0x02, 0x10, 0x6f];
+
+/// ID given to the ETM, which should never appear in the stream
+pub const ETM_TRACE_ID: u8 = 0x20;
+
+/// Number of single-byte writes that follow each marker
+pub const PATTERN_LEN: usize = 256;
+
+/// The marker that starts the phase for `id`
+pub fn marker(id: u8) -> [u32; 2] {
+    [MARKER_MAGIC, id as u32]
+}
+
+/// Byte `n` of the pattern sent after each marker
+pub fn pattern_byte(n: usize) -> u8 {
+    n as u8
+}
+
+/// Make the writes for the phase under `id`: its [`marker`], then the pattern
+pub fn send_phase(out: &mut impl Stimulus, id: u8) {
+    for word in marker(id).iter() {
+        out.write(FORMATTER_PORT, Payload::U32(*word));
+    }
+    for n in 0..PATTERN_LEN {
+        out.write(FORMATTER_PORT, Payload::U8(pattern_byte(n)));
+    }
+}
@@ -0,0 +1,339 @@
+//! Sequence-numbered message framing
+//!
+//! A bare stimulus write carries no way to tell that an earlier one went missing. A
+//! [`FramedPort`] wraps each message in a frame that the host can check with a
+//! [`Deframer`]:
+//!
+//! | part    | packet  | contents                                                  |
+//! |---------|---------|-----------------------------------------------------------|
+//! | header  | 4 bytes | [`FRAME_MAGIC`], payload length, 16-bit sequence number   |
+//! | payload | any     | the message, as by [`ItmPort::write_all`]                 |
+//! | CRC     | 4 bytes | CRC-32 of the four header bytes and the payload           |
+//!
+//! Sequence numbers count up from 0 separately on each port, so a gap tells the host
+//! exactly how many frames were dropped on that port. Each [`FramedPort`] also counts the
+//! messages it attempted, which the firmware reports as [`Report`]s on [`REPORT_PORT`]
+//! so the two sides' totals can be reconciled.
+
+use crate::hello::{counter_word, COUNTER_PORTS, HELLO_MESSAGE, HELLO_PORT};
+use crate::itm::{ItmPort, Payload, Stimulus, TraceWrite};
+
+/// First byte of every frame header
+pub const FRAME_MAGIC: u8 = 0xf5;
+
+/// Longest payload that fits in a frame
+pub const MAX_PAYLOAD: usize = 255;
+
+/// Port that carries the firmware's per-port [`Report`]s
+pub const REPORT_PORT: usize = 4;
+
+/// First word of every report, "FRM1" in little-endian order
+pub const REPORT_MAGIC: u32 = u32::from_le_bytes(*b"FRM1");
+
+/// Running CRC-32, as used by Ethernet and zlib
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    pub fn new() -> Self {
+        Crc32(0xffff_ffff)
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (self.0 & 1).wrapping_neg();
+                self.0 = (self.0 >> 1) ^ (0xedb8_8320 & mask);
+            }
+        }
+    }
+
+    pub fn finish(self) -> u32 {
+        !self.0
+    }
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The header word of a frame
+pub fn header(seq: u16, len: u8) -> u32 {
+    u32::from_le_bytes([FRAME_MAGIC, len, seq as u8, (seq >> 8) as u8])
+}
+
+/// The CRC word that ends a frame with this header and payload
+pub fn crc(header: u32, payload: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(&header.to_le_bytes());
+    crc.update(payload);
+    crc.finish()
+}
+
+/// Sequence number and message count for one port's frames
+///
+/// This is the state behind a [`FramedPort`], for writers that are not an [`ItmPort`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Framer {
+    seq: u16,
+    attempted: u32,
+}
+
+impl Framer {
+    pub fn new() -> Self {
+        Framer {
+            seq: 0,
+            attempted: 0,
+        }
+    }
+
+    /// Send `payload` to `out` as one frame. Anything past [`MAX_PAYLOAD`] bytes is cut
+    /// off.
+    pub fn send(&mut self, out: &mut impl TraceWrite, payload: &[u8]) {
+        let payload = &payload[..payload.len().min(MAX_PAYLOAD)];
+        let header = header(self.seq, payload.len() as u8);
+        out.write_u32(header);
+        out.write_all(payload);
+        out.write_u32(crc(header, payload));
+        self.seq = self.seq.wrapping_add(1);
+        self.attempted = self.attempted.wrapping_add(1);
+    }
+
+    /// Messages passed to [`Framer::send`] so far, wrapping
+    pub fn attempted(&self) -> u32 {
+        self.attempted
+    }
+
+    /// The totals for stimulus port `port`, to be sent on [`REPORT_PORT`]
+    pub fn report(&self, port: usize) -> Report {
+        Report {
+            port: port as u32,
+            attempted: self.attempted,
+        }
+    }
+}
+
+/// Writer that frames every message sent on stimulus port `N`
+pub struct FramedPort<const N: usize> {
+    port: ItmPort<N>,
+    framer: Framer,
+}
+
+impl<const N: usize> FramedPort<N> {
+    pub fn new(port: ItmPort<N>) -> Self {
+        FramedPort {
+            port,
+            framer: Framer::new(),
+        }
+    }
+
+    /// Send `payload` as one frame. Anything past [`MAX_PAYLOAD`] bytes is cut off.
+    pub fn send(&mut self, payload: &[u8]) {
+        self.framer.send(&mut self.port, payload)
+    }
+
+    /// Messages passed to [`FramedPort::send`] so far, wrapping
+    pub fn attempted(&self) -> u32 {
+        self.framer.attempted()
+    }
+
+    /// This port's totals, to be sent on [`REPORT_PORT`]
+    pub fn report(&self) -> Report {
+        self.framer.report(N)
+    }
+
+    /// Hand back the underlying port
+    pub fn into_inner(self) -> ItmPort<N> {
+        self.port
+    }
+}
+
+/// Make the writes for tick `tick` of the framed hello-world loop: one frame with the
+/// greeting, one with the first counter's [`counter_word`], then both ports' [`Report`]s
+///
+/// `hello` and `counter` carry the frame state of [`HELLO_PORT`] and the first of
+/// [`COUNTER_PORTS`] from one tick to the next.
+pub fn send_tick(out: &mut impl Stimulus, hello: &mut Framer, counter: &mut Framer, tick: u32) {
+    let counter_port = COUNTER_PORTS[0];
+    hello.send(&mut out.port(HELLO_PORT), HELLO_MESSAGE.as_bytes());
+    counter.send(
+        &mut out.port(counter_port),
+        &counter_word(counter_port, tick).to_le_bytes(),
+    );
+    for report in [hello.report(HELLO_PORT), counter.report(counter_port)].iter() {
+        for word in report.words().iter() {
+            out.write(REPORT_PORT, Payload::U32(*word));
+        }
+    }
+}
+
+/// The firmware's count of messages it tried to send on one port
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Report {
+    pub port: u32,
+    pub attempted: u32,
+}
+
+impl Report {
+    pub fn words(&self) -> [u32; 3] {
+        [REPORT_MAGIC, self.port, self.attempted]
+    }
+
+    pub fn from_words(words: &[u32; 3]) -> Option<Self> {
+        if words[0] != REPORT_MAGIC {
+            return None;
+        }
+        Some(Report {
+            port: words[1],
+            attempted: words[2],
+        })
+    }
+}
+
+/// Totals kept by a [`Deframer`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Stats {
+    /// Frames that passed their CRC
+    pub frames: u32,
+    /// Frames missing from gaps in the sequence numbers, including the corrupted ones
+    pub dropped: u32,
+    /// Times a frame failed its CRC. Searching the damaged bytes again can hit false
+    /// starts before the next good frame, which are not counted again.
+    pub corrupted: u32,
+    /// Frames with a sequence number behind the one expected
+    pub reordered: u32,
+}
+
+/// A frame that passed its CRC
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frame<'a> {
+    pub seq: u16,
+    pub payload: &'a [u8],
+}
+
+/// Header, longest payload and CRC
+const MAX_FRAME: usize = 4 + MAX_PAYLOAD + 4;
+
+/// Reassembles the frames sent on one port from its payload bytes
+///
+/// The bytes of the frame being received are kept until its CRC has been checked. If it
+/// fails, they are searched again from the byte after its [`FRAME_MAGIC`], since a lost
+/// packet can leave the start of the next frame inside the one being read. So one loss
+/// costs at most the frames it touched. Finding a frame that way can leave further bytes
+/// behind it, which later calls to [`Deframer::push`] go through first.
+pub struct Deframer {
+    /// The frame being received, starting with [`FRAME_MAGIC`] unless empty
+    buffer: [u8; MAX_FRAME],
+    len: usize,
+    /// Length of the frame returned last, taken off the buffer at the next call
+    returned: usize,
+    /// A frame has failed its CRC since the last good one
+    hunting: bool,
+    next_seq: Option<u16>,
+    stats: Stats,
+}
+
+impl Deframer {
+    pub fn new() -> Self {
+        Deframer {
+            buffer: [0; MAX_FRAME],
+            len: 0,
+            returned: 0,
+            hunting: false,
+            next_seq: None,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Take the next payload byte from the port, returning a frame once one is complete
+    pub fn push(&mut self, byte: u8) -> Option<Frame<'_>> {
+        self.discard(self.returned);
+        self.returned = 0;
+        self.buffer[self.len] = byte;
+        self.len += 1;
+        self.next_frame()
+    }
+
+    /// A frame that is already complete in the buffer, without taking another byte
+    ///
+    /// Searching again after a CRC failure can find more than one frame, which
+    /// [`Deframer::push`] returns one call at a time. Call this at the end of a capture
+    /// until it returns `None` to get the rest.
+    pub fn next_frame(&mut self) -> Option<Frame<'_>> {
+        self.discard(self.returned);
+        self.returned = 0;
+        loop {
+            /* Hunt for the start of a frame */
+            let skip = self.buffer[..self.len]
+                .iter()
+                .position(|byte| *byte == FRAME_MAGIC)
+                .unwrap_or(self.len);
+            self.discard(skip);
+            if self.len < 4 {
+                return None;
+            }
+            let frame_len = 4 + self.buffer[1] as usize + 4;
+            if self.len < frame_len {
+                return None;
+            }
+
+            let header = u32::from_le_bytes([
+                self.buffer[0],
+                self.buffer[1],
+                self.buffer[2],
+                self.buffer[3],
+            ]);
+            let payload = &self.buffer[4..frame_len - 4];
+            let mut crc_bytes = [0; 4];
+            crc_bytes.copy_from_slice(&self.buffer[frame_len - 4..frame_len]);
+            if crc(header, payload) == u32::from_le_bytes(crc_bytes) {
+                self.returned = frame_len;
+                self.hunting = false;
+                let seq = (header >> 16) as u16;
+                return Some(self.accept(seq, frame_len));
+            }
+            /* Search again from the byte after this frame's magic */
+            if !self.hunting {
+                self.stats.corrupted += 1;
+                self.hunting = true;
+            }
+            self.discard(1);
+        }
+    }
+
+    /// Count the frame at the start of the buffer, `frame_len` bytes long
+    fn accept(&mut self, seq: u16, frame_len: usize) -> Frame<'_> {
+        let payload = &self.buffer[4..frame_len - 4];
+        self.stats.frames += 1;
+        if let Some(next_seq) = self.next_seq {
+            let gap = seq.wrapping_sub(next_seq);
+            if gap >= 0x8000 {
+                /* Keep expecting the frame after the newest one seen */
+                self.stats.reordered += 1;
+                return Frame { seq, payload };
+            }
+            self.stats.dropped += gap as u32;
+        }
+        self.next_seq = Some(seq.wrapping_add(1));
+        Frame { seq, payload }
+    }
+
+    /// Take `count` bytes off the front of the buffer
+    fn discard(&mut self, count: usize) {
+        self.buffer.copy_within(count..self.len, 0);
+        self.len -= count;
+    }
+}
+
+impl Default for Deframer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
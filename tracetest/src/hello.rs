@@ -0,0 +1,51 @@
+//! The default hello-world loop
+//!
+//! With no scenario feature enabled, the firmware alternates between two kinds of tick:
+//!
+//! 1. [`HELLO_MESSAGE`] on [`HELLO_PORT`], with the LED on
+//! 2. the single byte [`HELLO_BYTE`] on [`HELLO_PORT`], with the LED off
+//!
+//! After either kind, each port in [`COUNTER_PORTS`] gets a 32-bit [`counter_word`]
+//! carrying its own port number and the tick count. There is one counter port in each
+//! privilege group, so a host demultiplexer that puts data on the wrong channel, or
+//! drops a port above 7, shows up straight away.
+
+use crate::itm::{Payload, Stimulus, TraceWrite};
+
+/// Stimulus port for the greeting
+pub const HELLO_PORT: usize = 0;
+
+/// Sent on the first tick of every pair
+pub const HELLO_MESSAGE: &str = "Hello, world!\n";
+
+/// Sent on the second tick of every pair
+pub const HELLO_BYTE: u8 = 0x55;
+
+/// Ports that get a counter word every tick
+pub const COUNTER_PORTS: [usize; 4] = [1, 8, 16, 24];
+
+/// Every port used by the loop, as a stimulus port enable mask
+pub const STIMULUS_PORTS: u32 = 1 << HELLO_PORT
+    | 1 << COUNTER_PORTS[0]
+    | 1 << COUNTER_PORTS[1]
+    | 1 << COUNTER_PORTS[2]
+    | 1 << COUNTER_PORTS[3];
+
+/// The word sent on `port` after tick `tick`: the port number in the top byte and the
+/// low 24 bits of the tick count below it
+pub fn counter_word(port: usize, tick: u32) -> u32 {
+    (port as u32) << 24 | (tick & 0x00ff_ffff)
+}
+
+/// Make the writes for tick `tick`: the greeting on even ticks and [`HELLO_BYTE`] on
+/// odd ones, then a [`counter_word`] on each of [`COUNTER_PORTS`]
+pub fn send_tick(out: &mut impl Stimulus, tick: u32) {
+    if tick & 1 == 0 {
+        out.port(HELLO_PORT).write_all(HELLO_MESSAGE.as_bytes());
+    } else {
+        out.write(HELLO_PORT, Payload::U8(HELLO_BYTE));
+    }
+    for port in COUNTER_PORTS.iter() {
+        out.write(*port, Payload::U32(counter_word(*port, tick)));
+    }
+}
@@ -0,0 +1,236 @@
+//! Per-port ITM writers
+//!
+//! Each of the 32 stimulus ports can be claimed once as an [`ItmPort`], so different
+//! parts of the firmware can each own their own channel without sharing the whole ITM.
+//! Whether a port's writes actually reach the wire is still up to the enable and
+//! privilege masks in [`SwoConfig`](crate::config::SwoConfig).
+
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Number of ITM stimulus ports
+pub const STIMULUS_PORTS: usize = 32;
+
+/// Address of stimulus port 0. The others follow at 4-byte intervals.
+const ITM_STIM_BASE: usize = 0xE000_0000;
+
+/// Stimulus ports that have already been handed out by [`ItmPort::take`]
+static TAKEN: AtomicU32 = AtomicU32::new(0);
+
+/// The value and width of one stimulus write
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Payload {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+}
+
+impl Payload {
+    /// Number of payload bytes
+    pub fn size(self) -> usize {
+        match self {
+            Payload::U8(_) => 1,
+            Payload::U16(_) => 2,
+            Payload::U32(_) => 4,
+        }
+    }
+
+    /// Value of the instrumentation packet header's size field
+    pub fn size_bits(self) -> u8 {
+        match self {
+            Payload::U8(_) => 0b01,
+            Payload::U16(_) => 0b10,
+            Payload::U32(_) => 0b11,
+        }
+    }
+
+    /// The value, zero-extended to 32 bits
+    pub fn value(self) -> u32 {
+        match self {
+            Payload::U8(v) => v as u32,
+            Payload::U16(v) => v as u32,
+            Payload::U32(v) => v,
+        }
+    }
+}
+
+/// The writer API shared by [`ItmPort`] and the RTT up-channels in
+/// [`rtt`](crate::rtt), so that the same payloads can be sent over either transport
+pub trait TraceWrite {
+    fn write_u8(&mut self, value: u8);
+    fn write_u16(&mut self, value: u16);
+    fn write_u32(&mut self, value: u32);
+
+    /// Send a payload with the width it was given
+    fn write_payload(&mut self, payload: Payload) {
+        match payload {
+            Payload::U8(value) => self.write_u8(value),
+            Payload::U16(value) => self.write_u16(value),
+            Payload::U32(value) => self.write_u32(value),
+        }
+    }
+
+    /// Send a byte string, four bytes at a time, then two, then one
+    fn write_all(&mut self, bytes: &[u8]) {
+        for payload in payloads(bytes) {
+            self.write_payload(payload);
+        }
+    }
+}
+
+/// The writes that make up `bytes`: four bytes at a time, then two, then one
+pub fn payloads(bytes: &[u8]) -> impl Iterator<Item = Payload> + '_ {
+    let words = bytes.chunks_exact(4);
+    let rest = words.remainder();
+    let (half, byte) = if rest.len() >= 2 {
+        (Some(&rest[..2]), rest.get(2))
+    } else {
+        (None, rest.first())
+    };
+    words
+        .map(|word| Payload::U32(u32::from_le_bytes([word[0], word[1], word[2], word[3]])))
+        .chain(half.map(|half| Payload::U16(u16::from_le_bytes([half[0], half[1]]))))
+        .chain(byte.map(|byte| Payload::U8(*byte)))
+}
+
+/// Writes to stimulus ports picked at run time
+///
+/// The fixed output of each scenario is written against this by a function in the
+/// scenario's module, such as [`hello::send_tick`](crate::hello::send_tick). The firmware
+/// implements it on the ITM, and the golden trace generator in `fixtures/` implements it
+/// by encoding packets, so the checked-in traces are made by the same code as the
+/// firmware's.
+pub trait Stimulus {
+    fn write(&mut self, port: usize, payload: Payload);
+
+    /// Send a byte string to `port`, split up as [`payloads`] does. Implementations
+    /// that also pass the bytes on somewhere else can override this to see the whole
+    /// string at once.
+    fn write_all(&mut self, port: usize, bytes: &[u8]) {
+        for payload in payloads(bytes) {
+            self.write(port, payload);
+        }
+    }
+
+    /// A [`TraceWrite`] that sends everything to `port`
+    fn port(&mut self, port: usize) -> PortWriter<'_, Self>
+    where
+        Self: Sized,
+    {
+        PortWriter { out: self, port }
+    }
+}
+
+/// One port of a [`Stimulus`], from [`Stimulus::port`]
+pub struct PortWriter<'a, S> {
+    out: &'a mut S,
+    port: usize,
+}
+
+impl<S: Stimulus> TraceWrite for PortWriter<'_, S> {
+    fn write_u8(&mut self, value: u8) {
+        self.out.write(self.port, Payload::U8(value))
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.out.write(self.port, Payload::U16(value))
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.out.write(self.port, Payload::U32(value))
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) {
+        Stimulus::write_all(self.out, self.port, bytes)
+    }
+}
+
+/// Exclusive writer for stimulus port `N`
+///
+/// Every write waits for room in the ITM FIFO first. If the ITM is disabled the FIFO
+/// never reports room, so only write once [`swo_setup`](crate::swo::swo_setup) has run.
+pub struct ItmPort<const N: usize> {
+    _private: (),
+}
+
+impl<const N: usize> ItmPort<N> {
+    const IN_RANGE: () = assert!(N < STIMULUS_PORTS, "the ITM has 32 stimulus ports");
+
+    /// Claim port `N`. Returns `None` if it has already been claimed.
+    pub fn take() -> Option<Self> {
+        let () = Self::IN_RANGE;
+        let bit = 1 << N;
+        if TAKEN.fetch_or(bit, Ordering::AcqRel) & bit != 0 {
+            return None;
+        }
+        Some(ItmPort { _private: () })
+    }
+
+    /// The stimulus port number
+    pub const fn port(&self) -> usize {
+        N
+    }
+
+    fn address(&self) -> usize {
+        ITM_STIM_BASE + 4 * N
+    }
+
+    /// Reading a stimulus port returns 1 when its FIFO can take another write
+    fn wait_ready(&self) {
+        // Safe because this is a read-only access to a stimulus port we own
+        while unsafe { ptr::read_volatile(self.address() as *const u32) } & 1 == 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Send one byte as a 1-byte stimulus packet
+    pub fn write_u8(&mut self, value: u8) {
+        self.wait_ready();
+        unsafe { ptr::write_volatile(self.address() as *mut u8, value) };
+    }
+
+    /// Send a halfword as a 2-byte stimulus packet
+    pub fn write_u16(&mut self, value: u16) {
+        self.wait_ready();
+        unsafe { ptr::write_volatile(self.address() as *mut u16, value) };
+    }
+
+    /// Send a word as a 4-byte stimulus packet
+    pub fn write_u32(&mut self, value: u32) {
+        self.wait_ready();
+        unsafe { ptr::write_volatile(self.address() as *mut u32, value) };
+    }
+
+    /// Send a byte string, four bytes to a packet, with the remainder in a 2-byte and
+    /// then a 1-byte packet
+    pub fn write_all(&mut self, bytes: &[u8]) {
+        TraceWrite::write_all(self, bytes)
+    }
+
+    /// Give the port back so that it can be claimed again
+    pub fn release(self) {
+        TAKEN.fetch_and(!(1 << N), Ordering::AcqRel);
+    }
+}
+
+impl<const N: usize> TraceWrite for ItmPort<N> {
+    fn write_u8(&mut self, value: u8) {
+        ItmPort::write_u8(self, value)
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        ItmPort::write_u16(self, value)
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        ItmPort::write_u32(self, value)
+    }
+}
+
+impl<const N: usize> fmt::Write for ItmPort<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_all(s.as_bytes());
+        Ok(())
+    }
+}
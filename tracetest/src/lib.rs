@@ -0,0 +1,33 @@
+//! SWO trace bring-up for the trace test firmware
+//!
+//! The STM32F103 firmware in `firmware/` uses this with [`regs::Mmio`]. The only part
+//! of the setup specific to it is claiming the trace pins through the STM32F1 DBGMCU,
+//! which the firmware opts into with [`regs::TracePins::Stm32f1Dbgmcu`]. Otherwise
+//! [`swo::swo_setup`] takes any [`regs::Registers`] and a [`config::SwoConfig`] built for
+//! the part's trace clock, so other Cortex-M firmware can depend on this crate for its
+//! own trace bring-up. Because nothing here depends on the target, the same setup code
+//! can also be driven against [`regs::MockRegisters`] on the host, and the host tools
+//! use the pattern definitions to check what they receive.
+
+#![no_std]
+
+pub mod bert;
+pub mod config;
+pub mod cpu;
+pub mod data_trace;
+pub mod dwt;
+pub mod event_counters;
+pub mod exceptions;
+pub mod formatter;
+pub mod framing;
+pub mod hello;
+pub mod itm;
+pub mod mailbox;
+pub mod pc_sampling;
+pub mod regs;
+pub mod rtt;
+pub mod saturation;
+pub mod sweep;
+pub mod swo;
+pub mod timestamps;
+pub mod widths;
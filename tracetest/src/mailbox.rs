@@ -0,0 +1,408 @@
+//! Host-to-target command mailbox
+//!
+//! The firmware exports a [`ControlBlock`] as the symbol `TRACETEST_MAILBOX`. It starts
+//! with [`MAILBOX_ID`] so that it can also be found by scanning RAM. To send a command, a
+//! debugger writes `command` and `args`, then increments `command_seq`. A command is
+//! pending whenever `command_seq` differs from `ack_seq`.
+//!
+//! | code | command                 | args                                        |
+//! |------|-------------------------|---------------------------------------------|
+//! | 1    | select scenario         | [`Scenario`] number                         |
+//! | 2    | set baud and protocol   | baud rate, 0 for Manchester or 1 for NRZ    |
+//! | 3    | set stimulus ports      | `ITM_TER` mask, or 0 for the scenario's own |
+//! | 4    | pause output            |                                             |
+//! | 5    | resume output           |                                             |
+//!
+//! Pause and resume take effect straight away, and stop and restart all trace output,
+//! not only the stimulus ports. Every other command is applied by resetting the core:
+//! the block lives in RAM that is not cleared at startup, so the firmware picks up the
+//! new [`Settings`] as it comes back up. Either way, once the command has taken effect
+//! the firmware writes `status` and then `ack_seq`, and sends an [`Ack`] on [`ACK_PORT`].
+//! No acknowledgement is sent on [`ACK_PORT`] while output is paused.
+//!
+//! Only the firmware's own reset carries the settings over. It sets `reset_token` to
+//! [`RESET_TOKEN`] just before resetting, and after any other reset, such as the one a
+//! debugger does after flashing a new image, the block starts again from the image's
+//! defaults.
+
+use core::cell::UnsafeCell;
+use core::ptr;
+
+use crate::swo::SwoProtocol;
+
+/// First 16 bytes of the control block
+pub const MAILBOX_ID: [u8; 16] = *b"TRACETEST MBOX1\0";
+
+/// Stimulus port that carries acknowledgements
+pub const ACK_PORT: usize = 29;
+
+/// First word of every acknowledgement, "ACK1" in little-endian order
+pub const ACK_MAGIC: u32 = u32::from_le_bytes(*b"ACK1");
+
+/// Left in `reset_token` by a reset the firmware asked for itself, "RST1" in
+/// little-endian order
+pub const RESET_TOKEN: u32 = u32::from_le_bytes(*b"RST1");
+
+/// What the firmware runs after setup, numbered as in the select scenario command
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scenario {
+    Hello,
+    Sweep,
+    Timestamps,
+    PcSampling,
+    Exceptions,
+    DataTrace,
+    EventCounters,
+    Formatter,
+    Widths,
+    Framing,
+    Saturation,
+    Bert,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 12] = [
+        Scenario::Hello,
+        Scenario::Sweep,
+        Scenario::Timestamps,
+        Scenario::PcSampling,
+        Scenario::Exceptions,
+        Scenario::DataTrace,
+        Scenario::EventCounters,
+        Scenario::Formatter,
+        Scenario::Widths,
+        Scenario::Framing,
+        Scenario::Saturation,
+        Scenario::Bert,
+    ];
+
+    pub fn from_u32(n: u32) -> Option<Scenario> {
+        Scenario::ALL.get(n as usize).copied()
+    }
+}
+
+/// Result of a command, as written to `status`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Ok,
+    UnknownCommand,
+    UnknownScenario,
+    UnknownProtocol,
+    /// The settings could not be turned into a valid configuration, for example because
+    /// the baud rate is out of range. The old settings stay in place.
+    InvalidConfig,
+}
+
+impl Status {
+    pub fn from_u32(n: u32) -> Option<Status> {
+        [
+            Status::Ok,
+            Status::UnknownCommand,
+            Status::UnknownScenario,
+            Status::UnknownProtocol,
+            Status::InvalidConfig,
+        ]
+        .get(n as usize)
+        .copied()
+    }
+}
+
+/// A decoded command
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    SelectScenario(Scenario),
+    SetBaud { baud: u32, protocol: SwoProtocol },
+    SetStimulusPorts(u32),
+    Pause,
+    Resume,
+}
+
+impl Command {
+    pub fn decode(code: u32, args: [u32; 3]) -> Result<Command, Status> {
+        match code {
+            1 => Scenario::from_u32(args[0])
+                .map(Command::SelectScenario)
+                .ok_or(Status::UnknownScenario),
+            2 => Ok(Command::SetBaud {
+                baud: args[0],
+                protocol: protocol_from_u32(args[1]).ok_or(Status::UnknownProtocol)?,
+            }),
+            3 => Ok(Command::SetStimulusPorts(args[0])),
+            4 => Ok(Command::Pause),
+            5 => Ok(Command::Resume),
+            _ => Err(Status::UnknownCommand),
+        }
+    }
+
+    /// The code and arguments a host writes for this command
+    pub fn encode(self) -> (u32, [u32; 3]) {
+        match self {
+            Command::SelectScenario(scenario) => (1, [scenario as u32, 0, 0]),
+            Command::SetBaud { baud, protocol } => (2, [baud, protocol_to_u32(protocol), 0]),
+            Command::SetStimulusPorts(mask) => (3, [mask, 0, 0]),
+            Command::Pause => (4, [0; 3]),
+            Command::Resume => (5, [0; 3]),
+        }
+    }
+}
+
+fn protocol_from_u32(n: u32) -> Option<SwoProtocol> {
+    match n {
+        0 => Some(SwoProtocol::Manchester),
+        1 => Some(SwoProtocol::Nrz),
+        _ => None,
+    }
+}
+
+fn protocol_to_u32(protocol: SwoProtocol) -> u32 {
+    match protocol {
+        SwoProtocol::Manchester => 0,
+        SwoProtocol::Nrz => 1,
+    }
+}
+
+/// The settings the firmware is running with, kept in the control block across resets
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    scenario: u32,
+    baud: u32,
+    protocol: u32,
+    stimulus_ports: u32,
+    paused: u32,
+}
+
+impl Settings {
+    pub fn new(scenario: Scenario, baud: u32, protocol: SwoProtocol) -> Self {
+        Settings {
+            scenario: scenario as u32,
+            baud,
+            protocol: protocol_to_u32(protocol),
+            stimulus_ports: 0,
+            paused: 0,
+        }
+    }
+
+    /// `None` if the block held something this firmware does not know
+    pub fn scenario(&self) -> Option<Scenario> {
+        Scenario::from_u32(self.scenario)
+    }
+
+    pub fn baud(&self) -> u32 {
+        self.baud
+    }
+
+    pub fn protocol(&self) -> Option<SwoProtocol> {
+        protocol_from_u32(self.protocol)
+    }
+
+    /// `ITM_TER` mask set by the host, or `None` to use the scenario's own
+    pub fn stimulus_ports(&self) -> Option<u32> {
+        match self.stimulus_ports {
+            0 => None,
+            mask => Some(mask),
+        }
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused != 0
+    }
+
+    /// These settings with `command` applied
+    pub fn with(mut self, command: Command) -> Self {
+        match command {
+            Command::SelectScenario(scenario) => self.scenario = scenario as u32,
+            Command::SetBaud { baud, protocol } => {
+                self.baud = baud;
+                self.protocol = protocol_to_u32(protocol);
+            }
+            Command::SetStimulusPorts(mask) => self.stimulus_ports = mask,
+            Command::Pause => self.paused = 1,
+            Command::Resume => self.paused = 0,
+        }
+        self
+    }
+}
+
+/// Layout of the mailbox in target memory
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ControlBlock {
+    pub id: [u8; 16],
+    /// Incremented by the host after writing `command` and `args`
+    pub command_seq: u32,
+    pub command: u32,
+    pub args: [u32; 3],
+    /// Set to `command_seq` by the firmware once the command has taken effect
+    pub ack_seq: u32,
+    /// [`Status`] of the last command acknowledged
+    pub status: u32,
+    pub settings: Settings,
+    /// [`RESET_TOKEN`] while the firmware is resetting to apply a command
+    pub reset_token: u32,
+}
+
+/// The firmware's side of the mailbox
+///
+/// Every access is volatile, since the debugger changes the block behind the
+/// firmware's back.
+#[repr(transparent)]
+pub struct Mailbox(UnsafeCell<ControlBlock>);
+
+// Safe because every access is a single volatile read or write of an aligned field
+unsafe impl Sync for Mailbox {}
+
+impl Mailbox {
+    pub const fn new() -> Self {
+        Mailbox(UnsafeCell::new(ControlBlock {
+            id: [0; 16],
+            command_seq: 0,
+            command: 0,
+            args: [0; 3],
+            ack_seq: 0,
+            status: 0,
+            settings: Settings {
+                scenario: 0,
+                baud: 0,
+                protocol: 0,
+                stimulus_ports: 0,
+                paused: 0,
+            },
+            reset_token: 0,
+        }))
+    }
+
+    fn block(&self) -> *mut ControlBlock {
+        self.0.get()
+    }
+
+    /// Whether the block has been set up since power-on. It holds garbage otherwise.
+    pub fn is_initialised(&self) -> bool {
+        unsafe { ptr::read_volatile(ptr::addr_of!((*self.block()).id)) == MAILBOX_ID }
+    }
+
+    /// Clear the block and start from `settings`
+    pub fn initialise(&self, settings: Settings) {
+        unsafe {
+            let block = self.block();
+            ptr::write_volatile(ptr::addr_of_mut!((*block).command_seq), 0);
+            ptr::write_volatile(ptr::addr_of_mut!((*block).ack_seq), 0);
+            ptr::write_volatile(ptr::addr_of_mut!((*block).status), Status::Ok as u32);
+            ptr::write_volatile(ptr::addr_of_mut!((*block).settings), settings);
+            ptr::write_volatile(ptr::addr_of_mut!((*block).reset_token), 0);
+            ptr::write_volatile(ptr::addr_of_mut!((*block).id), MAILBOX_ID);
+        }
+    }
+
+    /// Record that the next reset is one the firmware asked for, to apply a command
+    pub fn request_reset(&self) {
+        unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.block()).reset_token), RESET_TOKEN) }
+    }
+
+    /// Whether the last reset was asked for with [`Mailbox::request_reset`], clearing the
+    /// request. Always false if the block is not initialised.
+    pub fn take_reset_request(&self) -> bool {
+        if !self.is_initialised() {
+            return false;
+        }
+        unsafe {
+            let token = ptr::addr_of_mut!((*self.block()).reset_token);
+            let requested = ptr::read_volatile(token) == RESET_TOKEN;
+            ptr::write_volatile(token, 0);
+            requested
+        }
+    }
+
+    pub fn settings(&self) -> Settings {
+        unsafe { ptr::read_volatile(ptr::addr_of!((*self.block()).settings)) }
+    }
+
+    pub fn set_settings(&self, settings: Settings) {
+        unsafe { ptr::write_volatile(ptr::addr_of_mut!((*self.block()).settings), settings) }
+    }
+
+    /// The sequence number and decoded command, if one is waiting
+    pub fn pending(&self) -> Option<(u32, Result<Command, Status>)> {
+        unsafe {
+            let block = self.block();
+            let seq = ptr::read_volatile(ptr::addr_of!((*block).command_seq));
+            if seq == ptr::read_volatile(ptr::addr_of!((*block).ack_seq)) {
+                return None;
+            }
+            let code = ptr::read_volatile(ptr::addr_of!((*block).command));
+            let args = ptr::read_volatile(ptr::addr_of!((*block).args));
+            Some((seq, Command::decode(code, args)))
+        }
+    }
+
+    /// Report the command numbered `seq` as done
+    pub fn acknowledge(&self, seq: u32, status: Status) {
+        unsafe {
+            let block = self.block();
+            ptr::write_volatile(ptr::addr_of_mut!((*block).status), status as u32);
+            ptr::write_volatile(ptr::addr_of_mut!((*block).ack_seq), seq);
+        }
+    }
+}
+
+impl Default for Mailbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the firmware does with a command it finds while polling the mailbox
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Response {
+    /// Acknowledge, then stop trace once the [`Ack`] has gone out
+    Pause,
+    /// Restart trace, then acknowledge
+    Resume,
+    /// Acknowledge without touching trace. The [`Ack`] only goes on [`ACK_PORT`] while
+    /// output is running, since the ITM is off while it is paused.
+    Acknowledge { status: Status, on_port: bool },
+    /// Apply the command by resetting
+    Reset,
+}
+
+impl Response {
+    /// The response to `command` with output `paused` or running
+    pub fn to(command: Result<Command, Status>, paused: bool) -> Response {
+        match (command, paused) {
+            (Ok(Command::Pause), false) => Response::Pause,
+            (Ok(Command::Resume), true) => Response::Resume,
+            (Ok(Command::Pause), true) | (Ok(Command::Resume), false) => Response::Acknowledge {
+                status: Status::Ok,
+                on_port: !paused,
+            },
+            (Err(status), _) => Response::Acknowledge {
+                status,
+                on_port: !paused,
+            },
+            (Ok(_), _) => Response::Reset,
+        }
+    }
+}
+
+/// Sent on [`ACK_PORT`] for every command acknowledged
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Ack {
+    pub seq: u32,
+    pub status: Status,
+}
+
+impl Ack {
+    pub fn words(&self) -> [u32; 3] {
+        [ACK_MAGIC, self.seq, self.status as u32]
+    }
+
+    pub fn from_words(words: &[u32; 3]) -> Option<Self> {
+        if words[0] != ACK_MAGIC {
+            return None;
+        }
+        Some(Ack {
+            seq: words[1],
+            status: Status::from_u32(words[2])?,
+        })
+    }
+}
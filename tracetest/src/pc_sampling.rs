@@ -0,0 +1,81 @@
+//! Periodic PC sampling exercise
+//!
+//! The firmware enables `DWT_CTRL.PCSAMPLENA` and, on every timer tick, spends a fixed
+//! share of the tick in each of two hot functions and sleeps in WFI for the rest. Given
+//! the sample period, the host can predict how many PC samples land in each function
+//! and how many sleep samples (the PC sample packet with a one-byte zero payload) appear.
+//!
+//! Each rate in [`RATES`] is held for a number of ticks. On switching rate the firmware
+//! writes a [`Descriptor`] to [`PC_SAMPLING_PORT`], which includes the address of each
+//! hot function so that samples can be bucketed without the ELF file.
+
+use crate::dwt::{CycTap, DwtConfig, Enable};
+
+pub const PC_SAMPLING_PORT: usize = 0;
+
+/// First word of every descriptor, "PCS1" in little-endian order
+pub const DESCRIPTOR_MAGIC: u32 = u32::from_le_bytes(*b"PCS1");
+
+/// Number of words in an encoded [`Descriptor`]
+pub const DESCRIPTOR_WORDS: usize = 6;
+
+/// Fraction of each tick spent in the first hot function, in quarters
+pub const HOT_A_QUARTERS: u32 = 1;
+/// Fraction of each tick spent in the second hot function, in quarters
+pub const HOT_B_QUARTERS: u32 = 2;
+
+/// Sample rates to step through, as CYCTAP and POSTPRESET
+pub const RATES: [(CycTap, u8); 3] = [(CycTap::Bit6, 15), (CycTap::Bit10, 3), (CycTap::Bit10, 15)];
+
+/// DWT configuration for one entry of [`RATES`]
+pub fn dwt_config(cyc_tap: CycTap, post_preset: u8) -> DwtConfig {
+    DwtConfig {
+        cycle_counter: Enable::On,
+        cyc_tap,
+        post_preset,
+        pc_sampling: Enable::On,
+        ..DwtConfig::default()
+    }
+}
+
+/// Announces a new sample rate and where the hot functions are
+///
+/// | word | contents                          |
+/// |------|-----------------------------------|
+/// | 0    | [`DESCRIPTOR_MAGIC`]              |
+/// | 1    | cycles between PC samples         |
+/// | 2    | cycles per timer tick             |
+/// | 3    | address of the first hot function |
+/// | 4    | address of the second hot function|
+/// | 5    | ticks this rate is held for       |
+///
+/// Function addresses have the Thumb bit set, as taken from a function pointer.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Descriptor {
+    pub sample_period: u32,
+    pub tick_cycles: u32,
+    pub hot_a: u32,
+    pub hot_b: u32,
+    pub hold_ticks: u32,
+}
+
+impl Descriptor {
+    pub fn words(&self) -> [u32; DESCRIPTOR_WORDS] {
+        [
+            DESCRIPTOR_MAGIC,
+            self.sample_period,
+            self.tick_cycles,
+            self.hot_a,
+            self.hot_b,
+            self.hold_ticks,
+        ]
+    }
+
+    /// Expected number of samples per tick in each hot function and asleep
+    pub fn expected_samples_per_tick(&self) -> (u32, u32, u32) {
+        let per_tick = self.tick_cycles / self.sample_period;
+        let hot_a = per_tick * HOT_A_QUARTERS / 4;
+        let hot_b = per_tick * HOT_B_QUARTERS / 4;
+        (hot_a, hot_b, per_tick - hot_a - hot_b)
+    }
+}
@@ -0,0 +1,698 @@
+//! Register access for the trace peripherals
+//!
+//! Everything that the SWO bring-up touches goes through the [`Registers`] trait. On the
+//! target this is backed by [`Mmio`], which performs volatile accesses to the real
+//! addresses. On the host, [`MockRegisters`] keeps a copy of every register and records
+//! each access so that the exact order of a setup sequence can be checked.
+
+/// Key that unlocks the CoreSight Lock Access Registers
+pub const ARM_LAR_ACCESS_ENABLE: u32 = 0xc5acce55;
+
+/// Lock Status Register bit that is set when the component has a software lock
+const LSR_SLI: u32 = 1 << 0;
+
+/// Every register that the trace setup reads or writes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Register {
+    ScsDemcr,
+    /// CPUID Base Register, read to identify the core
+    ScbCpuid,
+    TpiuCspsr,
+    TpiuAcpr,
+    TpiuSppr,
+    TpiuFfcr,
+    TpiuLar,
+    /// Lock Status Register, which says whether the TPIU has a software lock at all
+    TpiuLsr,
+    /// The low two TPIU peripheral ID registers, which hold its part number
+    TpiuPidr0,
+    TpiuPidr1,
+    DwtCtrl,
+    DwtCpicnt,
+    DwtExccnt,
+    DwtSleepcnt,
+    DwtLsucnt,
+    DwtFoldcnt,
+    /// Comparator value, for comparators 0 to [`DWT_COMPARATORS`] - 1
+    DwtComp(u8),
+    DwtMask(u8),
+    DwtFunction(u8),
+    DwtLar,
+    DwtLsr,
+    ItmTer,
+    ItmTpr,
+    ItmTcr,
+    ItmLar,
+    ItmLsr,
+    DbgmcuCr,
+    EtmCr,
+    EtmTraceIdr,
+    EtmLar,
+    EtmLsr,
+}
+
+/// Number of DWT comparators used, the number on the Cortex-M3. Later cores have at least
+/// as many.
+pub const DWT_COMPARATORS: usize = 4;
+
+/// Number of distinct registers in [`Register`], used to size the mock register file
+pub const REGISTER_COUNT: usize = 28 + 3 * DWT_COMPARATORS;
+
+impl Register {
+    /// Memory-mapped address of this register
+    pub const fn address(self) -> usize {
+        match self {
+            Register::ScsDemcr => 0xE000_EDFC,
+            Register::ScbCpuid => 0xE000_ED00,
+            Register::TpiuCspsr => 0xE004_0004,
+            Register::TpiuAcpr => 0xE004_0010,
+            Register::TpiuSppr => 0xE004_00F0,
+            Register::TpiuFfcr => 0xE004_0304,
+            Register::TpiuLar => 0xE004_0FB0,
+            Register::TpiuLsr => 0xE004_0FB4,
+            Register::TpiuPidr0 => 0xE004_0FE0,
+            Register::TpiuPidr1 => 0xE004_0FE4,
+            Register::DwtCtrl => 0xE000_1000,
+            Register::DwtCpicnt => 0xE000_1008,
+            Register::DwtExccnt => 0xE000_100C,
+            Register::DwtSleepcnt => 0xE000_1010,
+            Register::DwtLsucnt => 0xE000_1014,
+            Register::DwtFoldcnt => 0xE000_1018,
+            Register::DwtComp(n) => 0xE000_1020 + 16 * n as usize,
+            Register::DwtMask(n) => 0xE000_1024 + 16 * n as usize,
+            Register::DwtFunction(n) => 0xE000_1028 + 16 * n as usize,
+            Register::DwtLar => 0xE000_1FB0,
+            Register::DwtLsr => 0xE000_1FB4,
+            Register::ItmTer => 0xE000_0E00,
+            Register::ItmTpr => 0xE000_0E40,
+            Register::ItmTcr => 0xE000_0E80,
+            Register::ItmLar => 0xE000_0FB0,
+            Register::ItmLsr => 0xE000_0FB4,
+            Register::DbgmcuCr => 0xE004_2004,
+            Register::EtmCr => 0xE004_1000,
+            Register::EtmTraceIdr => 0xE004_1200,
+            Register::EtmLar => 0xE004_1FB0,
+            Register::EtmLsr => 0xE004_1FB4,
+        }
+    }
+
+    const fn index(self) -> usize {
+        match self {
+            Register::ScsDemcr => 0,
+            Register::TpiuCspsr => 1,
+            Register::TpiuAcpr => 2,
+            Register::TpiuSppr => 3,
+            Register::TpiuFfcr => 4,
+            Register::TpiuLar => 5,
+            Register::DwtCtrl => 6,
+            Register::DwtLar => 7,
+            Register::ItmTer => 8,
+            Register::ItmTpr => 9,
+            Register::ItmTcr => 10,
+            Register::ItmLar => 11,
+            Register::DbgmcuCr => 12,
+            Register::EtmCr => 13,
+            Register::EtmTraceIdr => 14,
+            Register::EtmLar => 15,
+            Register::DwtCpicnt => 16,
+            Register::DwtExccnt => 17,
+            Register::DwtSleepcnt => 18,
+            Register::DwtLsucnt => 19,
+            Register::DwtFoldcnt => 20,
+            Register::ScbCpuid => 21,
+            Register::TpiuPidr0 => 22,
+            Register::TpiuPidr1 => 23,
+            Register::TpiuLsr => 24,
+            Register::DwtLsr => 25,
+            Register::ItmLsr => 26,
+            Register::EtmLsr => 27,
+            Register::DwtComp(n) => 28 + 3 * n as usize,
+            Register::DwtMask(n) => 29 + 3 * n as usize,
+            Register::DwtFunction(n) => 30 + 3 * n as usize,
+        }
+    }
+
+    /// The Lock Status Register next to this Lock Access Register
+    fn lock_status(self) -> Option<Register> {
+        match self {
+            Register::TpiuLar => Some(Register::TpiuLsr),
+            Register::DwtLar => Some(Register::DwtLsr),
+            Register::ItmLar => Some(Register::ItmLsr),
+            Register::EtmLar => Some(Register::EtmLsr),
+            _ => None,
+        }
+    }
+}
+
+/// A register whose bits have been broken out into named fields
+pub trait TypedRegister: Copy {
+    const REGISTER: Register;
+
+    fn from_bits(bits: u32) -> Self;
+    fn bits(self) -> u32;
+}
+
+/// Read and write access to the trace registers
+pub trait Registers {
+    fn read(&mut self, reg: Register) -> u32;
+    fn write(&mut self, reg: Register, value: u32);
+
+    fn modify<F: FnOnce(u32) -> u32>(&mut self, reg: Register, f: F) {
+        let value = self.read(reg);
+        self.write(reg, f(value));
+    }
+
+    fn read_typed<T: TypedRegister>(&mut self) -> T {
+        T::from_bits(self.read(T::REGISTER))
+    }
+
+    fn write_typed<T: TypedRegister>(&mut self, value: T) {
+        self.write(T::REGISTER, value.bits());
+    }
+
+    fn modify_typed<T: TypedRegister, F: FnOnce(&mut T)>(&mut self, f: F) {
+        let mut value = self.read_typed::<T>();
+        f(&mut value);
+        self.write_typed(value);
+    }
+
+    /// Write the CoreSight unlock key to the given Lock Access Register, if the
+    /// component has one
+    ///
+    /// Whether it does is read from the `SLI` bit of the Lock Status Register next to
+    /// it, which reads as zero on components without a lock. That depends on how the
+    /// part was built rather than on the core: an ARMv8-M ITM has no lock, but a
+    /// CoreSight SoC-400 TPIU beside it does.
+    fn unlock(&mut self, lar: Register) {
+        let lsr = lar.lock_status().expect("not a Lock Access Register");
+        if self.read(lsr) & LSR_SLI != 0 {
+            self.write(lar, ARM_LAR_ACCESS_ENABLE);
+        }
+    }
+}
+
+impl<R: Registers + ?Sized> Registers for &mut R {
+    fn read(&mut self, reg: Register) -> u32 {
+        (**self).read(reg)
+    }
+
+    fn write(&mut self, reg: Register, value: u32) {
+        (**self).write(reg, value)
+    }
+}
+
+/// Volatile accesses to the real peripherals
+pub struct Mmio {
+    _private: (),
+}
+
+impl Mmio {
+    /// # Safety
+    ///
+    /// The caller must be running on a part that has these registers at the addresses
+    /// given by [`Register::address`], and nothing else may be reconfiguring trace at
+    /// the same time.
+    pub unsafe fn new() -> Self {
+        Mmio { _private: () }
+    }
+}
+
+impl Registers for Mmio {
+    fn read(&mut self, reg: Register) -> u32 {
+        unsafe { (reg.address() as *const u32).read_volatile() }
+    }
+
+    fn write(&mut self, reg: Register, value: u32) {
+        unsafe { (reg.address() as *mut u32).write_volatile(value) }
+    }
+}
+
+/// A single access observed by [`MockRegisters`]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Access {
+    Read(Register, u32),
+    Write(Register, u32),
+}
+
+/// An in-memory register file that records every access
+///
+/// Up to `N` accesses are kept. Further accesses still update the register values but
+/// are counted in [`MockRegisters::dropped`] rather than logged.
+pub struct MockRegisters<const N: usize> {
+    values: [u32; REGISTER_COUNT],
+    log: [Access; N],
+    len: usize,
+    dropped: usize,
+}
+
+impl<const N: usize> MockRegisters<N> {
+    /// Create a register file where every register reads as zero
+    pub fn new() -> Self {
+        MockRegisters {
+            values: [0; REGISTER_COUNT],
+            log: [Access::Read(Register::ScsDemcr, 0); N],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Set the value of a register without recording an access
+    pub fn preset(&mut self, reg: Register, value: u32) {
+        self.values[reg.index()] = value;
+    }
+
+    /// Current value of a register, without recording an access
+    pub fn value(&self, reg: Register) -> u32 {
+        self.values[reg.index()]
+    }
+
+    /// Every access recorded so far, oldest first
+    pub fn accesses(&self) -> &[Access] {
+        &self.log[..self.len]
+    }
+
+    /// Only the writes, oldest first
+    pub fn writes(&self) -> impl Iterator<Item = (Register, u32)> + '_ {
+        self.accesses().iter().filter_map(|access| match *access {
+            Access::Write(reg, value) => Some((reg, value)),
+            Access::Read(..) => None,
+        })
+    }
+
+    /// Number of accesses that did not fit in the log
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Forget all recorded accesses, keeping the register values
+    pub fn clear_log(&mut self) {
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    fn record(&mut self, access: Access) {
+        if self.len < N {
+            self.log[self.len] = access;
+            self.len += 1;
+        } else {
+            self.dropped += 1;
+        }
+    }
+}
+
+impl<const N: usize> Default for MockRegisters<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Registers for MockRegisters<N> {
+    fn read(&mut self, reg: Register) -> u32 {
+        let value = self.values[reg.index()];
+        self.record(Access::Read(reg, value));
+        value
+    }
+
+    fn write(&mut self, reg: Register, value: u32) {
+        self.values[reg.index()] = value;
+        self.record(Access::Write(reg, value));
+    }
+}
+
+fn bit(bits: u32, n: u32) -> bool {
+    bits & (1 << n) != 0
+}
+
+fn set_bit(value: bool, n: u32) -> u32 {
+    (value as u32) << n
+}
+
+/// SCS Debug Exception and Monitor Control Register
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ScsDemcr {
+    /// Global enable for the DWT and ITM
+    pub trcena: bool,
+    /// Vector catch and monitor bits, passed through untouched
+    pub other: u32,
+}
+
+impl ScsDemcr {
+    const TRCENA: u32 = 24;
+}
+
+impl TypedRegister for ScsDemcr {
+    const REGISTER: Register = Register::ScsDemcr;
+
+    fn from_bits(bits: u32) -> Self {
+        ScsDemcr {
+            trcena: bit(bits, Self::TRCENA),
+            other: bits & !(1 << Self::TRCENA),
+        }
+    }
+
+    fn bits(self) -> u32 {
+        self.other | set_bit(self.trcena, Self::TRCENA)
+    }
+}
+
+/// TPIU Formatter and Flush Control Register
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TpiuFfcr {
+    /// Continuous formatting. Must be off for plain SWO output.
+    pub enfcont: bool,
+    /// Insert a trigger packet when TRIGIN is asserted
+    pub trigin: bool,
+    /// Flush control bits, passed through untouched
+    pub other: u32,
+}
+
+impl TpiuFfcr {
+    const ENFCONT: u32 = 1;
+    const TRIGIN: u32 = 8;
+}
+
+impl TypedRegister for TpiuFfcr {
+    const REGISTER: Register = Register::TpiuFfcr;
+
+    fn from_bits(bits: u32) -> Self {
+        TpiuFfcr {
+            enfcont: bit(bits, Self::ENFCONT),
+            trigin: bit(bits, Self::TRIGIN),
+            other: bits & !((1 << Self::ENFCONT) | (1 << Self::TRIGIN)),
+        }
+    }
+
+    fn bits(self) -> u32 {
+        self.other | set_bit(self.enfcont, Self::ENFCONT) | set_bit(self.trigin, Self::TRIGIN)
+    }
+}
+
+/// DWT Control Register
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DwtCtrl {
+    /// Run the cycle counter
+    pub cyccntena: bool,
+    /// 4-bit reload value of the POSTCNT timer
+    pub postpreset: u8,
+    /// 4-bit current value of the POSTCNT timer
+    pub postinit: u8,
+    /// Clock POSTCNT from CYCCNT bit 10 rather than bit 6
+    pub cyctap: bool,
+    /// 2-bit CYCCNT tap that drives synchronisation packets
+    pub synctap: u8,
+    /// Periodic PC sample packets
+    pub pcsamplena: bool,
+    /// Exception trace packets
+    pub exctrcena: bool,
+    pub cpievtena: bool,
+    pub excevtena: bool,
+    pub sleepevtena: bool,
+    pub lsuevtena: bool,
+    pub foldevtena: bool,
+    /// Cycle count event packets on POSTCNT underflow
+    pub cycevtena: bool,
+    /// Number of comparators implemented. Read-only.
+    pub numcomp: u8,
+    /// Read-only feature bits, passed through untouched
+    pub other: u32,
+}
+
+impl DwtCtrl {
+    const CYCCNTENA: u32 = 0;
+    const POSTPRESET: u32 = 1;
+    const POSTINIT: u32 = 5;
+    const CYCTAP: u32 = 9;
+    const SYNCTAP: u32 = 10;
+    const PCSAMPLENA: u32 = 12;
+    const EXCTRCENA: u32 = 16;
+    const CPIEVTENA: u32 = 17;
+    const EXCEVTENA: u32 = 18;
+    const SLEEPEVTENA: u32 = 19;
+    const LSUEVTENA: u32 = 20;
+    const FOLDEVTENA: u32 = 21;
+    const CYCEVTENA: u32 = 22;
+    const NUMCOMP: u32 = 28;
+    /// Every bit that has a named field
+    const FIELDS: u32 = 0xf07f_1fff;
+}
+
+impl TypedRegister for DwtCtrl {
+    const REGISTER: Register = Register::DwtCtrl;
+
+    fn from_bits(bits: u32) -> Self {
+        DwtCtrl {
+            cyccntena: bit(bits, Self::CYCCNTENA),
+            postpreset: ((bits >> Self::POSTPRESET) & 0xf) as u8,
+            postinit: ((bits >> Self::POSTINIT) & 0xf) as u8,
+            cyctap: bit(bits, Self::CYCTAP),
+            synctap: ((bits >> Self::SYNCTAP) & 0x3) as u8,
+            pcsamplena: bit(bits, Self::PCSAMPLENA),
+            exctrcena: bit(bits, Self::EXCTRCENA),
+            cpievtena: bit(bits, Self::CPIEVTENA),
+            excevtena: bit(bits, Self::EXCEVTENA),
+            sleepevtena: bit(bits, Self::SLEEPEVTENA),
+            lsuevtena: bit(bits, Self::LSUEVTENA),
+            foldevtena: bit(bits, Self::FOLDEVTENA),
+            cycevtena: bit(bits, Self::CYCEVTENA),
+            numcomp: (bits >> Self::NUMCOMP) as u8,
+            other: bits & !Self::FIELDS,
+        }
+    }
+
+    fn bits(self) -> u32 {
+        self.other
+            | set_bit(self.cyccntena, Self::CYCCNTENA)
+            | ((self.postpreset as u32 & 0xf) << Self::POSTPRESET)
+            | ((self.postinit as u32 & 0xf) << Self::POSTINIT)
+            | set_bit(self.cyctap, Self::CYCTAP)
+            | ((self.synctap as u32 & 0x3) << Self::SYNCTAP)
+            | set_bit(self.pcsamplena, Self::PCSAMPLENA)
+            | set_bit(self.exctrcena, Self::EXCTRCENA)
+            | set_bit(self.cpievtena, Self::CPIEVTENA)
+            | set_bit(self.excevtena, Self::EXCEVTENA)
+            | set_bit(self.sleepevtena, Self::SLEEPEVTENA)
+            | set_bit(self.lsuevtena, Self::LSUEVTENA)
+            | set_bit(self.foldevtena, Self::FOLDEVTENA)
+            | set_bit(self.cycevtena, Self::CYCEVTENA)
+            | ((self.numcomp as u32 & 0xf) << Self::NUMCOMP)
+    }
+}
+
+/// DWT Comparator Function Register
+///
+/// There is one of these per comparator, so it does not implement [`TypedRegister`].
+/// Use [`Register::DwtFunction`] with [`DwtFunction::from_bits`] and
+/// [`DwtFunction::bits`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DwtFunction {
+    /// 4-bit action taken on a match
+    pub function: u8,
+    /// Emit address offsets rather than PC values
+    pub emitrange: bool,
+    /// Compare against CYCCNT. Comparator 0 only.
+    pub cycmatch: bool,
+    /// Compare against the data value rather than the address
+    pub datavmatch: bool,
+    /// 2-bit size of the data value for DATAVMATCH
+    pub datavsize: u8,
+    /// The comparator has matched since this register was last read. Read-only.
+    pub matched: bool,
+    /// Linked comparator fields, passed through untouched
+    pub other: u32,
+}
+
+impl DwtFunction {
+    const EMITRANGE: u32 = 5;
+    const CYCMATCH: u32 = 7;
+    const DATAVMATCH: u32 = 8;
+    const DATAVSIZE: u32 = 10;
+    const MATCHED: u32 = 24;
+    /// Every bit that has a named field
+    const FIELDS: u32 = 0x0100_0daf;
+
+    pub fn from_bits(bits: u32) -> Self {
+        DwtFunction {
+            function: (bits & 0xf) as u8,
+            emitrange: bit(bits, Self::EMITRANGE),
+            cycmatch: bit(bits, Self::CYCMATCH),
+            datavmatch: bit(bits, Self::DATAVMATCH),
+            datavsize: ((bits >> Self::DATAVSIZE) & 0x3) as u8,
+            matched: bit(bits, Self::MATCHED),
+            other: bits & !Self::FIELDS,
+        }
+    }
+
+    pub fn bits(self) -> u32 {
+        self.other
+            | (self.function as u32 & 0xf)
+            | set_bit(self.emitrange, Self::EMITRANGE)
+            | set_bit(self.cycmatch, Self::CYCMATCH)
+            | set_bit(self.datavmatch, Self::DATAVMATCH)
+            | ((self.datavsize as u32 & 0x3) << Self::DATAVSIZE)
+    }
+}
+
+/// ETM Main Control Register
+///
+/// Only the bits needed to program the ETM while it is otherwise unused are named.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct EtmCr {
+    /// The ETM is powered down
+    pub power_down: bool,
+    /// The ETM is being programmed and is not tracing
+    pub programming: bool,
+    /// Trace configuration bits, passed through untouched
+    pub other: u32,
+}
+
+impl EtmCr {
+    const POWER_DOWN: u32 = 0;
+    const PROGRAMMING: u32 = 10;
+}
+
+impl TypedRegister for EtmCr {
+    const REGISTER: Register = Register::EtmCr;
+
+    fn from_bits(bits: u32) -> Self {
+        EtmCr {
+            power_down: bit(bits, Self::POWER_DOWN),
+            programming: bit(bits, Self::PROGRAMMING),
+            other: bits & !((1 << Self::POWER_DOWN) | (1 << Self::PROGRAMMING)),
+        }
+    }
+
+    fn bits(self) -> u32 {
+        self.other
+            | set_bit(self.power_down, Self::POWER_DOWN)
+            | set_bit(self.programming, Self::PROGRAMMING)
+    }
+}
+
+/// ITM Trace Control Register
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct ItmTcr {
+    pub itmena: bool,
+    /// Local timestamp generation
+    pub tsena: bool,
+    /// Pass DWT synchronisation packets through the ITM
+    pub syncena: bool,
+    /// Forward DWT hardware-source packets to the ITM
+    pub txena: bool,
+    /// Clock the local timestamp counter from the SWO clock
+    pub swoena: bool,
+    /// 7-bit ATB ID of the ITM
+    pub trace_bus_id: u8,
+    /// 2-bit global timestamp frequency
+    pub gtsfreq: u8,
+    /// 2-bit local timestamp prescaler
+    pub tsprescale: u8,
+    /// The ITM is processing events. Read-only.
+    pub busy: bool,
+}
+
+impl ItmTcr {
+    const ITMENA: u32 = 0;
+    const TSENA: u32 = 1;
+    const SYNCENA: u32 = 2;
+    const TXENA: u32 = 3;
+    const SWOENA: u32 = 4;
+    const TSPRESCALE: u32 = 8;
+    const GTSFREQ: u32 = 10;
+    const TRACE_BUS_ID: u32 = 16;
+    const BUSY: u32 = 23;
+}
+
+impl TypedRegister for ItmTcr {
+    const REGISTER: Register = Register::ItmTcr;
+
+    fn from_bits(bits: u32) -> Self {
+        ItmTcr {
+            itmena: bit(bits, Self::ITMENA),
+            tsena: bit(bits, Self::TSENA),
+            syncena: bit(bits, Self::SYNCENA),
+            txena: bit(bits, Self::TXENA),
+            swoena: bit(bits, Self::SWOENA),
+            tsprescale: ((bits >> Self::TSPRESCALE) & 0x3) as u8,
+            gtsfreq: ((bits >> Self::GTSFREQ) & 0x3) as u8,
+            trace_bus_id: ((bits >> Self::TRACE_BUS_ID) & 0x7f) as u8,
+            busy: bit(bits, Self::BUSY),
+        }
+    }
+
+    fn bits(self) -> u32 {
+        set_bit(self.itmena, Self::ITMENA)
+            | set_bit(self.tsena, Self::TSENA)
+            | set_bit(self.syncena, Self::SYNCENA)
+            | set_bit(self.txena, Self::TXENA)
+            | set_bit(self.swoena, Self::SWOENA)
+            | ((self.tsprescale as u32 & 0x3) << Self::TSPRESCALE)
+            | ((self.gtsfreq as u32 & 0x3) << Self::GTSFREQ)
+            | ((self.trace_bus_id as u32 & 0x7f) << Self::TRACE_BUS_ID)
+    }
+}
+
+/// Pin assignment selected by `DBGMCU_CR.TRACE_MODE`
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TraceMode {
+    /// Asynchronous trace on TRACESWO
+    #[default]
+    Async,
+    /// Synchronous trace with a 1-bit data port
+    Sync1,
+    /// Synchronous trace with a 2-bit data port
+    Sync2,
+    /// Synchronous trace with a 4-bit data port
+    Sync4,
+}
+
+/// Part-specific step that hands the trace pins to the TPIU
+///
+/// Which pins carry trace, and whether they have to be claimed at all, is up to the
+/// part rather than the Cortex-M core.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TracePins {
+    /// Leave the pins as the part or the debugger set them up
+    #[default]
+    Untouched,
+    /// Set `TRACE_MODE` and `TRACE_IOEN` in the STM32F1 [`DbgmcuCr`]
+    Stm32f1Dbgmcu,
+}
+
+/// STM32F1 DBGMCU Configuration Register
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct DbgmcuCr {
+    /// Assign the trace pins
+    pub trace_ioen: bool,
+    pub trace_mode: TraceMode,
+    /// Low-power and peripheral freeze bits, passed through untouched
+    pub other: u32,
+}
+
+impl DbgmcuCr {
+    const TRACE_IOEN: u32 = 5;
+    const TRACE_MODE: u32 = 6;
+    const TRACE_MODE_MASK: u32 = 0x3 << Self::TRACE_MODE;
+}
+
+impl TypedRegister for DbgmcuCr {
+    const REGISTER: Register = Register::DbgmcuCr;
+
+    fn from_bits(bits: u32) -> Self {
+        DbgmcuCr {
+            trace_ioen: bit(bits, Self::TRACE_IOEN),
+            trace_mode: match (bits >> Self::TRACE_MODE) & 0x3 {
+                0 => TraceMode::Async,
+                1 => TraceMode::Sync1,
+                2 => TraceMode::Sync2,
+                _ => TraceMode::Sync4,
+            },
+            other: bits & !((1 << Self::TRACE_IOEN) | Self::TRACE_MODE_MASK),
+        }
+    }
+
+    fn bits(self) -> u32 {
+        self.other
+            | set_bit(self.trace_ioen, Self::TRACE_IOEN)
+            | ((self.trace_mode as u32) << Self::TRACE_MODE)
+    }
+}
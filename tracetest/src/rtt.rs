@@ -0,0 +1,366 @@
+//! RTT up-channels
+//!
+//! SEGGER's Real-Time Transfer has the target write into ring buffers in RAM while the
+//! debug probe reads them out through the debug port, with no trace pins involved. The
+//! probe finds the buffers through a control block that starts with [`RTT_ID`],
+//! normally exported as the symbol `_SEGGER_RTT`. This module only implements
+//! up-channels (target to host).
+//!
+//! Each up-channel is written through the same [`TraceWrite`] API as an
+//! [`ItmPort`](crate::itm::ItmPort), so the same payloads can be sent over RTT and SWO
+//! and the two deliveries compared. Unlike the ITM, RTT does not split the bytes into
+//! packets: a `write_u32` just adds four bytes, in little-endian order, to the buffer.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use crate::itm::TraceWrite;
+
+/// First 16 bytes of the control block
+pub const RTT_ID: [u8; 16] = *b"SEGGER RTT\0\0\0\0\0\0";
+
+/// What an up-channel does when its buffer is too full for a write, kept in the
+/// channel's flags so that the host can read or change it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChannelMode {
+    /// Drop the whole write
+    NoBlockSkip,
+    /// Write as much as fits and drop the rest
+    NoBlockTrim,
+    /// Wait for the host to make room. Hangs if no probe is reading.
+    Blocking,
+}
+
+impl ChannelMode {
+    const MASK: u32 = 0x3;
+
+    fn from_flags(flags: u32) -> Self {
+        match flags & Self::MASK {
+            0 => ChannelMode::NoBlockSkip,
+            1 => ChannelMode::NoBlockTrim,
+            _ => ChannelMode::Blocking,
+        }
+    }
+}
+
+/// One ring buffer descriptor, laid out as the probe expects
+#[repr(C)]
+struct BufferDescriptor {
+    name: *const u8,
+    buffer: *mut u8,
+    size: u32,
+    /// Next byte the target will write, only changed by the target
+    write: u32,
+    /// Next byte the host will read, only changed by the host
+    read: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct ControlBlock<const UP: usize> {
+    id: [u8; 16],
+    max_up: u32,
+    max_down: u32,
+    up: [BufferDescriptor; UP],
+}
+
+/// Storage for one up-channel's ring buffer
+pub struct RttBuffer<const SIZE: usize> {
+    bytes: UnsafeCell<[u8; SIZE]>,
+    /// Already given to an [`UpConfig`]
+    taken: AtomicBool,
+}
+
+// Safe because the buffer is only given to one `UpConfig`, and so only written by one
+// `UpChannel`
+unsafe impl<const SIZE: usize> Sync for RttBuffer<SIZE> {}
+
+impl<const SIZE: usize> RttBuffer<SIZE> {
+    /// One byte of the ring always stays empty, so it needs two to hold anything
+    const USABLE: () = assert!(
+        SIZE >= 2 && SIZE <= u32::MAX as usize,
+        "an RTT buffer needs at least two bytes"
+    );
+
+    pub const fn new() -> Self {
+        RttBuffer {
+            bytes: UnsafeCell::new([0; SIZE]),
+            taken: AtomicBool::new(false),
+        }
+    }
+}
+
+impl<const SIZE: usize> Default for RttBuffer<SIZE> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Name, storage and starting mode of an up-channel, passed to [`Rtt::init`]
+pub struct UpConfig {
+    name: &'static [u8],
+    buffer: *mut u8,
+    size: u32,
+    mode: ChannelMode,
+}
+
+impl UpConfig {
+    /// `name` must end with a NUL byte, and each buffer can only be used for one channel
+    pub fn new<const SIZE: usize>(
+        name: &'static [u8],
+        buffer: &'static RttBuffer<SIZE>,
+        mode: ChannelMode,
+    ) -> Self {
+        let () = RttBuffer::<SIZE>::USABLE;
+        assert_eq!(
+            name.last(),
+            Some(&0),
+            "RTT channel names are NUL-terminated"
+        );
+        assert!(
+            !buffer.taken.swap(true, Ordering::AcqRel),
+            "RTT buffer used for two channels"
+        );
+        UpConfig {
+            name,
+            buffer: buffer.bytes.get() as *mut u8,
+            size: SIZE as u32,
+            mode,
+        }
+    }
+}
+
+/// An RTT control block with `UP` up-channels and no down-channels
+///
+/// Meant to live in a `#[no_mangle] static _SEGGER_RTT`. The ID is only written by
+/// [`Rtt::init`], once the channels are set up, so a probe never sees a half-written
+/// block. Channels can only be claimed after that.
+#[repr(C)]
+pub struct Rtt<const UP: usize> {
+    block: UnsafeCell<ControlBlock<UP>>,
+    /// Up-channels already handed out by [`Rtt::up`]
+    taken: AtomicU32,
+    /// [`UNINITIALISED`], [`INITIALISING`] or [`READY`]
+    state: AtomicU8,
+}
+
+/// Values of [`Rtt::state`]
+const UNINITIALISED: u8 = 0;
+const INITIALISING: u8 = 1;
+const READY: u8 = 2;
+
+// Safe because each descriptor is written by `init` before the ID, and after that only
+// by the one `UpChannel` that owns it
+unsafe impl<const UP: usize> Sync for Rtt<UP> {}
+
+impl<const UP: usize> Rtt<UP> {
+    const IN_RANGE: () = assert!(UP <= 32, "at most 32 up-channels");
+
+    const EMPTY: BufferDescriptor = BufferDescriptor {
+        name: ptr::null(),
+        buffer: ptr::null_mut(),
+        size: 0,
+        write: 0,
+        read: 0,
+        flags: 0,
+    };
+
+    pub const fn new() -> Self {
+        Rtt {
+            block: UnsafeCell::new(ControlBlock {
+                id: [0; 16],
+                max_up: UP as u32,
+                max_down: 0,
+                up: [Self::EMPTY; UP],
+            }),
+            taken: AtomicU32::new(0),
+            state: AtomicU8::new(UNINITIALISED),
+        }
+    }
+
+    /// Set up every up-channel and publish the control block
+    ///
+    /// Only the first call does anything, so that channels already handed out are not
+    /// reset under their owners. Returns whether this was it.
+    pub fn init(&'static self, channels: [UpConfig; UP]) -> bool {
+        let () = Self::IN_RANGE;
+        if self
+            .state
+            .compare_exchange(
+                UNINITIALISED,
+                INITIALISING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_err()
+        {
+            return false;
+        }
+        let block = self.block.get();
+        for (n, channel) in channels.iter().enumerate() {
+            unsafe {
+                ptr::write_volatile(
+                    ptr::addr_of_mut!((*block).up[n]),
+                    BufferDescriptor {
+                        name: channel.name.as_ptr(),
+                        buffer: channel.buffer,
+                        size: channel.size,
+                        write: 0,
+                        read: 0,
+                        flags: channel.mode as u32,
+                    },
+                );
+            }
+        }
+        compiler_fence(Ordering::SeqCst);
+        unsafe { ptr::write_volatile(ptr::addr_of_mut!((*block).id), RTT_ID) };
+        self.state.store(READY, Ordering::Release);
+        true
+    }
+
+    /// Claim up-channel `n`. Returns `None` if [`Rtt::init`] has not run yet, or if the
+    /// channel does not exist or is already claimed.
+    pub fn up(&'static self, n: usize) -> Option<UpChannel> {
+        if n >= UP || self.state.load(Ordering::Acquire) != READY {
+            return None;
+        }
+        let bit = 1 << n;
+        if self.taken.fetch_or(bit, Ordering::AcqRel) & bit != 0 {
+            return None;
+        }
+        Some(UpChannel {
+            channel: n,
+            descriptor: unsafe { ptr::addr_of_mut!((*self.block.get()).up[n]) },
+        })
+    }
+}
+
+impl<const UP: usize> Default for Rtt<UP> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Exclusive writer for one RTT up-channel
+pub struct UpChannel {
+    channel: usize,
+    descriptor: *mut BufferDescriptor,
+}
+
+impl UpChannel {
+    /// The up-channel number
+    pub fn channel(&self) -> usize {
+        self.channel
+    }
+
+    pub fn mode(&self) -> ChannelMode {
+        ChannelMode::from_flags(unsafe {
+            ptr::read_volatile(ptr::addr_of!((*self.descriptor).flags))
+        })
+    }
+
+    pub fn set_mode(&mut self, mode: ChannelMode) {
+        unsafe {
+            let flags = ptr::addr_of_mut!((*self.descriptor).flags);
+            let other = ptr::read_volatile(flags) & !ChannelMode::MASK;
+            ptr::write_volatile(flags, other | mode as u32);
+        }
+    }
+
+    /// Bytes that can be written before the buffer is full
+    fn free(&self) -> u32 {
+        unsafe {
+            let size = (*self.descriptor).size;
+            let write = ptr::read_volatile(ptr::addr_of!((*self.descriptor).write));
+            let read = ptr::read_volatile(ptr::addr_of!((*self.descriptor).read));
+            /* One byte always stays empty so that a full buffer differs from an empty one */
+            if read > write {
+                read - write - 1
+            } else {
+                size - write + read - 1
+            }
+        }
+    }
+
+    /// Copy bytes into the buffer, which must have room for all of them
+    fn push(&mut self, bytes: &[u8]) {
+        unsafe {
+            let buffer = (*self.descriptor).buffer;
+            let size = (*self.descriptor).size;
+            let mut write = ptr::read_volatile(ptr::addr_of!((*self.descriptor).write));
+            for byte in bytes {
+                ptr::write_volatile(buffer.add(write as usize), *byte);
+                write += 1;
+                if write == size {
+                    write = 0;
+                }
+            }
+            compiler_fence(Ordering::SeqCst);
+            ptr::write_volatile(ptr::addr_of_mut!((*self.descriptor).write), write);
+        }
+    }
+
+    /// Add `bytes` to the buffer according to the channel's [`ChannelMode`], returning
+    /// how many were written
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> usize {
+        match self.mode() {
+            ChannelMode::NoBlockSkip => {
+                if (self.free() as usize) < bytes.len() {
+                    return 0;
+                }
+                self.push(bytes);
+                bytes.len()
+            }
+            ChannelMode::NoBlockTrim => {
+                let len = bytes.len().min(self.free() as usize);
+                self.push(&bytes[..len]);
+                len
+            }
+            ChannelMode::Blocking => {
+                let mut rest = bytes;
+                while !rest.is_empty() {
+                    let len = rest.len().min(self.free() as usize);
+                    if len == 0 {
+                        core::hint::spin_loop();
+                        continue;
+                    }
+                    self.push(&rest[..len]);
+                    rest = &rest[len..];
+                }
+                bytes.len()
+            }
+        }
+    }
+}
+
+// Safe because the descriptor is only ever reached through this one handle
+unsafe impl Send for UpChannel {}
+
+impl TraceWrite for UpChannel {
+    fn write_u8(&mut self, value: u8) {
+        self.write_bytes(&[value]);
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    /// Add the whole string in one go, so that [`ChannelMode::NoBlockSkip`] drops all or
+    /// none of it
+    fn write_all(&mut self, bytes: &[u8]) {
+        self.write_bytes(bytes);
+    }
+}
+
+impl fmt::Write for UpChannel {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+//! ITM overflow stress
+//!
+//! Each [`Phase`] writes [`WRITES_PER_PHASE`] words round-robin to some of
+//! [`STRESS_PORTS`] without waiting for room in the ITM FIFO, so the ITM drops writes and
+//! emits overflow packets. Later phases add local timestamps and the DWT's PC sampling
+//! and exception trace, which compete for the same bandwidth.
+//!
+//! Word `n` of phase `p` is `p << 24 | n`, so the host can see which writes were lost.
+//! The firmware times each phase with `DWT_CYCCNT`, waits for the ITM to drain, then
+//! sends a [`Report`] on [`REPORT_PORT`] with what it offered and what the line can
+//! carry. Offered load is `offered_bytes * clock_hz / cycles` bytes per second.
+
+use crate::config::SwoConfig;
+use crate::dwt::{CycTap, DwtConfig, Enable};
+
+/// Ports hammered by the stress phases
+pub const STRESS_PORTS: [usize; 4] = [1, 2, 9, 17];
+
+/// Side channel for the reports, written only while the ITM is idle
+pub const REPORT_PORT: usize = 0;
+
+/// First word of every report, "SAT1" in little-endian order
+pub const REPORT_MAGIC: u32 = u32::from_le_bytes(*b"SAT1");
+
+/// Stimulus writes made in each phase
+pub const WRITES_PER_PHASE: u32 = 4096;
+
+/// Bytes of ITM packet for each 32-bit stimulus write: a header and four of payload
+pub const BYTES_PER_WRITE: u32 = 5;
+
+/// One load setting
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Phase {
+    /// How many of [`STRESS_PORTS`] to spread the writes over
+    pub ports: usize,
+    /// Local timestamps on every packet
+    pub timestamps: bool,
+    /// PC sampling at the fastest rate, and exception trace
+    pub dwt_sources: bool,
+}
+
+pub const PHASES: [Phase; 4] = [
+    Phase {
+        ports: 1,
+        timestamps: false,
+        dwt_sources: false,
+    },
+    Phase {
+        ports: 4,
+        timestamps: false,
+        dwt_sources: false,
+    },
+    Phase {
+        ports: 4,
+        timestamps: true,
+        dwt_sources: false,
+    },
+    Phase {
+        ports: 4,
+        timestamps: true,
+        dwt_sources: true,
+    },
+];
+
+impl Phase {
+    /// Stimulus port enable mask for this phase
+    pub fn stimulus_ports(&self) -> u32 {
+        STRESS_PORTS[..self.ports]
+            .iter()
+            .fold(0, |mask, port| mask | 1 << port)
+    }
+
+    /// DWT setup for this phase, based on `base`. The cycle counter is always on, since
+    /// it times the phase, and PC sampling and exception trace are on only in phases
+    /// that load the line with them.
+    pub fn dwt_config(&self, base: &DwtConfig) -> DwtConfig {
+        let mut dwt = DwtConfig {
+            cycle_counter: Enable::On,
+            pc_sampling: self.dwt_sources.into(),
+            exception_trace: self.dwt_sources.into(),
+            ..*base
+        };
+        if self.dwt_sources {
+            dwt.cyc_tap = CycTap::Bit6;
+            dwt.post_preset = 0;
+        }
+        dwt
+    }
+}
+
+/// The stimulus word for write `n` of phase `phase`
+pub fn stress_word(phase: usize, n: u32) -> u32 {
+    (phase as u32) << 24 | (n & 0x00ff_ffff)
+}
+
+/// What one phase offered, sent on [`REPORT_PORT`] after it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Report {
+    pub phase: u32,
+    /// Stimulus packet bytes written, not counting DWT packets or timestamps
+    pub offered_bytes: u32,
+    /// `DWT_CYCCNT` cycles taken to write them
+    pub cycles: u32,
+    pub clock_hz: u32,
+    /// [`SwoConfig::line_capacity`] in bytes per second
+    pub capacity: u32,
+}
+
+impl Report {
+    pub fn new(phase: usize, cycles: u32, config: &SwoConfig) -> Self {
+        Report {
+            phase: phase as u32,
+            offered_bytes: WRITES_PER_PHASE * BYTES_PER_WRITE,
+            cycles,
+            clock_hz: config.clock().frequency(),
+            capacity: config.line_capacity(),
+        }
+    }
+
+    /// Offered load in bytes per second
+    pub fn offered_rate(&self) -> u64 {
+        self.offered_bytes as u64 * self.clock_hz as u64 / (self.cycles as u64).max(1)
+    }
+
+    pub fn words(&self) -> [u32; 6] {
+        [
+            REPORT_MAGIC,
+            self.phase,
+            self.offered_bytes,
+            self.cycles,
+            self.clock_hz,
+            self.capacity,
+        ]
+    }
+
+    pub fn from_words(words: &[u32; 6]) -> Option<Self> {
+        if words[0] != REPORT_MAGIC {
+            return None;
+        }
+        Some(Report {
+            phase: words[1],
+            offered_bytes: words[2],
+            cycles: words[3],
+            clock_hz: words[4],
+            capacity: words[5],
+        })
+    }
+}
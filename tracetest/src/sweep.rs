@@ -0,0 +1,141 @@
+//! Baud rate and protocol sweep
+//!
+//! Walks a table of baud rate and [`SwoProtocol`] pairs. At each step the firmware
+//! repeatedly writes [`PREAMBLE`] followed by a [`Descriptor`] to [`SWEEP_PORT`], so a
+//! probe that is trying to autodetect the line settings always has a known pattern to
+//! lock onto and can confirm what it found.
+//!
+//! Every word is written as a 32-bit stimulus write, so on the wire each one appears as
+//! the header byte `0xFB` (port 31, four bytes) followed by the word in little-endian
+//! order.
+
+use crate::config::SwoConfig;
+use crate::itm::{Payload, Stimulus};
+use crate::swo::SwoProtocol;
+
+/// Stimulus port dedicated to the sweep announcements
+pub const SWEEP_PORT: usize = 31;
+
+/// Written before every descriptor. Alternating bits give autobaud detectors plenty of
+/// edges to measure.
+pub const PREAMBLE: [u32; 4] = [0x5555_5555; 4];
+
+/// First word of every descriptor, "SWP1" in little-endian order
+pub const DESCRIPTOR_MAGIC: u32 = u32::from_le_bytes(*b"SWP1");
+
+/// Number of words in an encoded [`Descriptor`]
+pub const DESCRIPTOR_WORDS: usize = 5;
+
+/// One entry in a sweep table
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SweepStep {
+    pub baud: u32,
+    pub protocol: SwoProtocol,
+}
+
+const fn step(baud: u32, protocol: SwoProtocol) -> SweepStep {
+    SweepStep { baud, protocol }
+}
+
+/// A short sweep that covers both protocols at a few common rates
+pub const BASIC_TABLE: &[SweepStep] = &[
+    step(4_000_000, SwoProtocol::Manchester),
+    step(4_000_000, SwoProtocol::Nrz),
+    step(1_000_000, SwoProtocol::Manchester),
+    step(1_000_000, SwoProtocol::Nrz),
+    step(115_200, SwoProtocol::Nrz),
+];
+
+/// Every rate that an 8 MHz HCLK can reach within tolerance, in both protocols
+pub const FULL_TABLE: &[SweepStep] = &[
+    step(4_000_000, SwoProtocol::Manchester),
+    step(4_000_000, SwoProtocol::Nrz),
+    step(2_000_000, SwoProtocol::Manchester),
+    step(2_000_000, SwoProtocol::Nrz),
+    step(1_000_000, SwoProtocol::Manchester),
+    step(1_000_000, SwoProtocol::Nrz),
+    step(500_000, SwoProtocol::Manchester),
+    step(500_000, SwoProtocol::Nrz),
+    step(250_000, SwoProtocol::Manchester),
+    step(250_000, SwoProtocol::Nrz),
+    step(115_200, SwoProtocol::Manchester),
+    step(115_200, SwoProtocol::Nrz),
+    step(57_600, SwoProtocol::Manchester),
+    step(57_600, SwoProtocol::Nrz),
+    step(9_600, SwoProtocol::Manchester),
+    step(9_600, SwoProtocol::Nrz),
+];
+
+/// The table selected at build time. Enable the `sweep-full` feature for [`FULL_TABLE`].
+#[cfg(not(feature = "sweep-full"))]
+pub const SWEEP_TABLE: &[SweepStep] = BASIC_TABLE;
+#[cfg(feature = "sweep-full")]
+pub const SWEEP_TABLE: &[SweepStep] = FULL_TABLE;
+
+/// Machine-readable description of the current sweep step
+///
+/// Encoded as [`DESCRIPTOR_WORDS`] words:
+///
+/// | word | contents                                     |
+/// |------|----------------------------------------------|
+/// | 0    | [`DESCRIPTOR_MAGIC`]                         |
+/// | 1    | step index in bits 0-15, step count in 16-31 |
+/// | 2    | requested baud rate                          |
+/// | 3    | achieved baud rate                           |
+/// | 4    | `TPIU_SPPR` value: 1 Manchester, 2 NRZ       |
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Descriptor {
+    pub index: u16,
+    pub count: u16,
+    pub requested_baud: u32,
+    pub achieved_baud: u32,
+    pub protocol: SwoProtocol,
+}
+
+impl Descriptor {
+    pub fn new(index: usize, count: usize, config: &SwoConfig) -> Self {
+        Descriptor {
+            index: index as u16,
+            count: count as u16,
+            requested_baud: config.requested_baud(),
+            achieved_baud: config.achieved_baud(),
+            protocol: config.protocol(),
+        }
+    }
+
+    pub fn words(&self) -> [u32; DESCRIPTOR_WORDS] {
+        [
+            DESCRIPTOR_MAGIC,
+            self.index as u32 | (self.count as u32) << 16,
+            self.requested_baud,
+            self.achieved_baud,
+            self.protocol.tpiu_sppr_value(),
+        ]
+    }
+
+    /// Write [`PREAMBLE`] and then this descriptor to [`SWEEP_PORT`]
+    pub fn announce(&self, out: &mut impl Stimulus) {
+        for word in PREAMBLE.iter().chain(self.words().iter()) {
+            out.write(SWEEP_PORT, Payload::U32(*word));
+        }
+    }
+
+    /// Decode a descriptor, returning `None` if the magic or protocol is not recognised
+    pub fn from_words(words: &[u32; DESCRIPTOR_WORDS]) -> Option<Self> {
+        if words[0] != DESCRIPTOR_MAGIC {
+            return None;
+        }
+        let protocol = match words[4] {
+            1 => SwoProtocol::Manchester,
+            2 => SwoProtocol::Nrz,
+            _ => return None,
+        };
+        Some(Descriptor {
+            index: words[1] as u16,
+            count: (words[1] >> 16) as u16,
+            requested_baud: words[2],
+            achieved_baud: words[3],
+            protocol,
+        })
+    }
+}
@@ -0,0 +1,292 @@
+//! SWO bring-up
+//!
+//! Configures the TPIU for 1-bit asynchronous output, or for the synchronous trace port,
+//! and routes the ITM through it. The sequence is written against [`Registers`] so that
+//! it can be run against [`MockRegisters`](crate::regs::MockRegisters) as well as the
+//! real hardware.
+
+use crate::config::SwoConfig;
+use crate::dwt::DwtConfig;
+use crate::regs::{
+    DbgmcuCr, DwtCtrl, EtmCr, ItmTcr, Register, Registers, ScsDemcr, TpiuFfcr, TracePins,
+    DWT_COMPARATORS,
+};
+
+const TPIU_SPPR_ASYNC_MANCHESTER: u32 = 1;
+const TPIU_SPPR_ASYNC_NRZ: u32 = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SwoProtocol {
+    Manchester,
+    Nrz,
+}
+
+impl SwoProtocol {
+    pub fn tpiu_sppr_value(&self) -> u32 {
+        match self {
+            SwoProtocol::Manchester => TPIU_SPPR_ASYNC_MANCHESTER,
+            SwoProtocol::Nrz => TPIU_SPPR_ASYNC_NRZ,
+        }
+    }
+
+    /// Bit periods on the wire for each byte of trace
+    ///
+    /// NRZ is framed like a UART, with a start and a stop bit. Manchester only needs the
+    /// start bit, since every bit period already has a transition.
+    pub fn bits_per_byte(&self) -> u32 {
+        match self {
+            SwoProtocol::Manchester => 9,
+            SwoProtocol::Nrz => 10,
+        }
+    }
+}
+
+/// Trace register state from before [`swo_setup`] ran
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct SavedState {
+    demcr: ScsDemcr,
+    /// `DBGMCU_CR`, saved the first time the STM32F1 trace pins are claimed
+    dbgmcu_cr: Option<DbgmcuCr>,
+    tpiu_ffcr: TpiuFfcr,
+    dwt_ctrl: DwtCtrl,
+    itm_tcr: ItmTcr,
+    itm_tpr: u32,
+    itm_ter: u32,
+    /// `DWT_FUNCTIONn` of each comparator, saved the first time it is programmed
+    dwt_functions: [Option<u32>; DWT_COMPARATORS],
+    /// `ETMCR` and `ETMTRACEIDR`, saved the first time an ETM trace ID is programmed
+    etm: Option<(EtmCr, u32)>,
+}
+
+/// A running SWO pipeline
+///
+/// Returned by [`swo_setup`]. Owns the register backend until [`SwoHandle::disable`]
+/// hands it back.
+pub struct SwoHandle<R: Registers> {
+    regs: R,
+    config: SwoConfig,
+    saved: SavedState,
+}
+
+/// Bring up SWO output from the ITM
+pub fn swo_setup<R: Registers>(mut regs: R, config: &SwoConfig) -> SwoHandle<R> {
+    let demcr = regs.read_typed::<ScsDemcr>();
+
+    /* Enable tracing in DEMCR */
+    regs.write_typed(ScsDemcr {
+        trcena: true,
+        ..demcr
+    });
+
+    /* Configure the TPIU port width, and the validated divisor for async trace (SWO) */
+    regs.unlock(Register::TpiuLar);
+    regs.write(Register::TpiuCspsr, config.cspsr());
+    regs.write(Register::TpiuAcpr, config.acpr());
+    regs.write(Register::TpiuSppr, config.sppr());
+    /* Formatter frames are needed to tell several trace sources apart, and by the
+     * synchronous port to mark idle cycles */
+    let tpiu_ffcr = regs.read_typed::<TpiuFfcr>();
+    regs.write_typed(TpiuFfcr {
+        enfcont: config.formatter(),
+        ..tpiu_ffcr
+    });
+    let mut etm = None;
+    program_etm_trace_id(&mut regs, config.etm_trace_id(), &mut etm);
+
+    /* Configure the DWT packet sources that feed the ITM */
+    regs.unlock(Register::DwtLar);
+    let dwt_ctrl = regs.read_typed::<DwtCtrl>();
+    regs.write_typed(config.dwt().dwt_ctrl(dwt_ctrl));
+    let mut dwt_functions = [None; DWT_COMPARATORS];
+    program_watchpoints(&mut regs, config.dwt(), None, &mut dwt_functions);
+    /* Enable access to the ITM registers and configure tracing output from the requested stimulus ports */
+    regs.unlock(Register::ItmLar);
+    let itm_tcr = regs.read_typed::<ItmTcr>();
+    let itm_tpr = regs.read(Register::ItmTpr);
+    let itm_ter = regs.read(Register::ItmTer);
+    regs.write(Register::ItmTpr, config.itm_tpr());
+    regs.write_typed(config.itm_tcr());
+    regs.write(Register::ItmTer, config.stimulus_ports());
+
+    let mut dbgmcu_cr = None;
+    claim_trace_pins(&mut regs, config, &mut dbgmcu_cr);
+
+    SwoHandle {
+        regs,
+        config: *config,
+        saved: SavedState {
+            demcr,
+            dbgmcu_cr,
+            tpiu_ffcr,
+            dwt_ctrl,
+            itm_tcr,
+            itm_tpr,
+            itm_ter,
+            dwt_functions,
+            etm,
+        },
+    }
+}
+
+/// Program the comparators used by `config`, and put back any that `previous` used but
+/// `config` does not
+fn program_watchpoints<R: Registers>(
+    regs: &mut R,
+    config: &DwtConfig,
+    previous: Option<&DwtConfig>,
+    saved: &mut [Option<u32>; DWT_COMPARATORS],
+) {
+    for (n, saved) in saved.iter_mut().enumerate() {
+        let watchpoint = config.watchpoints[n];
+        let was_used = previous.is_some_and(|previous| previous.watchpoints[n].is_some());
+        if watchpoint.is_none() && !was_used {
+            continue;
+        }
+
+        let function = Register::DwtFunction(n as u8);
+        if saved.is_none() {
+            *saved = Some(regs.read(function));
+        }
+        match watchpoint {
+            Some(watchpoint) => {
+                /* Turn the comparator off while its address and mask change */
+                regs.write(function, 0);
+                regs.write(Register::DwtComp(n as u8), watchpoint.address);
+                regs.write(Register::DwtMask(n as u8), watchpoint.mask());
+                /* The builder has already rejected unsupported watchpoints */
+                regs.write(function, watchpoint.function().map_or(0, |f| f.bits()));
+            }
+            None => regs.write(function, saved.unwrap_or(0)),
+        }
+    }
+}
+
+/// Give the ETM its ATB ID, leaving it powered down so that only the ID changes
+fn program_etm_trace_id<R: Registers>(
+    regs: &mut R,
+    id: Option<u8>,
+    saved: &mut Option<(EtmCr, u32)>,
+) {
+    let Some(id) = id else {
+        return;
+    };
+    regs.unlock(Register::EtmLar);
+    let etm_cr = regs.read_typed::<EtmCr>();
+    if saved.is_none() {
+        *saved = Some((etm_cr, regs.read(Register::EtmTraceIdr)));
+    }
+    /* TRACEIDR may only be written with the programming bit set */
+    regs.write_typed(EtmCr {
+        power_down: false,
+        programming: true,
+        ..etm_cr
+    });
+    regs.write(Register::EtmTraceIdr, id as u32);
+    regs.write_typed(EtmCr {
+        power_down: true,
+        programming: false,
+        ..etm_cr
+    });
+}
+
+/// Map trace to the pins for the configured trace mode, if the part needs telling
+fn claim_trace_pins<R: Registers>(regs: &mut R, config: &SwoConfig, saved: &mut Option<DbgmcuCr>) {
+    match config.trace_pins() {
+        TracePins::Untouched => {}
+        TracePins::Stm32f1Dbgmcu => {
+            let dbgmcu_cr = regs.read_typed::<DbgmcuCr>();
+            if saved.is_none() {
+                *saved = Some(dbgmcu_cr);
+            }
+            /* Pick the pins before handing them over */
+            let trace_mode = config.trace_mode();
+            regs.modify_typed(|cr: &mut DbgmcuCr| cr.trace_mode = trace_mode);
+            regs.modify_typed(|cr: &mut DbgmcuCr| cr.trace_ioen = true);
+        }
+    }
+}
+
+impl<R: Registers> SwoHandle<R> {
+    /// The configuration currently programmed into the TPIU
+    pub fn config(&self) -> &SwoConfig {
+        &self.config
+    }
+
+    /// Direct access to the register backend
+    pub fn registers(&mut self) -> &mut R {
+        &mut self.regs
+    }
+
+    /// Wait until the ITM has sent everything written to it so far
+    pub fn wait_idle(&mut self) {
+        while self.regs.read_typed::<ItmTcr>().busy {}
+    }
+
+    /// Stop the ITM accepting new stimulus writes and wait for what it already has to
+    /// leave
+    fn drain(&mut self) {
+        self.regs
+            .modify_typed(|tcr: &mut ItmTcr| tcr.itmena = false);
+        self.wait_idle();
+    }
+
+    /// Switch to a new trace port, baud rate, protocol, timestamp setting, formatter
+    /// setting, set of stimulus ports or set of DWT sources without tearing down the rest
+    /// of trace
+    ///
+    /// The ITM is drained first so that no packet straddles the change.
+    pub fn reconfigure(&mut self, config: &SwoConfig) {
+        self.drain();
+        self.regs.write(Register::TpiuCspsr, config.cspsr());
+        self.regs.write(Register::TpiuAcpr, config.acpr());
+        self.regs.write(Register::TpiuSppr, config.sppr());
+        let formatter = config.formatter();
+        self.regs
+            .modify_typed(|ffcr: &mut TpiuFfcr| ffcr.enfcont = formatter);
+        program_etm_trace_id(&mut self.regs, config.etm_trace_id(), &mut self.saved.etm);
+        claim_trace_pins(&mut self.regs, config, &mut self.saved.dbgmcu_cr);
+        /* Start from what setup found, so that sources the old configuration turned on
+         * go back to how the debugger left them */
+        let dwt = config.dwt();
+        self.regs.write_typed(dwt.dwt_ctrl(self.saved.dwt_ctrl));
+        program_watchpoints(
+            &mut self.regs,
+            dwt,
+            Some(self.config.dwt()),
+            &mut self.saved.dwt_functions,
+        );
+        self.regs.write(Register::ItmTpr, config.itm_tpr());
+        self.regs.write(Register::ItmTer, config.stimulus_ports());
+        self.regs.write_typed(config.itm_tcr());
+        self.config = *config;
+    }
+
+    /// Drain the ITM and put every register [`swo_setup`] changed back how it found it
+    pub fn disable(mut self) -> R {
+        self.drain();
+        let saved = self.saved;
+        self.regs.write(Register::ItmTer, saved.itm_ter);
+        self.regs.write(Register::ItmTpr, saved.itm_tpr);
+        self.regs.write_typed(saved.itm_tcr);
+        for (n, function) in saved.dwt_functions.iter().enumerate() {
+            if let Some(function) = function {
+                self.regs.write(Register::DwtFunction(n as u8), *function);
+            }
+        }
+        self.regs.write_typed(saved.dwt_ctrl);
+        if let Some((etm_cr, etm_traceidr)) = saved.etm {
+            self.regs.write_typed(EtmCr {
+                programming: true,
+                ..etm_cr
+            });
+            self.regs.write(Register::EtmTraceIdr, etm_traceidr);
+            self.regs.write_typed(etm_cr);
+        }
+        self.regs.write_typed(saved.tpiu_ffcr);
+        if let Some(dbgmcu_cr) = saved.dbgmcu_cr {
+            self.regs.write_typed(dbgmcu_cr);
+        }
+        self.regs.write_typed(saved.demcr);
+        self.regs
+    }
+}
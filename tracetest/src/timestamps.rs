@@ -0,0 +1,58 @@
+//! Local timestamp exercise
+//!
+//! Drives the ITM so that every local timestamp packet format appears on the wire. For
+//! each [`TimestampPrescaler`] the firmware:
+//!
+//! 1. writes [`marker`]`(prescaler, PHASE_BURST)` and then [`BURST_LEN`] single bytes
+//!    back to back. The FIFO backs up, so the timestamps that follow carry the delayed
+//!    TC values as well as the single-byte format for small deltas.
+//! 2. for each entry `i` of [`GAP_TICKS`], writes [`marker`]`(prescaler, i + 1)`, idles
+//!    for that many timestamp ticks and then writes the byte `i`. The timestamp after
+//!    that byte needs [`lts_size`] bytes to encode the gap.
+//!
+//! Finally, with [`TimestampPrescaler::Div1`], it writes
+//! [`marker`]`(Div1, PHASE_OVERFLOW)`, idles for [`OVERFLOW_GAP_TICKS`] and writes
+//! `0xff`, which makes the counter wrap and produces the timestamp overflow packet.
+//!
+//! All writes go to [`TIMESTAMP_PORT`]. Markers are 32-bit writes and data are 8-bit
+//! writes, so the two are distinguishable from the packet header alone.
+
+use crate::config::TimestampPrescaler;
+
+pub const TIMESTAMP_PORT: usize = 0;
+
+/// Upper half of every marker word, "TS"
+pub const MARKER_MAGIC: u32 = 0x5453_0000;
+
+pub const PHASE_BURST: u8 = 0;
+pub const PHASE_OVERFLOW: u8 = 0xff;
+
+/// Number of bytes in the back-to-back burst
+pub const BURST_LEN: usize = 32;
+
+/// Idle gaps, in timestamp ticks. One for each length of the long timestamp format.
+pub const GAP_TICKS: [u32; 5] = [3, 100, 10_000, 1_000_000, 3_000_000];
+
+/// A gap just past the 28-bit range of the timestamp counter
+pub const OVERFLOW_GAP_TICKS: u32 = (1 << 28) + (1 << 20);
+
+/// Marker word announcing the next phase of the exercise
+pub fn marker(prescaler: TimestampPrescaler, phase: u8) -> u32 {
+    MARKER_MAGIC | (prescaler.tsprescale() as u32) << 8 | phase as u32
+}
+
+/// Number of bytes in the synchronous local timestamp packet for a delta of `ticks`
+///
+/// Deltas of 1 to 6 fit in the single-byte format. Larger ones use a header byte plus up
+/// to four continuation bytes of seven bits each. Deltas that do not fit in 28 bits are
+/// reported as the one-byte overflow packet.
+pub fn lts_size(ticks: u32) -> usize {
+    match ticks {
+        1..=6 => 1,
+        0 | 7..=0x7f => 2,
+        0x80..=0x3fff => 3,
+        0x4000..=0x1f_ffff => 4,
+        0x20_0000..=0x0fff_ffff => 5,
+        _ => 1,
+    }
+}
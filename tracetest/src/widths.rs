@@ -0,0 +1,96 @@
+//! Stimulus payload width exercise
+//!
+//! An instrumentation packet's header gives the stimulus port in bits 7:3 and the payload
+//! size in bits 1:0: `0b01` for one byte, `0b10` for two and `0b11` for four. The payload
+//! follows in little-endian order. Once per tick the firmware makes every write in
+//! [`ROUND`], in order, each with the width given there, starting with [`MARKER`] on
+//! [`WIDTH_PORTS`]`[0]`.
+//!
+//! [`StimulusWrite::packet`] gives the exact bytes each write should produce, so the host
+//! can compare a round byte for byte, for example:
+//!
+//! | write                        | bytes on the wire |
+//! |------------------------------|-------------------|
+//! | port 2, `0x5a` as u8         | `11 5a`           |
+//! | port 2, `0x0102` as u16      | `12 02 01`        |
+//! | port 2, `0x01020304` as u32  | `13 04 03 02 01`  |
+//! | port 30, `0x80` as u8        | `f1 80`           |
+//!
+//! The values are chosen so that payload bytes include values that would be sync,
+//! overflow or timestamp headers if they were misread as the start of a packet.
+//! Local timestamps are off, so nothing else should appear between the packets of a
+//! round unless a DWT source has been turned on.
+
+pub use crate::itm::Payload;
+use crate::itm::Stimulus;
+
+use self::Payload::{U16, U32, U8};
+
+/// Ports used by the round. The first carries the marker.
+pub const WIDTH_PORTS: [usize; 4] = [2, 5, 17, 30];
+
+/// First write of every round, "WID1" in little-endian order
+pub const MARKER: u32 = u32::from_le_bytes(*b"WID1");
+
+/// One write in the round
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StimulusWrite {
+    pub port: usize,
+    pub payload: Payload,
+}
+
+impl StimulusWrite {
+    /// The instrumentation packet for this write, and how many of its bytes are used
+    pub fn packet(self) -> ([u8; 5], usize) {
+        let mut packet = [0; 5];
+        packet[0] = (self.port as u8) << 3 | self.payload.size_bits();
+        let len = self.payload.size();
+        packet[1..1 + len].copy_from_slice(&self.payload.value().to_le_bytes()[..len]);
+        (packet, 1 + len)
+    }
+}
+
+const fn w(port: usize, payload: Payload) -> StimulusWrite {
+    StimulusWrite { port, payload }
+}
+
+/// Every write made once per tick, in order
+pub const ROUND: [StimulusWrite; 22] = [
+    w(WIDTH_PORTS[0], U32(MARKER)),
+    /* Each width on its own, on one port */
+    w(2, U8(0x5a)),
+    w(2, U8(0x00)),
+    w(2, U8(0xff)),
+    w(2, U16(0x0102)),
+    w(2, U16(0x8000)),
+    w(2, U16(0xffff)),
+    w(2, U32(0x0102_0304)),
+    w(2, U32(0x0000_0000)),
+    w(2, U32(0xffff_ffff)),
+    /* Bytes that look like sync, overflow and timestamp headers */
+    w(5, U8(0x70)),
+    w(5, U16(0x00c0)),
+    w(5, U32(0x8000_0000)),
+    /* Widths and ports interleaved */
+    w(17, U8(0x11)),
+    w(30, U16(0x1e1e)),
+    w(5, U32(0x0505_0505)),
+    w(30, U8(0x80)),
+    w(17, U32(0x1111_1111)),
+    w(2, U16(0x2222)),
+    w(30, U32(0x1e1e_1e1e)),
+    w(17, U16(0x1111)),
+    w(5, U8(0x05)),
+];
+
+/// Make every write in [`ROUND`]
+pub fn send_round(out: &mut impl Stimulus) {
+    for write in ROUND.iter() {
+        out.write(write.port, write.payload);
+    }
+}
+
+/// Stimulus port enable mask covering [`WIDTH_PORTS`]
+pub fn stimulus_ports() -> u32 {
+    WIDTH_PORTS.iter().fold(0, |mask, port| mask | 1 << port)
+}
@@ -0,0 +1,95 @@
+//! The BERT checker against streams with known damage
+
+use tracetest::bert::{Checker, Lfsr, Prbs, Report, BLOCK_BYTES};
+
+/// The first `words` words of `prbs`, as the firmware sends them
+fn words(prbs: Prbs, words: usize) -> Vec<u32> {
+    let mut lfsr = Lfsr::new(prbs);
+    (0..words).map(|_| lfsr.next_word()).collect()
+}
+
+fn check(prbs: Prbs, words: &[u32]) -> Report {
+    let mut checker = Checker::new(prbs);
+    for word in words.iter() {
+        for byte in word.to_le_bytes().iter() {
+            checker.push(*byte);
+        }
+    }
+    assert!(checker.is_locked());
+    checker.report()
+}
+
+#[test]
+fn clean_stream() {
+    for prbs in Prbs::ALL.iter() {
+        let report = check(*prbs, &words(*prbs, 1000));
+        assert!(report.bits > 3900 * 8, "{:?}: {:?}", prbs, report);
+        assert_eq!(report.errors, 0, "{:?}", prbs);
+        assert_eq!((report.slips, report.resyncs), (0, 0), "{:?}", prbs);
+    }
+}
+
+#[test]
+fn flipped_bit() {
+    let mut stream = words(Prbs::Prbs31, 1000);
+    stream[500] ^= 1 << 13;
+    let report = check(Prbs::Prbs31, &stream);
+    assert_eq!(report.errors, 1);
+    assert_eq!((report.slips, report.resyncs), (0, 0));
+}
+
+#[test]
+fn dropped_word() {
+    for prbs in Prbs::ALL.iter() {
+        /* Whichever word of a block goes missing, none of the damage counts as errors */
+        for dropped in 500..500 + BLOCK_BYTES as usize / 4 {
+            let mut stream = words(*prbs, 1000);
+            stream.remove(dropped);
+            let report = check(*prbs, &stream);
+            assert_eq!(report.errors, 0, "{:?} without word {}", prbs, dropped);
+            assert_eq!(report.slips, 1, "{:?} without word {}", prbs, dropped);
+            assert_eq!(
+                report.slipped_bits, 32,
+                "{:?} without word {}",
+                prbs, dropped
+            );
+            assert_eq!(report.resyncs, 0, "{:?} without word {}", prbs, dropped);
+        }
+    }
+}
+
+#[test]
+fn inserted_word() {
+    /* The sequence never runs backwards, so an extra word is a resync */
+    let mut stream = words(Prbs::Prbs31, 1000);
+    stream.insert(501, 0x5555_5555);
+    let report = check(Prbs::Prbs31, &stream);
+    assert_eq!(report.errors, 0);
+    assert_eq!((report.slips, report.resyncs), (0, 1));
+}
+
+#[test]
+fn error_burst() {
+    /* Lock is lost and found again at the same place, so every wrong bit counts */
+    let mut stream = words(Prbs::Prbs31, 1000);
+    for word in stream[500..504].iter_mut() {
+        *word = !*word;
+    }
+    let report = check(Prbs::Prbs31, &stream);
+    assert_eq!(report.errors, 4 * 32);
+    assert_eq!((report.slips, report.resyncs), (0, 0));
+}
+
+#[test]
+fn capture_starting_mid_stream() {
+    let stream = words(Prbs::Prbs15, 1000);
+    let mut checker = Checker::new(Prbs::Prbs15);
+    for byte in stream.iter().flat_map(|word| word.to_le_bytes()).skip(1001) {
+        checker.push(byte);
+    }
+    assert!(checker.is_locked());
+    let report = checker.report();
+    assert!(report.bits > 2900 * 8, "{:?}", report);
+    assert_eq!(report.errors, 0);
+    assert_eq!((report.slips, report.resyncs), (0, 0));
+}
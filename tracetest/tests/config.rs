@@ -0,0 +1,87 @@
+//! What `SwoConfigBuilder::build` accepts and what the configuration reports
+
+use tracetest::{
+    config::{ClockSource, SwoConfig, SwoConfigBuilder, SwoConfigError},
+    cpu::Core,
+    dwt::{DataTraceOutput, DwtConfig, WatchAccess, Watchpoint},
+    regs::TraceMode,
+};
+
+fn builder(core: Core) -> SwoConfigBuilder {
+    SwoConfig::builder(ClockSource::Hclk(72_000_000), 2_000_000).core(core)
+}
+
+#[test]
+fn sync_modes_run_at_the_clock_rate() {
+    let config = SwoConfig::builder(ClockSource::Hclk(72_000_000), 2_000_000)
+        .trace_mode(TraceMode::Sync4)
+        .build()
+        .unwrap();
+    assert_eq!(config.achieved_baud(), 72_000_000);
+    assert_eq!(config.requested_baud(), 72_000_000);
+    assert_eq!(config.error_percent(), 0.0);
+
+    /* The SWO rate is kept for going back to asynchronous output */
+    let swo = config
+        .to_builder()
+        .trace_mode(TraceMode::Async)
+        .build()
+        .unwrap();
+    assert_eq!(swo.requested_baud(), 2_000_000);
+    assert_eq!(swo.acpr(), 0x23);
+}
+
+#[test]
+fn data_trace_needs_v7m_comparators() {
+    let mut dwt = DwtConfig::default();
+    dwt.watchpoints[0] = Some(Watchpoint {
+        address: 0x2000_0000,
+        size: 4,
+        access: WatchAccess::Write,
+        output: DataTraceOutput::Data,
+    });
+    for core in [Core::CortexM3, Core::CortexM4, Core::CortexM7] {
+        assert!(builder(core).dwt(dwt).build().is_ok(), "{:?}", core);
+    }
+    for core in [Core::CortexM33, Core::CortexM55] {
+        assert_eq!(
+            builder(core).dwt(dwt).build().err(),
+            Some(SwoConfigError::DataTraceUnsupported(core))
+        );
+    }
+}
+
+#[test]
+fn etm_trace_id_needs_etm_v3() {
+    for core in [Core::CortexM3, Core::CortexM4] {
+        let config = builder(core).etm_trace_id(0x22).build().unwrap();
+        assert_eq!(config.etm_trace_id(), Some(0x22));
+    }
+    for core in [Core::CortexM7, Core::CortexM33, Core::CortexM55] {
+        assert_eq!(
+            builder(core).etm_trace_id(0x22).build().err(),
+            Some(SwoConfigError::EtmUnsupported(core))
+        );
+        /* Without an ETM trace ID the same core is fine */
+        assert_eq!(builder(core).build().unwrap().core(), core);
+    }
+}
+
+#[test]
+fn to_builder_keeps_the_tolerance() {
+    /* 18 MHz is the nearest rate to 20 MHz, 10% off and outside the default tolerance */
+    let wide = SwoConfig::builder(ClockSource::Hclk(72_000_000), 20_000_000)
+        .tolerance_percent(15.0)
+        .build()
+        .unwrap();
+    assert_eq!(wide.achieved_baud(), 18_000_000);
+    assert_eq!(wide.tolerance_percent(), 15.0);
+    assert_eq!(wide.to_builder().build(), Ok(wide));
+
+    /* 2.5 MHz comes out 0.7% slow, which a tight tolerance still refuses */
+    let tight = builder(Core::CortexM3)
+        .tolerance_percent(0.5)
+        .build()
+        .unwrap();
+    assert!(tight.to_builder().baud(2_500_000).build().is_err());
+}
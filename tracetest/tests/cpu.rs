@@ -0,0 +1,105 @@
+//! Identifying the core and its TPIU from the ID registers
+
+use tracetest::{
+    cpu::{Core, CoreError},
+    regs::{MockRegisters, Register},
+};
+
+/// `CPUID` of each core, as read from real parts
+const CPUIDS: [(Core, u32); 5] = [
+    (Core::CortexM3, 0x412f_c231),
+    (Core::CortexM4, 0x410f_c241),
+    (Core::CortexM7, 0x411f_c272),
+    (Core::CortexM33, 0x410f_d213),
+    (Core::CortexM55, 0x411f_d220),
+];
+
+/// Registers of `core` with a TPIU whose part number is `tpiu`
+fn registers(core: Core, tpiu: u16) -> MockRegisters<8> {
+    let cpuid = CPUIDS.iter().find(|(c, _)| *c == core).unwrap().1;
+    let mut regs = MockRegisters::new();
+    regs.preset(Register::ScbCpuid, cpuid);
+    regs.preset(Register::TpiuPidr0, tpiu as u32 & 0xff);
+    /* The top half of PIDR1 is part of the designer code */
+    regs.preset(Register::TpiuPidr1, 0xb0 | (tpiu as u32 >> 8));
+    regs
+}
+
+#[test]
+fn cores_from_cpuid() {
+    for (core, cpuid) in CPUIDS.iter() {
+        assert_eq!(Core::from_cpuid(*cpuid), Ok(*core));
+        assert_eq!(((cpuid >> 4) & 0xfff) as u16, core.cpuid_partno());
+    }
+}
+
+#[test]
+fn cores_without_an_itm() {
+    /* Cortex-M0, M0+, M1 and M23 */
+    for (cpuid, partno) in [
+        (0x410c_c200, 0xc20),
+        (0x410c_c601, 0xc60),
+        (0x410c_c210, 0xc21),
+        (0x411c_d200, 0xd20),
+    ] {
+        assert_eq!(Core::from_cpuid(cpuid), Err(CoreError::NoItm(partno)));
+    }
+}
+
+#[test]
+fn unknown_core() {
+    /* A Cortex-R5 */
+    assert_eq!(
+        Core::from_cpuid(0x411f_c153),
+        Err(CoreError::UnknownCore(0xc15))
+    );
+}
+
+#[test]
+fn check_finds_the_expected_tpiu() {
+    for core in Core::ALL.iter() {
+        for tpiu in core.tpiu_part_numbers() {
+            let mut regs = registers(*core, *tpiu);
+            assert_eq!(core.check(&mut regs), Ok(()), "{:?} {:#x}", core, tpiu);
+            assert_eq!(regs.writes().count(), 0);
+        }
+    }
+
+    /* The ARMv8-M cores may have a SoC-400 TPIU, the Cortex-M4 may not */
+    assert_eq!(
+        Core::CortexM33.check(&mut registers(Core::CortexM33, 0x912)),
+        Ok(())
+    );
+    assert_eq!(
+        Core::CortexM55.check(&mut registers(Core::CortexM55, 0x912)),
+        Ok(())
+    );
+    assert_eq!(
+        Core::CortexM4.check(&mut registers(Core::CortexM4, 0x912)),
+        Err(CoreError::UnknownTpiu(0x912))
+    );
+    /* Nothing answering at the TPIU's address */
+    assert_eq!(
+        Core::CortexM3.check(&mut registers(Core::CortexM3, 0)),
+        Err(CoreError::UnknownTpiu(0))
+    );
+}
+
+#[test]
+fn check_refuses_the_wrong_core() {
+    let mut regs = registers(Core::CortexM4, 0x9a1);
+    assert_eq!(
+        Core::CortexM3.check(&mut regs),
+        Err(CoreError::WrongCore {
+            expected: Core::CortexM3,
+            found: Core::CortexM4,
+        })
+    );
+
+    let mut regs = MockRegisters::<8>::new();
+    regs.preset(Register::ScbCpuid, 0x410c_c601);
+    assert_eq!(
+        Core::CortexM3.check(&mut regs),
+        Err(CoreError::NoItm(0xc60))
+    );
+}
@@ -0,0 +1,69 @@
+//! `DWT_CTRL` as a configuration leaves it, on top of what a debugger had set
+
+use tracetest::{
+    dwt::{CycTap, DwtConfig, DwtConfigError, Enable, SyncTap},
+    regs::{DwtCtrl, TypedRegister},
+};
+
+/// A debugger's `DWT_CTRL`: four comparators, the cycle counter, SYNCTAP at bit 24, PC
+/// sampling, exception trace and the CPI and fold event counters all on
+const DEBUGGER: u32 = 0x4023_1401;
+
+#[test]
+fn default_keeps_what_it_finds() {
+    let ctrl = DwtConfig::default().dwt_ctrl(DwtCtrl::from_bits(DEBUGGER));
+    /* Only POSTPRESET, POSTINIT and CYCTAP change */
+    assert_eq!(ctrl.bits(), DEBUGGER | 0x3fe);
+}
+
+#[test]
+fn off_clears_what_a_debugger_set() {
+    let config = DwtConfig {
+        cycle_counter: Enable::Off,
+        sync_tap: SyncTap::Disabled,
+        pc_sampling: Enable::Off,
+        exception_trace: Enable::Off,
+        cpi_events: Enable::Off,
+        fold_events: Enable::Off,
+        ..DwtConfig::default()
+    };
+    let ctrl = config.dwt_ctrl(DwtCtrl::from_bits(DEBUGGER));
+    assert_eq!(ctrl.bits(), 0x4000_03fe);
+}
+
+#[test]
+fn on_sets_each_source() {
+    let config = DwtConfig {
+        cycle_counter: Enable::On,
+        cyc_tap: CycTap::Bit6,
+        post_preset: 3,
+        sync_tap: SyncTap::Bit28,
+        pc_sampling: Enable::On,
+        exc_events: Enable::On,
+        sleep_events: Enable::On,
+        lsu_events: Enable::On,
+        ..DwtConfig::default()
+    };
+    assert_eq!(config.validate(), Ok(()));
+    let ctrl = config.dwt_ctrl(DwtCtrl::from_bits(0x4000_0000));
+    assert_eq!(ctrl.bits(), 0x401c_1c67);
+}
+
+#[test]
+fn cycle_counter_must_be_turned_on() {
+    /* Kept, the cycle counter may be off, and these sources would then never fire */
+    for cycle_counter in [Enable::Keep, Enable::Off] {
+        let sampling = DwtConfig {
+            cycle_counter,
+            pc_sampling: Enable::On,
+            ..DwtConfig::default()
+        };
+        assert_eq!(sampling.validate(), Err(DwtConfigError::NeedsCycleCounter));
+        let sync = DwtConfig {
+            cycle_counter,
+            sync_tap: SyncTap::Bit26,
+            ..DwtConfig::default()
+        };
+        assert_eq!(sync.validate(), Err(DwtConfigError::NeedsCycleCounter));
+    }
+}
@@ -0,0 +1,128 @@
+//! Frames made by `Framer` and taken apart again by `Deframer`, with damage in between
+
+use tracetest::{
+    framing::{Crc32, Deframer, Framer, Stats, FRAME_MAGIC},
+    itm::TraceWrite,
+};
+
+/// The stimulus packets written for a run of frames, one entry each
+#[derive(Default)]
+struct Packets(Vec<Vec<u8>>);
+
+impl TraceWrite for Packets {
+    fn write_u8(&mut self, value: u8) {
+        self.0.push(vec![value]);
+    }
+
+    fn write_u16(&mut self, value: u16) {
+        self.0.push(value.to_le_bytes().to_vec());
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.0.push(value.to_le_bytes().to_vec());
+    }
+}
+
+/// Packets of `count` frames, each its own list, with frame 3 carrying `third` and the
+/// others a short message
+fn frames(count: u16, third: &[u8]) -> Vec<Vec<Vec<u8>>> {
+    let mut framer = Framer::new();
+    (0..count)
+        .map(|n| {
+            let message = format!("message {}", n);
+            let mut packets = Packets::default();
+            framer.send(
+                &mut packets,
+                if n == 3 { third } else { message.as_bytes() },
+            );
+            packets.0
+        })
+        .collect()
+}
+
+/// Sequence numbers of every frame found in `frames`, and the totals
+fn deframe(frames: &[Vec<Vec<u8>>]) -> (Vec<u16>, Stats) {
+    let mut deframer = Deframer::new();
+    let mut seqs = Vec::new();
+    for byte in frames.iter().flatten().flatten() {
+        if let Some(frame) = deframer.push(*byte) {
+            if frame.seq != 3 {
+                assert_eq!(frame.payload, format!("message {}", frame.seq).as_bytes());
+            }
+            seqs.push(frame.seq);
+        }
+    }
+    while let Some(frame) = deframer.next_frame() {
+        seqs.push(frame.seq);
+    }
+    (seqs, deframer.stats())
+}
+
+fn all_but_3(count: u16) -> Vec<u16> {
+    (0..count).filter(|n| *n != 3).collect()
+}
+
+#[test]
+fn crc32_check_value() {
+    let mut crc = Crc32::new();
+    crc.update(b"123456789");
+    assert_eq!(crc.finish(), 0xcbf4_3926);
+}
+
+#[test]
+fn clean_stream() {
+    let (seqs, stats) = deframe(&frames(8, b"message 3"));
+    assert_eq!(seqs, (0..8).collect::<Vec<_>>());
+    assert_eq!(
+        stats,
+        Stats {
+            frames: 8,
+            ..Stats::default()
+        }
+    );
+}
+
+#[test]
+fn dropped_payload_packet() {
+    /* Frame 3 comes up short and reads on into frame 4, which must still be found */
+    let mut frames = frames(8, b"a longer message for frame 3");
+    frames[3].remove(2);
+    let (seqs, stats) = deframe(&frames);
+    assert_eq!(seqs, all_but_3(8));
+    assert_eq!((stats.dropped, stats.corrupted), (1, 1));
+}
+
+#[test]
+fn dropped_crc_packet() {
+    let mut frames = frames(8, b"message 3");
+    frames[3].pop();
+    let (seqs, stats) = deframe(&frames);
+    assert_eq!(seqs, all_but_3(8));
+    assert_eq!((stats.dropped, stats.corrupted), (1, 1));
+}
+
+#[test]
+fn corrupted_crc() {
+    let mut frames = frames(8, b"message 3");
+    frames[3].last_mut().unwrap()[1] ^= 0x40;
+    let (seqs, stats) = deframe(&frames);
+    assert_eq!(seqs, all_but_3(8));
+    assert_eq!((stats.dropped, stats.corrupted), (1, 1));
+}
+
+#[test]
+fn false_magic_in_payload() {
+    /* Once its header is lost, frame 3's payload looks like the start of a frame long
+     * enough to swallow the next dozen, and holds another false start after that */
+    let third = [FRAME_MAGIC, 200, 0, 0, FRAME_MAGIC, 4, 1, 0, 0xaa];
+    let mut frames = frames(24, &third);
+    frames[3].remove(0);
+    let (seqs, stats) = deframe(&frames);
+    assert_eq!(seqs, all_but_3(24));
+    assert_eq!((stats.dropped, stats.corrupted), (1, 1));
+
+    /* With its header, the same payload is only a payload */
+    let (seqs, stats) = deframe(&self::frames(24, &third));
+    assert_eq!(seqs, (0..24).collect::<Vec<_>>());
+    assert_eq!(stats.corrupted, 0);
+}
@@ -0,0 +1,47 @@
+//! How byte strings are split into stimulus writes
+
+use tracetest::{
+    hello::{self, HELLO_MESSAGE, HELLO_PORT},
+    itm::{payloads, Payload, Stimulus},
+};
+
+#[test]
+fn split_into_payloads() {
+    let split: Vec<_> = payloads(b"abcdefg").collect();
+    assert_eq!(
+        split,
+        [
+            Payload::U32(u32::from_le_bytes(*b"abcd")),
+            Payload::U16(u16::from_le_bytes(*b"ef")),
+            Payload::U8(b'g'),
+        ]
+    );
+    assert_eq!(payloads(b"abcdef").count(), 2);
+    assert_eq!(payloads(b"abcde").last(), Some(Payload::U8(b'e')));
+    assert_eq!(payloads(b"").count(), 0);
+}
+
+/// Keeps the byte strings it is given whole
+#[derive(Default)]
+struct Strings(Vec<(usize, Vec<u8>)>);
+
+impl Stimulus for Strings {
+    fn write(&mut self, port: usize, payload: Payload) {
+        let bytes = payload.value().to_le_bytes();
+        self.0.push((port, bytes[..payload.size()].to_vec()));
+    }
+
+    fn write_all(&mut self, port: usize, bytes: &[u8]) {
+        self.0.push((port, bytes.to_vec()));
+    }
+}
+
+#[test]
+fn greeting_arrives_whole() {
+    let mut strings = Strings::default();
+    hello::send_tick(&mut strings, 0);
+    assert_eq!(
+        strings.0[0],
+        (HELLO_PORT, HELLO_MESSAGE.as_bytes().to_vec())
+    );
+}
@@ -0,0 +1,185 @@
+//! The mailbox wire protocol, as a host programs against it
+
+use tracetest::{
+    mailbox::{Ack, Command, Mailbox, Response, Scenario, Settings, Status, ACK_MAGIC},
+    swo::SwoProtocol,
+};
+
+fn commands() -> Vec<Command> {
+    let mut commands: Vec<_> = Scenario::ALL
+        .iter()
+        .map(|scenario| Command::SelectScenario(*scenario))
+        .collect();
+    commands.extend_from_slice(&[
+        Command::SetBaud {
+            baud: 2_000_000,
+            protocol: SwoProtocol::Manchester,
+        },
+        Command::SetBaud {
+            baud: 115_200,
+            protocol: SwoProtocol::Nrz,
+        },
+        Command::SetStimulusPorts(0),
+        Command::SetStimulusPorts(0x8000_0003),
+        Command::Pause,
+        Command::Resume,
+    ]);
+    commands
+}
+
+#[test]
+fn commands_round_trip() {
+    for command in commands() {
+        let (code, args) = command.encode();
+        assert_eq!(Command::decode(code, args), Ok(command));
+    }
+}
+
+#[test]
+fn command_codes() {
+    assert_eq!(
+        Command::SelectScenario(Scenario::Bert).encode(),
+        (1, [11, 0, 0])
+    );
+    assert_eq!(
+        Command::SetBaud {
+            baud: 9600,
+            protocol: SwoProtocol::Nrz
+        }
+        .encode(),
+        (2, [9600, 1, 0])
+    );
+    assert_eq!(Command::SetStimulusPorts(0xf).encode(), (3, [0xf, 0, 0]));
+    assert_eq!(Command::Pause.encode(), (4, [0; 3]));
+    assert_eq!(Command::Resume.encode(), (5, [0; 3]));
+}
+
+#[test]
+fn bad_commands() {
+    assert_eq!(Command::decode(0, [0; 3]), Err(Status::UnknownCommand));
+    assert_eq!(Command::decode(6, [0; 3]), Err(Status::UnknownCommand));
+    assert_eq!(
+        Command::decode(1, [Scenario::ALL.len() as u32, 0, 0]),
+        Err(Status::UnknownScenario)
+    );
+    assert_eq!(
+        Command::decode(2, [2_000_000, 2, 0]),
+        Err(Status::UnknownProtocol)
+    );
+}
+
+#[test]
+fn settings_transitions() {
+    let defaults = Settings::new(Scenario::Hello, 4_000_000, SwoProtocol::Manchester);
+    assert_eq!(defaults.scenario(), Some(Scenario::Hello));
+    assert_eq!(defaults.baud(), 4_000_000);
+    assert_eq!(defaults.protocol(), Some(SwoProtocol::Manchester));
+    assert_eq!(defaults.stimulus_ports(), None);
+    assert!(!defaults.paused());
+
+    let settings = defaults
+        .with(Command::SelectScenario(Scenario::Sweep))
+        .with(Command::SetBaud {
+            baud: 115_200,
+            protocol: SwoProtocol::Nrz,
+        })
+        .with(Command::SetStimulusPorts(0x11));
+    assert_eq!(settings.scenario(), Some(Scenario::Sweep));
+    assert_eq!(settings.baud(), 115_200);
+    assert_eq!(settings.protocol(), Some(SwoProtocol::Nrz));
+    assert_eq!(settings.stimulus_ports(), Some(0x11));
+
+    /* Pausing changes nothing else, and resuming undoes it */
+    let paused = settings.with(Command::Pause);
+    assert!(paused.paused());
+    assert!(paused.with(Command::Pause).paused());
+    assert_eq!(paused.with(Command::Resume), settings);
+
+    /* A mask of 0 goes back to the scenario's own ports */
+    assert_eq!(
+        settings.with(Command::SetStimulusPorts(0)).stimulus_ports(),
+        None
+    );
+}
+
+#[test]
+fn acks() {
+    let ack = Ack {
+        seq: 7,
+        status: Status::InvalidConfig,
+    };
+    assert_eq!(ack.words(), [ACK_MAGIC, 7, 4]);
+    assert_eq!(Ack::from_words(&ack.words()), Some(ack));
+    assert_eq!(Ack::from_words(&[ACK_MAGIC ^ 1, 7, 0]), None);
+    assert_eq!(Ack::from_words(&[ACK_MAGIC, 7, 5]), None);
+}
+
+#[test]
+fn reset_requests() {
+    let mailbox = Mailbox::new();
+    assert!(!mailbox.is_initialised());
+    assert!(!mailbox.take_reset_request());
+
+    let defaults = Settings::new(Scenario::Hello, 4_000_000, SwoProtocol::Manchester);
+    mailbox.initialise(defaults);
+    assert!(mailbox.is_initialised());
+    assert_eq!(mailbox.settings(), defaults);
+    assert_eq!(mailbox.pending(), None);
+
+    mailbox.request_reset();
+    assert!(mailbox.take_reset_request());
+    assert!(!mailbox.take_reset_request());
+
+    /* Starting again clears a request left over from before */
+    mailbox.request_reset();
+    mailbox.initialise(defaults);
+    assert!(!mailbox.take_reset_request());
+}
+
+#[test]
+fn responses_while_paused() {
+    let invalid = Command::decode(9, [0; 3]);
+
+    /* Running: everything is acknowledged on the port */
+    assert_eq!(Response::to(Ok(Command::Pause), false), Response::Pause);
+    assert_eq!(
+        Response::to(Ok(Command::Resume), false),
+        Response::Acknowledge {
+            status: Status::Ok,
+            on_port: true
+        }
+    );
+    assert_eq!(
+        Response::to(invalid, false),
+        Response::Acknowledge {
+            status: Status::UnknownCommand,
+            on_port: true
+        }
+    );
+
+    /* Paused: a second pause and a bad command are acknowledged in the mailbox only,
+     * since the ITM is off */
+    assert_eq!(
+        Response::to(Ok(Command::Pause), true),
+        Response::Acknowledge {
+            status: Status::Ok,
+            on_port: false
+        }
+    );
+    assert_eq!(
+        Response::to(invalid, true),
+        Response::Acknowledge {
+            status: Status::UnknownCommand,
+            on_port: false
+        }
+    );
+    assert_eq!(Response::to(Ok(Command::Resume), true), Response::Resume);
+
+    /* Anything else resets, paused or not */
+    for paused in [false, true] {
+        assert_eq!(
+            Response::to(Ok(Command::SetStimulusPorts(3)), paused),
+            Response::Reset
+        );
+    }
+}
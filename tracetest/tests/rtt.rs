@@ -0,0 +1,170 @@
+//! Up-channels written by the target and read back the way a probe reads them
+
+use std::ptr;
+use std::thread;
+
+use tracetest::{
+    itm::TraceWrite,
+    rtt::{ChannelMode, Rtt, RttBuffer, UpChannel, UpConfig, RTT_ID},
+};
+
+/// The control block as a probe sees it in target memory
+#[repr(C)]
+struct Descriptor {
+    name: *const u8,
+    buffer: *mut u8,
+    size: u32,
+    write: u32,
+    read: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+struct Block {
+    id: [u8; 16],
+    max_up: u32,
+    max_down: u32,
+    up: [Descriptor; 1],
+}
+
+/// Reads an up-channel from the other side, as a probe does
+struct Probe(*mut Descriptor);
+
+// Safe because the probe only changes `read`, which the target never writes
+unsafe impl Send for Probe {}
+
+impl Probe {
+    fn new(rtt: &'static Rtt<1>) -> Self {
+        let block = rtt as *const Rtt<1> as *mut Block;
+        unsafe {
+            assert_eq!(ptr::read_volatile(ptr::addr_of!((*block).id)), RTT_ID);
+            Probe(ptr::addr_of_mut!((*block).up[0]))
+        }
+    }
+
+    fn write_index(&self) -> u32 {
+        unsafe { ptr::read_volatile(ptr::addr_of!((*self.0).write)) }
+    }
+
+    /// Everything written since the last read
+    fn read(&mut self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        unsafe {
+            let descriptor = self.0;
+            let write = ptr::read_volatile(ptr::addr_of!((*descriptor).write));
+            let mut read = ptr::read_volatile(ptr::addr_of!((*descriptor).read));
+            while read != write {
+                bytes.push(ptr::read_volatile((*descriptor).buffer.add(read as usize)));
+                read = (read + 1) % (*descriptor).size;
+            }
+            ptr::write_volatile(ptr::addr_of_mut!((*descriptor).read), read);
+        }
+        bytes
+    }
+}
+
+/// Set up `rtt` with one 8-byte channel in `mode`, and claim it
+fn channel(
+    rtt: &'static Rtt<1>,
+    buffer: &'static RttBuffer<8>,
+    mode: ChannelMode,
+) -> (UpChannel, Probe) {
+    assert!(rtt.init([UpConfig::new(b"test\0", buffer, mode)]));
+    (rtt.up(0).unwrap(), Probe::new(rtt))
+}
+
+#[test]
+fn claiming() {
+    static RTT: Rtt<1> = Rtt::new();
+    static BUFFER: RttBuffer<8> = RttBuffer::new();
+    static SPARE: RttBuffer<8> = RttBuffer::new();
+
+    /* Nothing can be claimed before the block is set up */
+    assert!(RTT.up(0).is_none());
+    let (mut up, mut probe) = channel(&RTT, &BUFFER, ChannelMode::NoBlockSkip);
+    assert_eq!(up.channel(), 0);
+    assert!(RTT.up(0).is_none());
+    assert!(RTT.up(1).is_none());
+
+    /* A second set-up is refused rather than resetting the claimed channel */
+    up.write_all(b"abc");
+    assert!(!RTT.init([UpConfig::new(b"again\0", &SPARE, ChannelMode::Blocking)]));
+    assert_eq!(up.mode(), ChannelMode::NoBlockSkip);
+    assert_eq!(probe.read(), b"abc");
+}
+
+#[test]
+#[should_panic(expected = "RTT buffer used for two channels")]
+fn sharing_a_buffer() {
+    static BUFFER: RttBuffer<8> = RttBuffer::new();
+    let _first = UpConfig::new(b"first\0", &BUFFER, ChannelMode::NoBlockSkip);
+    let _second = UpConfig::new(b"second\0", &BUFFER, ChannelMode::NoBlockSkip);
+}
+
+#[test]
+fn wrap_around() {
+    static RTT: Rtt<1> = Rtt::new();
+    static BUFFER: RttBuffer<8> = RttBuffer::new();
+    let (mut up, mut probe) = channel(&RTT, &BUFFER, ChannelMode::NoBlockSkip);
+
+    for round in 0..5u8 {
+        let bytes: Vec<u8> = (0..5).map(|n| round * 16 + n).collect();
+        assert_eq!(up.write_bytes(&bytes), 5);
+        assert_eq!(probe.read(), bytes);
+    }
+    /* 25 bytes through an 8-byte ring */
+    assert_eq!(probe.write_index(), 25 % 8);
+}
+
+#[test]
+fn skip_drops_whole_writes() {
+    static RTT: Rtt<1> = Rtt::new();
+    static BUFFER: RttBuffer<8> = RttBuffer::new();
+    let (mut up, mut probe) = channel(&RTT, &BUFFER, ChannelMode::NoBlockSkip);
+
+    /* Seven bytes fit, the eighth is the gap between full and empty */
+    assert_eq!(up.write_bytes(b"abcd"), 4);
+    assert_eq!(up.write_bytes(b"efgh"), 0);
+    assert_eq!(up.write_bytes(b"efg"), 3);
+    assert_eq!(up.write_bytes(b"h"), 0);
+    assert_eq!(probe.read(), b"abcdefg");
+
+    assert_eq!(up.write_bytes(b"hijklmn"), 7);
+    assert_eq!(probe.read(), b"hijklmn");
+}
+
+#[test]
+fn trim_writes_what_fits() {
+    static RTT: Rtt<1> = Rtt::new();
+    static BUFFER: RttBuffer<8> = RttBuffer::new();
+    let (mut up, mut probe) = channel(&RTT, &BUFFER, ChannelMode::NoBlockTrim);
+
+    assert_eq!(up.write_bytes(b"abcd"), 4);
+    assert_eq!(up.write_bytes(b"efghij"), 3);
+    assert_eq!(up.write_bytes(b"k"), 0);
+    assert_eq!(probe.read(), b"abcdefg");
+
+    /* The mode can be changed at run time, as a host may do */
+    up.set_mode(ChannelMode::NoBlockSkip);
+    assert_eq!(up.write_bytes(b"abcdefgh"), 0);
+    assert_eq!(up.write_bytes(b"abcdefg"), 7);
+}
+
+#[test]
+fn blocking_waits_for_the_probe() {
+    static RTT: Rtt<1> = Rtt::new();
+    static BUFFER: RttBuffer<8> = RttBuffer::new();
+    let (mut up, mut probe) = channel(&RTT, &BUFFER, ChannelMode::Blocking);
+
+    let sent: Vec<u8> = (0..=255).collect();
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        while received.len() < 256 {
+            received.extend(probe.read());
+            thread::yield_now();
+        }
+        received
+    });
+    assert_eq!(up.write_bytes(&sent), 256);
+    assert_eq!(reader.join().unwrap(), sent);
+}
@@ -0,0 +1,194 @@
+//! The bring-up sequence run against the mock register file
+
+use tracetest::{
+    config::{ClockSource, SwoConfig},
+    cpu::Core,
+    dwt::{CycTap, DwtConfig, Enable},
+    pc_sampling,
+    regs::{Access, DwtCtrl, MockRegisters, Register, Registers, TracePins},
+    swo::swo_setup,
+};
+
+/// Key written to a Lock Access Register to unlock it
+const UNLOCK: u32 = 0xC5AC_CE55;
+
+/// Lock Status Register of a component that has a lock and is locked: SLI and SLK set
+const LOCKED: u32 = 0x3;
+
+fn config() -> SwoConfig {
+    SwoConfig::builder(ClockSource::Hclk(72_000_000), 2_000_000)
+        .core(Core::CortexM3)
+        .trace_pins(TracePins::Stm32f1Dbgmcu)
+        .build()
+        .unwrap()
+}
+
+/// Registers of a Cortex-M3, where every component has a software lock
+fn registers() -> MockRegisters<64> {
+    let mut regs = MockRegisters::new();
+    for lsr in [
+        Register::TpiuLsr,
+        Register::DwtLsr,
+        Register::ItmLsr,
+        Register::EtmLsr,
+    ] {
+        regs.preset(lsr, LOCKED);
+    }
+    regs
+}
+
+fn lock_writes(regs: &MockRegisters<64>) -> Vec<Register> {
+    regs.writes()
+        .filter(|(_, value)| *value == UNLOCK)
+        .map(|(reg, _)| reg)
+        .collect()
+}
+
+#[test]
+fn setup_writes_in_order() {
+    let mut swo = swo_setup(registers(), &config());
+    let regs = swo.registers();
+    assert_eq!(regs.dropped(), 0);
+    assert_eq!(
+        regs.writes().collect::<Vec<_>>(),
+        [
+            (Register::ScsDemcr, 0x0100_0000),
+            (Register::TpiuLar, UNLOCK),
+            (Register::TpiuCspsr, 0x1),
+            (Register::TpiuAcpr, 0x23),
+            (Register::TpiuSppr, 0x1),
+            (Register::TpiuFfcr, 0x0),
+            (Register::DwtLar, UNLOCK),
+            (Register::DwtCtrl, 0x3fe),
+            (Register::ItmLar, UNLOCK),
+            (Register::ItmTpr, 0xf),
+            (Register::ItmTcr, 0x1001d),
+            (Register::ItmTer, 0x1),
+            (Register::DbgmcuCr, 0x0),
+            (Register::DbgmcuCr, 0x20),
+        ]
+    );
+}
+
+fn touches_dbgmcu(regs: &MockRegisters<64>) -> bool {
+    assert_eq!(regs.dropped(), 0);
+    regs.accesses().iter().any(|access| match *access {
+        Access::Read(reg, _) | Access::Write(reg, _) => reg == Register::DbgmcuCr,
+    })
+}
+
+#[test]
+fn pins_are_left_alone_by_default() {
+    let untouched = config()
+        .to_builder()
+        .trace_pins(TracePins::Untouched)
+        .build()
+        .unwrap();
+    let mut regs = registers();
+    regs.preset(Register::DbgmcuCr, 0x7);
+
+    let mut swo = swo_setup(regs, &untouched);
+    assert!(!touches_dbgmcu(swo.registers()));
+    swo.registers().clear_log();
+    swo.reconfigure(&untouched);
+    assert!(!touches_dbgmcu(swo.registers()));
+    swo.registers().clear_log();
+    let regs = swo.disable();
+    assert!(!touches_dbgmcu(&regs));
+    assert_eq!(regs.value(Register::DbgmcuCr), 0x7);
+}
+
+#[test]
+fn disable_restores_what_setup_found() {
+    let preset = [
+        (Register::ScsDemcr, 0x0000_0401),
+        (Register::DbgmcuCr, 0x0000_0007),
+        (Register::TpiuFfcr, 0x0000_0102),
+        (Register::DwtCtrl, 0x4000_0001),
+        (Register::ItmTcr, 0x0003_0009),
+        (Register::ItmTpr, 0x1),
+        (Register::ItmTer, 0x8000_0000),
+    ];
+    let mut regs = registers();
+    for (reg, value) in preset.iter() {
+        regs.preset(*reg, *value);
+    }
+
+    let mut swo = swo_setup(regs, &config());
+    for (reg, value) in preset.iter() {
+        assert_ne!(
+            swo.registers().value(*reg),
+            *value,
+            "{:?} was not changed by the setup",
+            reg
+        );
+    }
+    let regs = swo.disable();
+    for (reg, value) in preset.iter() {
+        assert_eq!(regs.value(*reg), *value, "{:?} was not restored", reg);
+    }
+}
+
+#[test]
+fn setup_keeps_a_running_cycle_counter() {
+    let mut regs = registers();
+    regs.preset(Register::DwtCtrl, 0x4000_0001);
+    let mut swo = swo_setup(regs, &config());
+    assert_eq!(swo.registers().value(Register::DwtCtrl), 0x4000_03ff);
+
+    /* Sources a later configuration drops go back to how setup found them */
+    let sampling = config()
+        .to_builder()
+        .dwt(pc_sampling::dwt_config(CycTap::Bit6, 15))
+        .build()
+        .unwrap();
+    swo.reconfigure(&sampling);
+    assert!(swo.registers().read_typed::<DwtCtrl>().pcsamplena);
+    swo.reconfigure(&config());
+    assert_eq!(swo.registers().value(Register::DwtCtrl), 0x4000_03ff);
+}
+
+#[test]
+fn unlocks_only_components_with_a_lock() {
+    /* A Cortex-M33 whose ITM and DWT have no lock, next to a SoC-400 TPIU that does */
+    let mut regs = MockRegisters::<64>::new();
+    regs.preset(Register::TpiuLsr, LOCKED);
+    let m33 = config().to_builder().core(Core::CortexM33).build().unwrap();
+    let mut swo = swo_setup(regs, &m33);
+    assert_eq!(lock_writes(swo.registers()), [Register::TpiuLar]);
+
+    /* The ETM is unlocked too when it is given a trace ID */
+    let etm = config().to_builder().etm_trace_id(0x22).build().unwrap();
+    let mut swo = swo_setup(registers(), &etm);
+    assert_eq!(
+        lock_writes(swo.registers()),
+        [
+            Register::TpiuLar,
+            Register::EtmLar,
+            Register::DwtLar,
+            Register::ItmLar
+        ]
+    );
+}
+
+#[test]
+fn reconfigure_turns_off_a_debugger_source() {
+    /* PC sampling and the cycle counter left on by a debugger */
+    let mut regs = registers();
+    regs.preset(Register::DwtCtrl, 0x4000_1001);
+    let mut swo = swo_setup(regs, &config());
+    assert!(swo.registers().read_typed::<DwtCtrl>().pcsamplena);
+
+    let quiet = config()
+        .to_builder()
+        .dwt(DwtConfig {
+            pc_sampling: Enable::Off,
+            ..DwtConfig::default()
+        })
+        .build()
+        .unwrap();
+    swo.reconfigure(&quiet);
+    let ctrl = swo.registers().read_typed::<DwtCtrl>();
+    assert!(!ctrl.pcsamplena);
+    assert!(ctrl.cyccntena);
+}